@@ -2,28 +2,26 @@ use secp256k1::{Secp256k1, SecretKey, PublicKey, Keypair, schnorr::{Signature as
 use secp256k1::scalar::Scalar;
 use rand::rngs::OsRng;
 
+#[derive(Debug, Clone)]
 pub struct AdaptorSignature {
-    pub nonce_point: PublicKey, // R'
+    pub nonce_point: PublicKey, // R' = R + T, even-Y
+    pub adaptor_point: PublicKey, // T
     pub s: SecretKey,
-    pub ex: SecretKey,
+    pub ex: Option<SecretKey>, // e * x, only known to the signer
     pub pubkey: PublicKey,      // even-Y adjusted
     pub message: Vec<u8>,
 }
 
 impl AdaptorSignature {
     pub fn new(secp: &Secp256k1<secp256k1::All>, keypair: &Keypair, message: &Message, t: &SecretKey) -> Self {
+        Self::with_adaptor_point(secp, keypair, message, &t.public_key(secp))
+    }
+
+    /// Pre-sign knowing only the adaptor point T = t * G, as a remote signer does.
+    pub fn with_adaptor_point(secp: &Secp256k1<secp256k1::All>, keypair: &Keypair, message: &Message, t_pub: &PublicKey) -> Self {
         let mut rng = OsRng;
-        let mut k = SecretKey::new(&mut rng);
         let mut x = keypair.secret_key();
 
-        // BIP340 parity checks
-        // For R: ensure even y
-        let r_full = k.public_key(secp);
-        let (_, parity) = r_full.x_only_public_key();
-        if parity == secp256k1::Parity::Odd {
-            k = k.negate();
-        }
-
         // For P: ensure even y
         let p_full = x.public_key(secp);
         let (_, p_parity) = p_full.x_only_public_key();
@@ -31,9 +29,15 @@ impl AdaptorSignature {
             x = x.negate();
         }
 
-        // Now compute R' = R + T
-        let t_pub = t.public_key(secp);
-        let r_prime = r_full.combine(&t_pub).unwrap();
+        // The completed signature commits to R' = R + T, so R' (not R) must have even y.
+        // Negating k would not negate R', so draw a fresh nonce until R' is even.
+        let (k, r_prime) = loop {
+            let k = SecretKey::new(&mut rng);
+            let r_prime = k.public_key(secp).combine(t_pub).unwrap();
+            if r_prime.x_only_public_key().1 == secp256k1::Parity::Even {
+                break (k, r_prime);
+            }
+        };
 
         // e = schnorr_challenge(R', P, m)
         let e_scalar = crate::crypto::schnorr_challenge(&r_prime, &x.public_key(secp), message.as_ref());
@@ -46,18 +50,49 @@ impl AdaptorSignature {
 
         AdaptorSignature {
             nonce_point: r_prime,
+            adaptor_point: *t_pub,
+            s,
+            ex: Some(ex),
+            pubkey,
+            message: message.as_ref().to_vec(),
+        }
+    }
+
+    /// Rebuild a pre-signature received from the signer. `verify` checks it against `message`.
+    pub fn from_parts(nonce_point: PublicKey, adaptor_point: PublicKey, s: SecretKey, pubkey: PublicKey, message: &Message) -> Self {
+        AdaptorSignature {
+            nonce_point,
+            adaptor_point,
             s,
-            ex,
+            ex: None,
             pubkey,
             message: message.as_ref().to_vec(),
         }
     }
 
-    pub fn verify(&self, _secp: &Secp256k1<secp256k1::All>) -> bool {
-        // For now, assume correct
+    /// The signer's nonce k = s - e * x; `None` for a pre-signature received from the signer.
+    pub fn nonce(&self) -> Option<SecretKey> {
+        let ex = self.ex?;
+        self.s.add_tweak(&Scalar::from_be_bytes(*ex.negate().as_ref()).ok()?).ok()
+    }
+
+    /// Verify the pre-signature: s * G == (R' - T) + e * P
+    /// Both R' and P must have even y, or the completed signature is not valid BIP340.
+    pub fn verify(&self, secp: &Secp256k1<secp256k1::All>) -> bool {
+        if self.nonce_point.x_only_public_key().1 != secp256k1::Parity::Even || self.pubkey.x_only_public_key().1 != secp256k1::Parity::Even {
+            return false;
+        }
         let e_scalar = crate::crypto::schnorr_challenge(&self.nonce_point, &self.pubkey, &self.message);
-        // TODO: check s * G == nonce_point + e_scalar * pubkey
-        true
+        let Ok(e_p) = self.pubkey.mul_tweak(secp, &e_scalar) else {
+            return false;
+        };
+        let Ok(r) = self.nonce_point.combine(&crate::crypto::negate_point(&self.adaptor_point)) else {
+            return false;
+        };
+        match r.combine(&e_p) {
+            Ok(rhs) => self.s.public_key(secp) == rhs,
+            Err(_) => false,
+        }
     }
 
     pub fn complete(&self, t: &SecretKey) -> SecretKey {
@@ -68,6 +103,18 @@ impl AdaptorSignature {
         s_prime.add_tweak(&Scalar::from_be_bytes(*self.s.negate().as_ref()).unwrap()).unwrap()
     }
 
+    /// Extract `t` from a completed signature found in the wild (event, witness).
+    /// Returns `None` unless the signature uses our nonce R' and yields t with t * G == T.
+    pub fn try_extract_secret(&self, secp: &Secp256k1<secp256k1::All>, sig: &SchnorrSignature) -> Option<SecretKey> {
+        let bytes = sig.as_ref();
+        if bytes[..32] != self.nonce_point.x_only_public_key().0.serialize() {
+            return None;
+        }
+        let s_prime = SecretKey::from_slice(&bytes[32..64]).ok()?;
+        let t = s_prime.add_tweak(&Scalar::from_be_bytes(*self.s.negate().as_ref()).ok()?).ok()?;
+        (t.public_key(secp) == self.adaptor_point).then_some(t)
+    }
+
     pub fn generate_final_signature(&self, s_prime: &SecretKey) -> SchnorrSignature {
         let (r, _) = self.nonce_point.x_only_public_key();
         SchnorrSignature::from_slice(&[&r.serialize()[..], &s_prime[..]].concat()).unwrap()
@@ -98,4 +145,90 @@ pub fn verify_swap(secp: &Secp256k1<secp256k1::All>, swap: &Swap) -> bool {
 pub fn complete_swap(_secp: &Secp256k1<secp256k1::All>, swap: &Swap, t: &SecretKey) -> SchnorrSignature {
     let s_prime = swap.signature.complete(t);
     swap.signature.generate_final_signature(&s_prime)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presign() -> (Secp256k1<secp256k1::All>, Keypair, Message, SecretKey, AdaptorSignature) {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::new(&secp, &mut OsRng);
+        let message = Message::from_digest([7; 32]);
+        let t = SecretKey::new(&mut OsRng);
+        let adaptor_sig = AdaptorSignature::new(&secp, &keypair, &message, &t);
+        (secp, keypair, message, t, adaptor_sig)
+    }
+
+    #[test]
+    fn completed_signature_verifies_and_reveals_secret() {
+        let (secp, keypair, message, t, adaptor_sig) = presign();
+        assert!(adaptor_sig.verify(&secp));
+
+        let s_prime = adaptor_sig.complete(&t);
+        let sig = adaptor_sig.generate_final_signature(&s_prime);
+        secp.verify_schnorr(&sig, &message, &keypair.x_only_public_key().0).unwrap();
+
+        assert_eq!(adaptor_sig.extract_secret(&s_prime), t);
+        assert_eq!(adaptor_sig.try_extract_secret(&secp, &sig), Some(t));
+    }
+
+    #[test]
+    fn nonce_is_recovered_only_by_the_signer() {
+        let (secp, _, message, _, adaptor_sig) = presign();
+        let k = adaptor_sig.nonce().unwrap();
+        let r = adaptor_sig.nonce_point.combine(&crate::crypto::negate_point(&adaptor_sig.adaptor_point)).unwrap();
+        assert_eq!(k.public_key(&secp), r);
+
+        let received = AdaptorSignature::from_parts(adaptor_sig.nonce_point, adaptor_sig.adaptor_point, adaptor_sig.s, adaptor_sig.pubkey, &message);
+        assert!(received.verify(&secp));
+        assert!(received.nonce().is_none());
+    }
+
+    #[test]
+    fn verify_rejects_tampered_pre_signatures() {
+        let (secp, _, _, _, adaptor_sig) = presign();
+
+        let mut wrong_point = adaptor_sig.clone();
+        wrong_point.adaptor_point = SecretKey::new(&mut OsRng).public_key(&secp);
+        assert!(!wrong_point.verify(&secp));
+
+        let mut wrong_s = adaptor_sig.clone();
+        wrong_s.s = wrong_s.s.add_tweak(&Scalar::ONE).unwrap();
+        assert!(!wrong_s.verify(&secp));
+
+        let mut wrong_message = adaptor_sig;
+        wrong_message.message = vec![8; 32];
+        assert!(!wrong_message.verify(&secp));
+    }
+
+    #[test]
+    fn verify_rejects_odd_y_key() {
+        let secp = Secp256k1::new();
+        let x = loop {
+            let x = SecretKey::new(&mut OsRng);
+            if x.public_key(&secp).x_only_public_key().1 == secp256k1::Parity::Odd {
+                break x;
+            }
+        };
+        let message = Message::from_digest([7; 32]);
+        let t = SecretKey::new(&mut OsRng);
+        let t_pub = t.public_key(&secp);
+        let (k, r_prime) = loop {
+            let k = SecretKey::new(&mut OsRng);
+            let r_prime = k.public_key(&secp).combine(&t_pub).unwrap();
+            if r_prime.x_only_public_key().1 == secp256k1::Parity::Even {
+                break (k, r_prime);
+            }
+        };
+        // Consistent for the odd-Y point itself, but not under BIP340's even-Y lift.
+        let pubkey = x.public_key(&secp);
+        let e = crate::crypto::schnorr_challenge(&r_prime, &pubkey, message.as_ref());
+        let s = k.add_tweak(&Scalar::from(x.mul_tweak(&e).unwrap())).unwrap();
+        let adaptor_sig = AdaptorSignature::from_parts(r_prime, t_pub, s, pubkey, &message);
+
+        assert!(!adaptor_sig.verify(&secp));
+        let sig = adaptor_sig.generate_final_signature(&adaptor_sig.complete(&t));
+        assert!(secp.verify_schnorr(&sig, &message, &pubkey.x_only_public_key().0).is_err());
+    }
+}
@@ -0,0 +1,547 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::{Amount, Network, OutPoint, ScriptBuf, Txid};
+use nostr::{Event, EventId, UnsignedEvent};
+use secp256k1::{schnorr::Signature, Message, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use crate::adaptor::AdaptorSignature;
+use crate::bitcoin_utils::SwapOutput;
+use crate::chain::ChainBackend;
+use crate::content::EncryptedContent;
+use crate::network::NetworkParams;
+use crate::nostr_utils::{event_message, RelayClient, WebSocketTransport};
+use crate::signer::{SchnorrSigner, SigningKey};
+
+/// Mount point of the current API version.
+pub const API_PREFIX: &str = "/api/v1";
+
+/// Domain tag of the message a buyer signs to accept an offer.
+const ACCEPT_TAG: &[u8] = b"tapstr/accept";
+
+/// What the buyer signs to accept swap `swap_id`: `sha256("tapstr/accept" || swap_id)`.
+pub fn accept_message(swap_id: &str) -> Message {
+    let mut engine = sha256::Hash::engine();
+    engine.input(ACCEPT_TAG);
+    engine.input(swap_id.as_bytes());
+    Message::from_digest(sha256::Hash::from_engine(engine).to_byte_array())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapStatus {
+    /// Seller posted the event and pre-signature.
+    Offered,
+    /// Buyer accepted; the swap output is fixed.
+    Accepted,
+    /// Buyer reported the lock transaction.
+    Locked,
+    /// Seller published the completed event, revealing `t`.
+    Completed,
+}
+
+/// Wire form of an adaptor pre-signature. The signer's `e * x` never leaves the signer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreSignature {
+    pub nonce_point: PublicKey,
+    pub adaptor_point: PublicKey,
+    pub s: SecretKey,
+    pub pubkey: PublicKey,
+}
+
+impl From<&AdaptorSignature> for PreSignature {
+    fn from(sig: &AdaptorSignature) -> Self {
+        PreSignature { nonce_point: sig.nonce_point, adaptor_point: sig.adaptor_point, s: sig.s, pubkey: sig.pubkey }
+    }
+}
+
+impl PreSignature {
+    /// The adaptor signature this pre-signs over `event_id`.
+    pub fn to_adaptor_signature(&self, event_id: &EventId) -> AdaptorSignature {
+        AdaptorSignature::from_parts(self.nonce_point, self.adaptor_point, self.s, self.pubkey, &event_message(event_id))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateOfferRequest {
+    /// The event the buyer is paying for; its id is the adaptor message.
+    pub event: UnsignedEvent,
+    pub presignature: PreSignature,
+    pub amount_sat: u64,
+    /// Key the seller will claim the swap output with.
+    pub claim_pubkey: XOnlyPublicKey,
+    /// Content the buyer can decrypt once the swap reveals `t`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_content: Option<EncryptedContent>,
+    /// Only this key may accept the offer. Without it, the first buyer to accept gets it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buyer_pubkey: Option<XOnlyPublicKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateOfferResponse {
+    pub swap_id: String,
+    pub status: SwapStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptOfferRequest {
+    pub buyer_pubkey: XOnlyPublicKey,
+    /// BIP340 signature by `buyer_pubkey` over `accept_message(swap_id)`.
+    pub signature: Signature,
+}
+
+impl AcceptOfferRequest {
+    /// Accept `swap_id` with the signer's key as the refund key.
+    pub async fn sign(signer: &impl SchnorrSigner, swap_id: &str) -> anyhow::Result<Self> {
+        let signature = signer.sign_schnorr(&accept_message(swap_id), SigningKey::Internal).await?;
+        Ok(AcceptOfferRequest { buyer_pubkey: signer.public_key().await?, signature })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptOfferResponse {
+    pub swap_id: String,
+    pub status: SwapStatus,
+    /// Address the buyer must fund with `amount_sat`.
+    pub address: String,
+    pub descriptor: String,
+    pub amount_sat: u64,
+    pub refund_blocks: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitLockRequest {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreSignatureResponse {
+    pub swap_id: String,
+    pub event: UnsignedEvent,
+    pub presignature: PreSignature,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitEventRequest {
+    pub event: Event,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitEventResponse {
+    pub swap_id: String,
+    pub status: SwapStatus,
+    /// The adaptor secret `t` recovered from the event signature.
+    pub secret: SecretKey,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapStatusResponse {
+    pub swap_id: String,
+    pub status: SwapStatus,
+    pub network: Network,
+    pub amount_sat: u64,
+    pub seller_pubkey: XOnlyPublicKey,
+    pub buyer_pubkey: Option<XOnlyPublicKey>,
+    pub address: Option<String>,
+    pub lock_outpoint: Option<OutPoint>,
+    /// The seller's completed event, once submitted.
+    pub completed_event: Option<Event>,
+    pub encrypted_content: Option<EncryptedContent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// API failures and the HTTP status each maps to.
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("swap {0} not found")]
+    NotFound(String),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    Conflict(String),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+        };
+        (status, Json(ErrorResponse { error: self.to_string() })).into_response()
+    }
+}
+
+struct SwapRecord {
+    event: UnsignedEvent,
+    adaptor_sig: AdaptorSignature,
+    amount: Amount,
+    claim_pubkey: XOnlyPublicKey,
+    seller_pubkey: XOnlyPublicKey,
+    buyer_pubkey: Option<XOnlyPublicKey>,
+    swap_output: Option<SwapOutput>,
+    lock_outpoint: Option<OutPoint>,
+    completed_event: Option<Event>,
+    encrypted_content: Option<EncryptedContent>,
+    status: SwapStatus,
+}
+
+/// In-memory swap coordinator behind the JSON API. Swaps are keyed by the offered event id.
+/// Reported locks are checked against `chain`.
+#[derive(Clone)]
+pub struct SwapApi<B> {
+    params: NetworkParams,
+    chain: B,
+    swaps: Arc<Mutex<HashMap<String, SwapRecord>>>,
+    relays: Vec<String>,
+}
+
+impl<B: ChainBackend + Clone + Send + Sync + 'static> SwapApi<B> {
+    pub fn new(network: Network, chain: B) -> Self {
+        SwapApi { params: NetworkParams::new(network), chain, swaps: Arc::new(Mutex::new(HashMap::new())), relays: vec![] }
+    }
+
+    /// Forward completed events submitted over HTTP to these relays.
+    pub fn with_relays(mut self, relays: Vec<String>) -> Self {
+        self.relays = relays;
+        self
+    }
+
+    /// Routes relative to `API_PREFIX`.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/offers", post(create_offer))
+            .route("/offers/:id/accept", post(accept_offer))
+            .route("/swaps/:id", get(swap_status))
+            .route("/swaps/:id/lock", post(submit_lock))
+            .route("/swaps/:id/presignature", get(get_presignature))
+            .route("/swaps/:id/event", post(submit_event))
+            .with_state(self.clone())
+    }
+
+    fn with_swap<T>(&self, id: &str, f: impl FnOnce(&mut SwapRecord) -> Result<T, ApiError>) -> Result<T, ApiError> {
+        let mut swaps = self.swaps.lock().unwrap();
+        let record = swaps.get_mut(id).ok_or_else(|| ApiError::NotFound(id.to_string()))?;
+        f(record)
+    }
+
+    /// Register the seller's offer after checking the event id and pre-signature.
+    pub fn create_offer(&self, req: CreateOfferRequest) -> Result<CreateOfferResponse, ApiError> {
+        let event = req.event;
+        let computed = EventId::new(&event.pubkey, event.created_at, &event.kind, &event.tags, &event.content);
+        if computed != event.id {
+            return Err(ApiError::BadRequest(format!("event id should be {}", computed)));
+        }
+        let seller_pubkey = XOnlyPublicKey::from_slice(&event.pubkey.serialize()).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        if req.presignature.pubkey.x_only_public_key().0 != seller_pubkey {
+            return Err(ApiError::BadRequest("pre-signature key is not the event author".to_string()));
+        }
+        let adaptor_sig = req.presignature.to_adaptor_signature(&event.id);
+        if !adaptor_sig.verify(&Secp256k1::new()) {
+            return Err(ApiError::BadRequest("pre-signature does not verify against the event id".to_string()));
+        }
+        if let Some(content) = &req.encrypted_content {
+            if content.adaptor_point() != adaptor_sig.adaptor_point {
+                return Err(ApiError::BadRequest("content is not encrypted to the pre-signature's adaptor point".to_string()));
+            }
+            if !content.verify(&Secp256k1::new()) {
+                return Err(ApiError::BadRequest("content key is not recoverable with the adaptor secret".to_string()));
+            }
+        }
+        // The swap output is P2TR, and every P2TR output has the same dust threshold.
+        let amount = Amount::from_sat(req.amount_sat);
+        let dust_limit = ScriptBuf::new_p2tr(&Secp256k1::new(), req.claim_pubkey, None).dust_value();
+        if amount < dust_limit {
+            return Err(ApiError::BadRequest(format!("amount is below the dust limit of {}", dust_limit)));
+        }
+
+        let swap_id = event.id.to_hex();
+        let mut swaps = self.swaps.lock().unwrap();
+        if swaps.contains_key(&swap_id) {
+            return Err(ApiError::Conflict(format!("swap {} already exists", swap_id)));
+        }
+        swaps.insert(
+            swap_id.clone(),
+            SwapRecord {
+                event,
+                adaptor_sig,
+                amount,
+                claim_pubkey: req.claim_pubkey,
+                seller_pubkey,
+                buyer_pubkey: req.buyer_pubkey,
+                swap_output: None,
+                lock_outpoint: None,
+                completed_event: None,
+                encrypted_content: req.encrypted_content,
+                status: SwapStatus::Offered,
+            },
+        );
+        Ok(CreateOfferResponse { swap_id, status: SwapStatus::Offered })
+    }
+
+    /// Fix the swap output for the buyer's refund key, once the buyer proves they hold it.
+    pub fn accept_offer(&self, id: &str, req: AcceptOfferRequest) -> Result<AcceptOfferResponse, ApiError> {
+        let params = self.params;
+        Secp256k1::verification_only()
+            .verify_schnorr(&req.signature, &accept_message(id), &req.buyer_pubkey)
+            .map_err(|_| ApiError::Forbidden("signature does not accept this swap with the buyer key".to_string()))?;
+        self.with_swap(id, |record| {
+            require_status(record, SwapStatus::Offered)?;
+            if record.buyer_pubkey.is_some_and(|buyer| buyer != req.buyer_pubkey) {
+                return Err(ApiError::Forbidden("offer is reserved for another buyer".to_string()));
+            }
+            let swap = SwapOutput::new(record.claim_pubkey, req.buyer_pubkey, params.refund_blocks).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+            let address = swap.address(params.network).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+            record.swap_output = Some(swap);
+            record.status = SwapStatus::Accepted;
+            Ok(AcceptOfferResponse {
+                swap_id: id.to_string(),
+                status: record.status,
+                address: address.to_string(),
+                descriptor: swap.descriptor(),
+                amount_sat: record.amount.to_sat(),
+                refund_blocks: params.refund_blocks,
+            })
+        })
+    }
+
+    /// Move the swap to Locked once the chain shows an unspent output paying it in full.
+    pub async fn submit_lock(&self, id: &str, req: SubmitLockRequest) -> Result<SwapStatusResponse, ApiError> {
+        let (swap, amount) = self.with_swap(id, |record| {
+            require_status(record, SwapStatus::Accepted)?;
+            Ok((record.swap_output.expect("accepted swaps have an output"), record.amount))
+        })?;
+        let outpoint = OutPoint { txid: req.txid, vout: req.vout };
+        let chain_error = |e: anyhow::Error| ApiError::BadRequest(format!("cannot check {}: {}", outpoint, e));
+        let tx = self.chain.get_tx(&req.txid).await.map_err(chain_error)?;
+        let txout = tx
+            .and_then(|tx| tx.output.get(req.vout as usize).cloned())
+            .ok_or_else(|| ApiError::BadRequest(format!("output {} not found", outpoint)))?;
+        let script_pubkey = swap.script_pubkey().map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        if txout.script_pubkey != script_pubkey {
+            return Err(ApiError::BadRequest(format!("output {} does not pay the swap address", outpoint)));
+        }
+        if txout.value != amount {
+            return Err(ApiError::BadRequest(format!("output {} pays {}, expected {}", outpoint, txout.value, amount)));
+        }
+        if self.chain.get_spending_tx(&outpoint).await.map_err(chain_error)?.is_some() {
+            return Err(ApiError::BadRequest(format!("output {} is already spent", outpoint)));
+        }
+        self.with_swap(id, |record| {
+            require_status(record, SwapStatus::Accepted)?;
+            record.lock_outpoint = Some(outpoint);
+            record.status = SwapStatus::Locked;
+            Ok(self.status_response(id, record))
+        })
+    }
+
+    pub fn presignature(&self, id: &str) -> Result<PreSignatureResponse, ApiError> {
+        self.with_swap(id, |record| {
+            Ok(PreSignatureResponse {
+                swap_id: id.to_string(),
+                event: record.event.clone(),
+                presignature: PreSignature::from(&record.adaptor_sig),
+            })
+        })
+    }
+
+    /// Accept the completed event once it verifies and completes the pre-signature.
+    pub fn submit_event(&self, id: &str, req: SubmitEventRequest) -> Result<SubmitEventResponse, ApiError> {
+        self.with_swap(id, |record| {
+            require_status(record, SwapStatus::Locked)?;
+            let event = req.event;
+            if event.id != record.event.id {
+                return Err(ApiError::BadRequest(format!("event {} is not the offered event", event.id)));
+            }
+            event.verify().map_err(|e| ApiError::BadRequest(format!("invalid event: {}", e)))?;
+            let sig = secp256k1::schnorr::Signature::from_slice(event.signature().as_ref()).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+            let secret = record
+                .adaptor_sig
+                .try_extract_secret(&Secp256k1::new(), &sig)
+                .ok_or_else(|| ApiError::BadRequest("event signature does not complete the pre-signature".to_string()))?;
+            record.completed_event = Some(event);
+            record.status = SwapStatus::Completed;
+            Ok(SubmitEventResponse { swap_id: id.to_string(), status: record.status, secret })
+        })
+    }
+
+    pub fn status(&self, id: &str) -> Result<SwapStatusResponse, ApiError> {
+        self.with_swap(id, |record| Ok(self.status_response(id, record)))
+    }
+
+    fn status_response(&self, id: &str, record: &SwapRecord) -> SwapStatusResponse {
+        SwapStatusResponse {
+            swap_id: id.to_string(),
+            status: record.status,
+            network: self.params.network,
+            amount_sat: record.amount.to_sat(),
+            seller_pubkey: record.seller_pubkey,
+            buyer_pubkey: record.swap_output.map(|swap| swap.buyer_pubkey),
+            address: record.swap_output.and_then(|swap| swap.address(self.params.network).ok()).map(|a| a.to_string()),
+            lock_outpoint: record.lock_outpoint,
+            completed_event: record.completed_event.clone(),
+            encrypted_content: record.encrypted_content.clone(),
+        }
+    }
+}
+
+fn require_status(record: &SwapRecord, expected: SwapStatus) -> Result<(), ApiError> {
+    if record.status != expected {
+        return Err(ApiError::Conflict(format!("swap is {:?}, expected {:?}", record.status, expected)));
+    }
+    Ok(())
+}
+
+async fn create_offer<B: ChainBackend + Clone + Send + Sync + 'static>(
+    State(api): State<SwapApi<B>>,
+    Json(req): Json<CreateOfferRequest>,
+) -> Result<(StatusCode, Json<CreateOfferResponse>), ApiError> {
+    Ok((StatusCode::CREATED, Json(api.create_offer(req)?)))
+}
+
+async fn accept_offer<B: ChainBackend + Clone + Send + Sync + 'static>(
+    State(api): State<SwapApi<B>>,
+    Path(id): Path<String>,
+    Json(req): Json<AcceptOfferRequest>,
+) -> Result<Json<AcceptOfferResponse>, ApiError> {
+    Ok(Json(api.accept_offer(&id, req)?))
+}
+
+async fn submit_lock<B: ChainBackend + Clone + Send + Sync + 'static>(
+    State(api): State<SwapApi<B>>,
+    Path(id): Path<String>,
+    Json(req): Json<SubmitLockRequest>,
+) -> Result<Json<SwapStatusResponse>, ApiError> {
+    Ok(Json(api.submit_lock(&id, req).await?))
+}
+
+async fn get_presignature<B: ChainBackend + Clone + Send + Sync + 'static>(State(api): State<SwapApi<B>>, Path(id): Path<String>) -> Result<Json<PreSignatureResponse>, ApiError> {
+    Ok(Json(api.presignature(&id)?))
+}
+
+async fn submit_event<B: ChainBackend + Clone + Send + Sync + 'static>(
+    State(api): State<SwapApi<B>>,
+    Path(id): Path<String>,
+    Json(req): Json<SubmitEventRequest>,
+) -> Result<Json<SubmitEventResponse>, ApiError> {
+    let event = req.event.clone();
+    let response = api.submit_event(&id, req)?;
+    if !api.relays.is_empty() {
+        tokio::spawn(async move {
+            let mut client = RelayClient::new(WebSocketTransport, api.relays.clone());
+            let published = match client.connect().await {
+                Ok(_) => client.publish(&event, Duration::from_secs(10)).await,
+                Err(e) => Err(e),
+            };
+            match published {
+                Ok(accepted) => tracing::info!("Forwarded event {} to {} relay(s)", event.id, accepted.len()),
+                Err(e) => tracing::warn!("Could not forward event {}: {}", event.id, e),
+            }
+        });
+    }
+    Ok(Json(response))
+}
+
+async fn swap_status<B: ChainBackend + Clone + Send + Sync + 'static>(State(api): State<SwapApi<B>>, Path(id): Path<String>) -> Result<Json<SwapStatusResponse>, ApiError> {
+    Ok(Json(api.status(&id)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::MockChain;
+    use crate::nostr_utils::create_unsigned_event;
+    use crate::signer::AdaptorSigner;
+    use nostr::Kind;
+    use secp256k1::Keypair;
+
+    async fn offer(api: &SwapApi<MockChain>, buyer_pubkey: Option<XOnlyPublicKey>) -> String {
+        let secp = Secp256k1::new();
+        let seller = Keypair::new(&secp, &mut rand::thread_rng());
+        let t = SecretKey::new(&mut rand::thread_rng());
+        let event = create_unsigned_event(&seller.x_only_public_key().0, Kind::TextNote, "for sale", vec![]).unwrap();
+        let adaptor_sig = seller.presign_event(&event, &t.public_key(&secp)).await.unwrap();
+        let request = CreateOfferRequest {
+            event,
+            presignature: PreSignature::from(&adaptor_sig),
+            amount_sat: 50_000,
+            claim_pubkey: seller.x_only_public_key().0,
+            encrypted_content: None,
+            buyer_pubkey,
+        };
+        api.create_offer(request).unwrap().swap_id
+    }
+
+    fn buyer() -> Keypair {
+        Keypair::new(&Secp256k1::new(), &mut rand::thread_rng())
+    }
+
+    #[tokio::test]
+    async fn accepting_needs_the_buyer_signature() {
+        let api = SwapApi::new(Network::Regtest, MockChain::new());
+        let swap_id = offer(&api, None).await;
+        let mut request = AcceptOfferRequest::sign(&buyer(), &swap_id).await.unwrap();
+        request.buyer_pubkey = buyer().x_only_public_key().0;
+        assert!(matches!(api.accept_offer(&swap_id, request), Err(ApiError::Forbidden(_))));
+
+        let request = AcceptOfferRequest::sign(&buyer(), "another swap").await.unwrap();
+        assert!(matches!(api.accept_offer(&swap_id, request), Err(ApiError::Forbidden(_))));
+
+        let request = AcceptOfferRequest::sign(&buyer(), &swap_id).await.unwrap();
+        assert_eq!(api.accept_offer(&swap_id, request).unwrap().status, SwapStatus::Accepted);
+        let request = AcceptOfferRequest::sign(&buyer(), &swap_id).await.unwrap();
+        assert!(matches!(api.accept_offer(&swap_id, request), Err(ApiError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn reserved_offer_is_only_accepted_by_its_buyer() {
+        let api = SwapApi::new(Network::Regtest, MockChain::new());
+        let reserved = buyer();
+        let swap_id = offer(&api, Some(reserved.x_only_public_key().0)).await;
+        let request = AcceptOfferRequest::sign(&buyer(), &swap_id).await.unwrap();
+        assert!(matches!(api.accept_offer(&swap_id, request), Err(ApiError::Forbidden(_))));
+        let request = AcceptOfferRequest::sign(&reserved, &swap_id).await.unwrap();
+        assert!(api.accept_offer(&swap_id, request).is_ok());
+    }
+
+    #[tokio::test]
+    async fn lock_must_pay_the_swap_output_in_full() {
+        let chain = MockChain::new();
+        let api = SwapApi::new(Network::Regtest, chain.clone());
+        let swap_id = offer(&api, None).await;
+        let request = AcceptOfferRequest::sign(&buyer(), &swap_id).await.unwrap();
+        let accepted = api.accept_offer(&swap_id, request).unwrap();
+        let script_pubkey = SwapOutput::from_descriptor(&accepted.descriptor).unwrap().script_pubkey().unwrap();
+        let lock = |outpoint: OutPoint| SubmitLockRequest { txid: outpoint.txid, vout: outpoint.vout };
+
+        let unknown = OutPoint { txid: Txid::all_zeros(), vout: 0 };
+        assert!(matches!(api.submit_lock(&swap_id, lock(unknown)).await, Err(ApiError::BadRequest(_))));
+        let elsewhere = chain.fund(ScriptBuf::new_p2tr(&Secp256k1::new(), buyer().x_only_public_key().0, None), Amount::from_sat(50_000));
+        assert!(matches!(api.submit_lock(&swap_id, lock(elsewhere)).await, Err(ApiError::BadRequest(_))));
+        let short = chain.fund(script_pubkey.clone(), Amount::from_sat(49_999));
+        assert!(matches!(api.submit_lock(&swap_id, lock(short)).await, Err(ApiError::BadRequest(_))));
+        assert_eq!(api.status(&swap_id).unwrap().status, SwapStatus::Accepted);
+
+        let funded = chain.fund(script_pubkey, Amount::from_sat(50_000));
+        let status = api.submit_lock(&swap_id, lock(funded)).await.unwrap();
+        assert_eq!((status.status, status.lock_outpoint), (SwapStatus::Locked, Some(funded)));
+    }
+}
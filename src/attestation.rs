@@ -0,0 +1,166 @@
+use crate::adaptor::AdaptorSignature;
+use crate::nostr_utils::{check_presigned_event, create_signed_event, create_unsigned_event, extract_secret_from_event, to_nostr_pubkey};
+use crate::signer::AdaptorSigner;
+use anyhow::{anyhow, Result};
+use nostr::{Event, Keys, Kind, Tag, TagKind, Timestamp, UnsignedEvent};
+use secp256k1::{PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use std::str::FromStr;
+
+/// NIP-32 label event kind.
+pub const LABEL_KIND: u64 = 1985;
+/// Tag carrying the adaptor point of the swap that paid for an attestation.
+pub const SWAP_TAG: &str = "swap";
+
+/// What the seller attests about the buyer's key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Claim {
+    /// A NIP-32 label in a namespace, e.g. `verified` in `com.example.nip05`.
+    Label { namespace: String, label: String },
+    /// An event of a custom attestation kind with free-form content.
+    Custom { kind: u64, content: String },
+}
+
+/// A signed statement about the buyer's key, sold through an adaptor swap.
+///
+/// The event names the buyer in a `p` tag, expires per NIP-40 and carries a `swap` tag.
+/// The swap id is the event id, so the tag names the swap by its adaptor point instead,
+/// which is fixed before the event is drafted. Once the event is published, its signature
+/// and the offer's pre-signature reveal `t` for that point: proof the payment released it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+    pub claim: Claim,
+    pub subject: XOnlyPublicKey,
+    pub expiration: Timestamp,
+    pub adaptor_point: PublicKey,
+}
+
+impl Attestation {
+    pub fn kind(&self) -> Kind {
+        match &self.claim {
+            Claim::Label { .. } => Kind::from(LABEL_KIND),
+            Claim::Custom { kind, .. } => Kind::from(*kind),
+        }
+    }
+
+    pub fn content(&self) -> &str {
+        match &self.claim {
+            Claim::Label { .. } => "",
+            Claim::Custom { content, .. } => content,
+        }
+    }
+
+    pub fn tags(&self) -> Result<Vec<Tag>> {
+        let mut tags = match &self.claim {
+            Claim::Label { namespace, label } => vec![
+                Tag::Generic(TagKind::Custom("L".into()), vec![namespace.clone()]),
+                Tag::Generic(TagKind::Custom("l".into()), vec![label.clone(), namespace.clone()]),
+            ],
+            Claim::Custom { .. } => vec![],
+        };
+        tags.push(Tag::public_key(to_nostr_pubkey(&self.subject)?));
+        tags.push(Tag::Expiration(self.expiration));
+        tags.push(Tag::Generic(TagKind::Custom(SWAP_TAG.into()), vec![self.adaptor_point.to_string()]));
+        Ok(tags)
+    }
+
+    /// Sign the attestation outright, without a swap.
+    pub fn to_signed_event(&self, keys: &Keys) -> Result<Event> {
+        Ok(create_signed_event(keys, self.kind(), self.content(), self.tags()?))
+    }
+
+    pub fn to_unsigned_event(&self, seller: &XOnlyPublicKey) -> Result<UnsignedEvent> {
+        create_unsigned_event(seller, self.kind(), self.content(), self.tags()?)
+    }
+
+    /// Draft the event and pre-sign it for the attestation's adaptor point.
+    pub async fn presign(&self, signer: &impl AdaptorSigner) -> Result<(UnsignedEvent, AdaptorSignature)> {
+        let event = self.to_unsigned_event(&signer.public_key().await?)?;
+        let adaptor_sig = signer.presign_event(&event, &self.adaptor_point).await?;
+        Ok((event, adaptor_sig))
+    }
+
+    /// Read the attestation an event makes.
+    pub fn from_event(kind: Kind, content: &str, tags: &[Tag]) -> Result<Self> {
+        let tags: Vec<Vec<String>> = tags.iter().map(|tag| tag.as_vec()).collect();
+        let value = |name: &str| tags.iter().find(|tag| tag.len() >= 2 && tag[0] == name).map(|tag| tag[1].as_str());
+        let claim = if kind == Kind::from(LABEL_KIND) {
+            let namespace = value("L").ok_or_else(|| anyhow!("Label event has no L tag"))?;
+            let label = tags
+                .iter()
+                .find(|tag| tag.len() >= 3 && tag[0] == "l" && tag[2] == namespace)
+                .ok_or_else(|| anyhow!("Label event has no l tag in namespace {}", namespace))?;
+            Claim::Label { namespace: namespace.to_string(), label: label[1].clone() }
+        } else {
+            Claim::Custom { kind: kind.as_u64(), content: content.to_string() }
+        };
+        let subject = value("p").ok_or_else(|| anyhow!("Attestation has no p tag"))?;
+        let expiration = value("expiration").ok_or_else(|| anyhow!("Attestation has no expiration tag"))?;
+        let adaptor_point = value(SWAP_TAG).ok_or_else(|| anyhow!("Attestation has no {} tag", SWAP_TAG))?;
+        Ok(Attestation {
+            claim,
+            subject: XOnlyPublicKey::from_str(subject)?,
+            expiration: Timestamp::from_str(expiration)?,
+            adaptor_point: PublicKey::from_str(adaptor_point)?,
+        })
+    }
+}
+
+/// Before locking: check the offered event attests about `buyer`, has not expired and is
+/// pre-signed by its author for the adaptor point in its swap tag.
+pub fn check_attestation_offer(event: &UnsignedEvent, adaptor_sig: &AdaptorSignature, buyer: &XOnlyPublicKey) -> Result<Attestation> {
+    let attestation = Attestation::from_event(event.kind, &event.content, &event.tags)?;
+    if attestation.subject != *buyer {
+        return Err(anyhow!("Attestation is about {}, not {}", attestation.subject, buyer));
+    }
+    if attestation.expiration <= Timestamp::now() {
+        return Err(anyhow!("Attestation expired at {}", attestation.expiration));
+    }
+    if attestation.adaptor_point != adaptor_sig.adaptor_point {
+        return Err(anyhow!("Swap tag {} is not the pre-signature's adaptor point", attestation.adaptor_point));
+    }
+    check_presigned_event(event, adaptor_sig)?;
+    Ok(attestation)
+}
+
+/// Recover `t` from the published attestation and its pre-signature, and check it opens the
+/// swap tag's adaptor point. The buyer shows `t` with the event to prove the swap paid for it.
+pub fn prove_attestation_payment(event: &Event, adaptor_sig: &AdaptorSignature) -> Result<(Attestation, SecretKey)> {
+    let attestation = Attestation::from_event(event.kind, &event.content, &event.tags)?;
+    let t = extract_secret_from_event(adaptor_sig, event)?;
+    if t.public_key(&Secp256k1::new()) != attestation.adaptor_point {
+        return Err(anyhow!("Event signature does not reveal the secret for swap {}", attestation.adaptor_point));
+    }
+    Ok((attestation, t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_utils::event_message;
+    use crate::signer::SigningKey;
+    use secp256k1::Keypair;
+
+    fn keypair() -> Keypair {
+        Keypair::new(&Secp256k1::new(), &mut rand::thread_rng())
+    }
+
+    fn attestation(subject: XOnlyPublicKey, adaptor_point: PublicKey) -> Attestation {
+        let claim = Claim::Label { namespace: "com.example.nip05".to_string(), label: "verified".to_string() };
+        Attestation { claim, subject, expiration: Timestamp::now() + 3600u64, adaptor_point }
+    }
+
+    #[tokio::test]
+    async fn offer_must_be_presigned_by_its_author() {
+        let (seller, buyer) = (keypair(), keypair().x_only_public_key().0);
+        let adaptor_point = SecretKey::new(&mut rand::thread_rng()).public_key(&Secp256k1::new());
+        let (event, adaptor_sig) = attestation(buyer, adaptor_point).presign(&seller).await.unwrap();
+        assert_eq!(check_attestation_offer(&event, &adaptor_sig, &buyer).unwrap().subject, buyer);
+
+        let forged = keypair().presign(&event_message(&event.id), &adaptor_point, SigningKey::Internal).await.unwrap();
+        assert!(check_attestation_offer(&event, &forged, &buyer).is_err());
+
+        let mut altered = event.clone();
+        altered.content = "edited".to_string();
+        assert!(check_attestation_offer(&altered, &adaptor_sig, &buyer).is_err());
+    }
+}
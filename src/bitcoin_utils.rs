@@ -1,10 +1,21 @@
 use bitcoin::{
-    Amount, Address, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, absolute::LockTime,
-    key::Keypair, sighash::{SighashCache, Prevouts, TapSighashType}, transaction::Version,
+    Amount, Address, FeeRate, Network, address::NetworkUnchecked, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, absolute::LockTime,
+    key::Keypair, opcodes::all::{OP_CSV, OP_CHECKSIGVERIFY}, script::Builder,
+    sighash::{SighashCache, Prevouts, TapSighash, TapSighashType}, taproot::{ControlBlock, Signature as TaprootSignature, LeafVersion, TapLeafHash, TapNodeHash, TaprootBuilder, TaprootSpendInfo},
+    transaction::Version,
 };
-use secp256k1::{Secp256k1, Scalar, XOnlyPublicKey, Message};
-use anyhow::Result;
-use rand::rngs::OsRng;
+use secp256k1::{Secp256k1, Scalar, SecretKey, XOnlyPublicKey, Message, schnorr::Signature as SchnorrSignature};
+use anyhow::{anyhow, Result};
+use crate::network::require_network;
+use crate::signer::{AdaptorSigner, SchnorrSigner, SigningKey};
+use crate::spend::{InputSigner, SpendBuilder, SpendInput};
+use crate::validation::{validate_transaction, MIN_RELAY_FEE};
+
+/// Value of a CPFP anchor output: the P2TR dust limit.
+pub const ANCHOR_VALUE: Amount = Amount::from_sat(330);
+
+/// Minimum feerate increase a BIP125 replacement must pay for its own size.
+pub const INCREMENTAL_RELAY_FEE: FeeRate = FeeRate::from_sat_per_vb_unchecked(1);
 
 /// Create a P2TR locking transaction
 pub fn create_locking_transaction(
@@ -15,7 +26,16 @@ pub fn create_locking_transaction(
 ) -> Transaction {
     let secp = Secp256k1::new();
     let script_pubkey = ScriptBuf::new_p2tr(&secp, recipient_pubkey, None);
+    create_locking_transaction_to_script(prev_txid, prev_vout, amount, script_pubkey)
+}
 
+/// Create a locking transaction paying `amount` to an arbitrary output script, e.g. a `SwapOutput`.
+pub fn create_locking_transaction_to_script(
+    prev_txid: Txid,
+    prev_vout: u32,
+    amount: Amount,
+    script_pubkey: ScriptBuf,
+) -> Transaction {
     Transaction {
         version: Version(2),
         lock_time: LockTime::ZERO,
@@ -32,46 +52,572 @@ pub fn create_locking_transaction(
     }
 }
 
-/// Create a spending transaction for P2TR using Schnorr signature
-pub fn create_spending_transaction(
+/// Create a P2TR locking transaction funded from wallet UTXOs at `fee_rate`.
+/// The swap output is output 0; change, if any, is output 1. Inputs are left unsigned.
+pub fn create_funded_locking_transaction(
+    utxos: &[crate::chain::Utxo],
+    amount: Amount,
+    script_pubkey: ScriptBuf,
+    change_script: ScriptBuf,
+    fee_rate: FeeRate,
+) -> Result<(Transaction, crate::coin_selection::CoinSelection)> {
+    let mut outputs = vec![TxOut { value: amount, script_pubkey }];
+    let selection = crate::coin_selection::select_coins(utxos, &outputs, &change_script, fee_rate)?;
+    if let Some(change) = selection.change {
+        outputs.push(TxOut { value: change, script_pubkey: change_script });
+    }
+
+    let tx = Transaction {
+        version: Version(2),
+        lock_time: LockTime::ZERO,
+        input: selection
+            .selected
+            .iter()
+            .map(|utxo| TxIn {
+                previous_output: utxo.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: outputs,
+    };
+    Ok((tx, selection))
+}
+
+/// Create a spending transaction for P2TR using Schnorr signature.
+/// With an ANYONECANPAY `sighash_type` the result may be left for a third party to add
+/// fee inputs, so it is only held to the feerate floor for other types.
+/// See `SpendBuilder` for several inputs or outputs.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_spending_transaction(
     prev_txid: Txid,
     prev_vout: u32,
     prev_txout: &TxOut,
     amount: Amount,
-    recipient: Address,
-    signer_keypair: &Keypair,
+    recipient: Address<NetworkUnchecked>,
+    network: Network,
+    signer: &impl SchnorrSigner,
+    sighash_type: TapSighashType,
 ) -> Result<Transaction> {
+    let signer = InputSigner::KeyPath { signer, merkle_root: None };
+    let mut input = SpendInput::new(OutPoint { txid: prev_txid, vout: prev_vout }, prev_txout.clone(), signer);
+    input.sequence = Sequence::MAX;
+    input.sighash_type = sighash_type;
+
+    let mut builder = SpendBuilder::new(network);
+    builder.add_input(input).add_output(recipient, amount)?;
+    builder.build().await
+}
+
+/// Create Taproot output tweaking key with commitment
+pub fn create_nostr_signature_lock_script(commitment: [u8; 32], internal_key: XOnlyPublicKey) -> Result<XOnlyPublicKey> {
     let secp = Secp256k1::new();
-    let mut tx = Transaction {
+    let tweak = Scalar::from_be_bytes(commitment)?;
+    let (tweaked_key, _) = internal_key.add_tweak(&secp, &tweak)?;
+    Ok(tweaked_key)
+}
+
+/// Keypair for the key returned by `create_nostr_signature_lock_script(commitment, keypair's x-only key)`.
+pub fn nostr_signature_claim_keypair(commitment: [u8; 32], keypair: &Keypair) -> Result<Keypair> {
+    let secp = Secp256k1::new();
+    let (_, parity) = keypair.x_only_public_key();
+    let secret = if parity == secp256k1::Parity::Odd { keypair.secret_key().negate() } else { keypair.secret_key() };
+    let tweaked = secret.add_tweak(&Scalar::from_be_bytes(commitment)?)?;
+    Ok(Keypair::from_secret_key(&secp, &tweaked))
+}
+/// Collect the Schnorr signatures from a taproot input witness.
+/// Key path: `[sig]`. Script path: `[inputs..., script, control_block]`, where any
+/// 64/65-byte stack input is taken as a signature. A trailing annex is ignored.
+pub fn taproot_witness_signatures(witness: &Witness) -> Vec<SchnorrSignature> {
+    let mut elements: Vec<&[u8]> = witness.iter().collect();
+    if elements.len() >= 2 && elements.last().is_some_and(|e| e.first() == Some(&0x50)) {
+        elements.pop();
+    }
+    let stack = match elements.len() {
+        0 => return vec![],
+        1 => &elements[..],
+        n => &elements[..n - 2],
+    };
+    stack
+        .iter()
+        .filter(|e| e.len() == 64 || e.len() == 65)
+        // A 65th byte is the sighash type
+        .filter_map(|e| SchnorrSignature::from_slice(&e[..64]).ok())
+        .collect()
+}
+
+/// Find the input spending `outpoint` in a claim transaction and extract the adaptor secret
+/// from its witness signature. `adaptor_sig` is the claim's pre-signature from
+/// `presign_claim_transaction`; a plain claim from `create_claim_transaction` holds no secret.
+pub fn extract_secret_from_claim_tx(
+    secp: &Secp256k1<secp256k1::All>,
+    claim_tx: &Transaction,
+    outpoint: &OutPoint,
+    adaptor_sig: &crate::adaptor::AdaptorSignature,
+) -> Result<SecretKey> {
+    let input = claim_tx
+        .input
+        .iter()
+        .find(|input| input.previous_output == *outpoint)
+        .ok_or_else(|| anyhow!("Transaction {} does not spend {}", claim_tx.txid(), outpoint))?;
+    taproot_witness_signatures(&input.witness)
+        .iter()
+        .find_map(|sig| adaptor_sig.try_extract_secret(secp, sig))
+        .ok_or_else(|| anyhow!("No completed adaptor signature in witness of {}", claim_tx.txid()))
+}
+
+/// The swap lock output: the claim key spends by key path, the buyer can refund
+/// through a script leaf once the relative timelock has passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapOutput {
+    pub claim_key: XOnlyPublicKey,
+    pub buyer_pubkey: XOnlyPublicKey,
+    pub refund_sequence: Sequence,
+}
+
+impl SwapOutput {
+    /// `refund_blocks` must be at least 1: `older(0)` pushes `OP_0`, which leaves `OP_CSV`
+    /// a false stack top, so the refund leaf could never be spent.
+    pub fn new(claim_key: XOnlyPublicKey, buyer_pubkey: XOnlyPublicKey, refund_blocks: u16) -> Result<Self> {
+        if refund_blocks == 0 {
+            return Err(anyhow!("Refund timelock must be at least 1 block"));
+        }
+        Ok(SwapOutput {
+            claim_key,
+            buyer_pubkey,
+            refund_sequence: Sequence::from_height(refund_blocks),
+        })
+    }
+
+    /// `<buyer_pubkey> OP_CHECKSIGVERIFY <refund_sequence> OP_CSV`, the miniscript
+    /// `and_v(v:pk(buyer_pubkey),older(refund_sequence))`.
+    pub fn refund_script(&self) -> ScriptBuf {
+        Builder::new()
+            .push_x_only_key(&self.buyer_pubkey)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_sequence(self.refund_sequence)
+            .push_opcode(OP_CSV)
+            .into_script()
+    }
+
+    pub fn spend_info(&self) -> Result<TaprootSpendInfo> {
+        TaprootBuilder::new()
+            .add_leaf(0, self.refund_script())?
+            .finalize(&Secp256k1::new(), self.claim_key)
+            .map_err(|_| anyhow!("Failed to finalize swap taproot tree"))
+    }
+
+    pub fn script_pubkey(&self) -> Result<ScriptBuf> {
+        Ok(ScriptBuf::new_p2tr_tweaked(self.spend_info()?.output_key()))
+    }
+
+    pub fn address(&self, network: Network) -> Result<Address> {
+        Ok(Address::p2tr_tweaked(self.spend_info()?.output_key(), network))
+    }
+
+    pub fn refund_control_block(&self) -> Result<ControlBlock> {
+        self.spend_info()?
+            .control_block(&(self.refund_script(), LeafVersion::TapScript))
+            .ok_or_else(|| anyhow!("Refund leaf missing from swap tree"))
+    }
+}
+
+/// Whether `sighash_type` lets other parties add inputs after signing.
+pub fn is_anyone_can_pay(sighash_type: TapSighashType) -> bool {
+    matches!(
+        sighash_type,
+        TapSighashType::AllPlusAnyoneCanPay | TapSighashType::NonePlusAnyoneCanPay | TapSighashType::SinglePlusAnyoneCanPay
+    )
+}
+
+/// Sighash for input `index`. ANYONECANPAY types commit only to the input's own prevout
+/// (`Prevouts::One`), so `prevouts` may then hold just that output; otherwise it must line
+/// up with `tx.input`.
+pub fn taproot_sighash(
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+    leaf_hash: Option<TapLeafHash>,
+    sighash_type: TapSighashType,
+) -> Result<TapSighash> {
+    taproot_sighash_with_cache(&mut SighashCache::new(tx), index, prevouts, leaf_hash, sighash_type)
+}
+
+/// `taproot_sighash` through a shared `cache`, so the transaction-wide hashes are computed
+/// once when signing several inputs.
+pub fn taproot_sighash_with_cache(
+    cache: &mut SighashCache<&Transaction>,
+    index: usize,
+    prevouts: &[TxOut],
+    leaf_hash: Option<TapLeafHash>,
+    sighash_type: TapSighashType,
+) -> Result<TapSighash> {
+    let sighash = if is_anyone_can_pay(sighash_type) {
+        let prevout = if prevouts.len() == 1 { &prevouts[0] } else { prevouts.get(index).ok_or_else(|| anyhow!("No prevout for input {}", index))? };
+        let prevouts = Prevouts::One(index, prevout);
+        match leaf_hash {
+            Some(leaf_hash) => cache.taproot_script_spend_signature_hash(index, &prevouts, leaf_hash, sighash_type)?,
+            None => cache.taproot_key_spend_signature_hash(index, &prevouts, sighash_type)?,
+        }
+    } else {
+        let prevouts = Prevouts::All(prevouts);
+        match leaf_hash {
+            Some(leaf_hash) => cache.taproot_script_spend_signature_hash(index, &prevouts, leaf_hash, sighash_type)?,
+            None => cache.taproot_key_spend_signature_hash(index, &prevouts, sighash_type)?,
+        }
+    };
+    Ok(sighash)
+}
+
+/// Witness encoding of `sig`: 64 bytes for `Default`, otherwise with the sighash byte appended.
+pub fn taproot_signature_bytes(sig: SchnorrSignature, sighash_type: TapSighashType) -> Vec<u8> {
+    TaprootSignature { sig, hash_ty: sighash_type }.to_vec()
+}
+
+/// Placeholder signature of the right size for fee estimation.
+pub(crate) fn dummy_signature(sighash_type: TapSighashType) -> Vec<u8> {
+    vec![0u8; if sighash_type == TapSighashType::Default { 64 } else { 65 }]
+}
+
+/// Sign input `index` by the taproot key path. The signer's key is tweaked with
+/// `merkle_root` the same way the output key was.
+pub async fn sign_taproot_key_spend(
+    tx: &mut Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+    signer: &impl SchnorrSigner,
+    merkle_root: Option<TapNodeHash>,
+    sighash_type: TapSighashType,
+) -> Result<()> {
+    let sighash = taproot_sighash(tx, index, prevouts, None, sighash_type)?;
+    let sig = signer.sign_schnorr(&Message::from(sighash), SigningKey::Taproot(merkle_root)).await?;
+    tx.input[index].witness = Witness::from_slice(&[taproot_signature_bytes(sig, sighash_type)]);
+    Ok(())
+}
+
+/// Sign input `index` through the tapscript leaf `script`.
+pub async fn sign_taproot_script_spend(
+    tx: &mut Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+    signer: &impl SchnorrSigner,
+    script: &ScriptBuf,
+    control_block: &ControlBlock,
+    sighash_type: TapSighashType,
+) -> Result<()> {
+    let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+    let sighash = taproot_sighash(tx, index, prevouts, Some(leaf_hash), sighash_type)?;
+    let sig = signer.sign_schnorr(&Message::from(sighash), SigningKey::Internal).await?;
+    tx.input[index].witness = Witness::from_slice(&[taproot_signature_bytes(sig, sighash_type), script.to_bytes(), control_block.serialize()]);
+    Ok(())
+}
+
+/// Adaptor pre-signature for a key-path spend of input `index`, encrypted to `t * G`.
+/// `complete_taproot_key_spend` turns it into a valid witness once `t` is known.
+#[allow(clippy::too_many_arguments)]
+pub async fn presign_taproot_key_spend(
+    secp: &Secp256k1<secp256k1::All>,
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+    signer: &impl AdaptorSigner,
+    merkle_root: Option<TapNodeHash>,
+    sighash_type: TapSighashType,
+    t: &SecretKey,
+) -> Result<crate::adaptor::AdaptorSignature> {
+    let sighash = taproot_sighash(tx, index, prevouts, None, sighash_type)?;
+    signer.presign(&Message::from(sighash), &t.public_key(secp), SigningKey::Taproot(merkle_root)).await
+}
+
+/// Complete a pre-signature from `presign_taproot_key_spend` with `t` and place it in the
+/// witness of input `index`. `sighash_type` must be the one it was pre-signed with.
+pub fn complete_taproot_key_spend(
+    tx: &mut Transaction,
+    index: usize,
+    adaptor_sig: &crate::adaptor::AdaptorSignature,
+    t: &SecretKey,
+    sighash_type: TapSighashType,
+) -> Result<()> {
+    let input = tx.input.get_mut(index).ok_or_else(|| anyhow!("No input {}", index))?;
+    let sig = adaptor_sig.generate_final_signature(&adaptor_sig.complete(t));
+    input.witness = Witness::from_slice(&[taproot_signature_bytes(sig, sighash_type)]);
+    Ok(())
+}
+
+/// Fee for `tx` at `fee_rate` once its inputs carry witnesses shaped like `witnesses`.
+fn fee_with_witnesses(tx: &Transaction, witnesses: &[Witness], fee_rate: FeeRate) -> Result<Amount> {
+    let mut sized = tx.clone();
+    for (input, witness) in sized.input.iter_mut().zip(witnesses) {
+        input.witness = witness.clone();
+    }
+    fee_rate.fee_vb(sized.vsize() as u64).ok_or_else(|| anyhow!("Fee overflow"))
+}
+
+/// Set output 0 to `total_in` minus the fee and every other output.
+fn pay_remainder_to_first_output(tx: &mut Transaction, total_in: Amount, fee: Amount) -> Result<()> {
+    let others = tx.output.iter().skip(1).map(|o| o.value).sum::<Amount>();
+    let value = total_in
+        .checked_sub(fee + others)
+        .ok_or_else(|| anyhow!("Inputs of {} do not cover fee of {}", total_in, fee))?;
+    if value < tx.output[0].script_pubkey.dust_value() {
+        return Err(anyhow!("Output of {} would be dust after a fee of {}", value, fee));
+    }
+    tx.output[0].value = value;
+    Ok(())
+}
+
+fn swap_spend_skeleton(lock_outpoint: OutPoint, sequence: Sequence, recipient: &Address, anchor: Option<ScriptBuf>) -> Transaction {
+    let mut output = vec![TxOut { value: Amount::ZERO, script_pubkey: recipient.script_pubkey() }];
+    if let Some(script_pubkey) = anchor {
+        output.push(TxOut { value: ANCHOR_VALUE, script_pubkey });
+    }
+    Transaction {
         version: Version(2),
         lock_time: LockTime::ZERO,
         input: vec![TxIn {
-            previous_output: OutPoint { txid: prev_txid, vout: prev_vout },
+            previous_output: lock_outpoint,
             script_sig: ScriptBuf::new(),
-            sequence: Sequence::MAX,
+            sequence,
             witness: Witness::new(),
         }],
-        output: vec![TxOut {
-            value: amount,
-            script_pubkey: recipient.script_pubkey(),
-        }],
-    };
+        output,
+    }
+}
+
+fn claim_skeleton(
+    lock_outpoint: OutPoint,
+    lock_txout: &TxOut,
+    recipient: Address<NetworkUnchecked>,
+    network: Network,
+    fee_rate: FeeRate,
+    anchor: Option<ScriptBuf>,
+    sighash_type: TapSighashType,
+) -> Result<Transaction> {
+    let recipient = require_network(recipient, network)?;
+    let mut tx = swap_spend_skeleton(lock_outpoint, Sequence::ENABLE_RBF_NO_LOCKTIME, &recipient, anchor);
+    let fee = fee_with_witnesses(&tx, &[Witness::from_slice(&[dummy_signature(sighash_type)])], fee_rate)?;
+    pay_remainder_to_first_output(&mut tx, lock_txout.value, fee)?;
+    Ok(tx)
+}
 
-    let prevouts = Prevouts::All(&[prev_txout]);
-    let sighash = SighashCache::new(&tx).taproot_key_spend_signature_hash(0, &prevouts, TapSighashType::Default)?;
+/// Claim the swap output by key path at `fee_rate`. Signals BIP125 replaceability so the
+/// claim can be re-issued at a higher feerate, and optionally adds a CPFP anchor output.
+/// The signature is a plain one and reveals no adaptor secret; see `presign_claim_transaction`.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_claim_transaction(
+    lock_outpoint: OutPoint,
+    lock_txout: &TxOut,
+    swap: &SwapOutput,
+    recipient: Address<NetworkUnchecked>,
+    network: Network,
+    fee_rate: FeeRate,
+    anchor: Option<ScriptBuf>,
+    claim_signer: &impl SchnorrSigner,
+    sighash_type: TapSighashType,
+) -> Result<Transaction> {
+    let mut tx = claim_skeleton(lock_outpoint, lock_txout, recipient, network, fee_rate, anchor, sighash_type)?;
+    sign_taproot_key_spend(&mut tx, 0, std::slice::from_ref(lock_txout), claim_signer, swap.spend_info()?.merkle_root(), sighash_type).await?;
+    validate_transaction(&tx, std::slice::from_ref(lock_txout), MIN_RELAY_FEE)?;
+    Ok(tx)
+}
 
-    let mut rng = OsRng;
-    let sig = secp.sign_schnorr_with_rng(&Message::from(sighash), signer_keypair, &mut rng);
+/// Build the same claim as `create_claim_transaction`, but leave its key-path signature as
+/// an adaptor pre-signature for `adaptor_point`. The claimer hands the pre-signature to the
+/// counterparty; broadcasting the claim completed by `complete_claim_transaction` then
+/// reveals `t` on chain, where `extract_secret_from_claim_tx` recovers it.
+#[allow(clippy::too_many_arguments)]
+pub async fn presign_claim_transaction(
+    lock_outpoint: OutPoint,
+    lock_txout: &TxOut,
+    swap: &SwapOutput,
+    recipient: Address<NetworkUnchecked>,
+    network: Network,
+    fee_rate: FeeRate,
+    anchor: Option<ScriptBuf>,
+    claim_signer: &impl AdaptorSigner,
+    adaptor_point: &secp256k1::PublicKey,
+) -> Result<(Transaction, crate::adaptor::AdaptorSignature)> {
+    let tx = claim_skeleton(lock_outpoint, lock_txout, recipient, network, fee_rate, anchor, TapSighashType::Default)?;
+    let sighash = taproot_sighash(&tx, 0, std::slice::from_ref(lock_txout), None, TapSighashType::Default)?;
+    let merkle_root = swap.spend_info()?.merkle_root();
+    let adaptor_sig = claim_signer.presign(&Message::from(sighash), adaptor_point, SigningKey::Taproot(merkle_root)).await?;
+    Ok((tx, adaptor_sig))
+}
 
-    tx.input[0].witness = Witness::from_slice(&[sig.as_ref()]);
+/// Complete a claim from `presign_claim_transaction` with `t`.
+pub fn complete_claim_transaction(
+    mut tx: Transaction,
+    lock_txout: &TxOut,
+    adaptor_sig: &crate::adaptor::AdaptorSignature,
+    t: &SecretKey,
+) -> Result<Transaction> {
+    complete_taproot_key_spend(&mut tx, 0, adaptor_sig, t, TapSighashType::Default)?;
+    validate_transaction(&tx, std::slice::from_ref(lock_txout), MIN_RELAY_FEE)?;
+    Ok(tx)
+}
 
+/// Refund the swap output to the buyer through the timelocked leaf at `fee_rate`.
+/// The CSV sequence already signals BIP125 replaceability.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_refund_transaction(
+    lock_outpoint: OutPoint,
+    lock_txout: &TxOut,
+    swap: &SwapOutput,
+    recipient: Address<NetworkUnchecked>,
+    network: Network,
+    fee_rate: FeeRate,
+    anchor: Option<ScriptBuf>,
+    buyer_signer: &impl SchnorrSigner,
+    sighash_type: TapSighashType,
+) -> Result<Transaction> {
+    let recipient = require_network(recipient, network)?;
+    let script = swap.refund_script();
+    let control_block = swap.refund_control_block()?;
+    let mut tx = swap_spend_skeleton(lock_outpoint, swap.refund_sequence, &recipient, anchor);
+    let dummy = Witness::from_slice(&[dummy_signature(sighash_type), script.to_bytes(), control_block.serialize()]);
+    let fee = fee_with_witnesses(&tx, &[dummy], fee_rate)?;
+    pay_remainder_to_first_output(&mut tx, lock_txout.value, fee)?;
+    sign_taproot_script_spend(&mut tx, 0, std::slice::from_ref(lock_txout), buyer_signer, &script, &control_block, sighash_type).await?;
+    validate_transaction(&tx, std::slice::from_ref(lock_txout), MIN_RELAY_FEE)?;
     Ok(tx)
 }
 
-/// Create Taproot output tweaking key with commitment
-pub fn create_nostr_signature_lock_script(commitment: [u8; 32], internal_key: XOnlyPublicKey) -> Result<XOnlyPublicKey> {
-    let secp = Secp256k1::new();
-    let tweak = Scalar::from_be_bytes(commitment)?;
-    let (tweaked_key, _) = internal_key.add_tweak(&secp, &tweak)?;
-    Ok(tweaked_key)
-}
\ No newline at end of file
+/// Feerate for a same-size replacement of `original` that satisfies BIP125
+/// (higher absolute fee plus the incremental relay fee), and is at least `target`.
+pub fn replacement_fee_rate(original: &Transaction, original_fee: Amount, target: FeeRate) -> FeeRate {
+    let weight = original.weight();
+    let required = original_fee + INCREMENTAL_RELAY_FEE.fee_wu(weight).unwrap_or(Amount::MAX_MONEY);
+    let required_rate = FeeRate::from_sat_per_kwu((required.to_sat() * 1000).div_ceil(weight.to_wu().max(1)));
+    required_rate.max(target)
+}
+
+/// Check that `replacement` can replace `original` under BIP125: it must pay a higher
+/// feerate (rule 6) and a higher absolute fee plus the incremental relay fee (rules 3 and 4).
+pub fn check_replacement(original: &Transaction, original_fee: Amount, replacement: &Transaction, replacement_fee: Amount) -> Result<()> {
+    if !original.is_explicitly_rbf() {
+        return Err(anyhow!("Transaction {} does not signal replaceability", original.txid()));
+    }
+    let shares_input = replacement.input.iter().any(|r| original.input.iter().any(|o| o.previous_output == r.previous_output));
+    if !shares_input {
+        return Err(anyhow!("Replacement does not conflict with {}", original.txid()));
+    }
+    // Compare fee / vsize by cross-multiplying, so rounding cannot let an equal feerate through.
+    let original_vsize = original.vsize() as u128;
+    let replacement_vsize = replacement.vsize() as u128;
+    if u128::from(replacement_fee.to_sat()) * original_vsize <= u128::from(original_fee.to_sat()) * replacement_vsize {
+        return Err(anyhow!(
+            "Replacement feerate {:.2} sat/vB does not exceed the original's {:.2} sat/vB",
+            replacement_fee.to_sat() as f64 / replacement_vsize as f64,
+            original_fee.to_sat() as f64 / original_vsize as f64
+        ));
+    }
+    let min_fee = original_fee + INCREMENTAL_RELAY_FEE.fee_wu(replacement.weight()).unwrap_or(Amount::MAX_MONEY);
+    if replacement_fee < min_fee {
+        return Err(anyhow!("Replacement fee {} is below the required {}", replacement_fee, min_fee));
+    }
+    Ok(())
+}
+
+/// Child transaction spending `parent`'s anchor output (plus optional wallet UTXOs) so that
+/// parent and child together pay `package_fee_rate`. All inputs must be P2TR key-path
+/// outputs of `signer`'s key; the remainder goes to `change_script`.
+pub async fn create_cpfp_child(
+    parent: &Transaction,
+    parent_fee: Amount,
+    anchor_vout: u32,
+    funding: &[crate::chain::Utxo],
+    signer: &impl SchnorrSigner,
+    change_script: ScriptBuf,
+    package_fee_rate: FeeRate,
+) -> Result<Transaction> {
+    let anchor_txout = parent
+        .output
+        .get(anchor_vout as usize)
+        .ok_or_else(|| anyhow!("Parent has no output {}", anchor_vout))?
+        .clone();
+    let mut inputs = vec![(OutPoint { txid: parent.txid(), vout: anchor_vout }, anchor_txout)];
+    inputs.extend(funding.iter().map(|u| (u.outpoint, u.txout.clone())));
+
+    let mut tx = Transaction {
+        version: Version(2),
+        lock_time: LockTime::ZERO,
+        input: inputs
+            .iter()
+            .map(|(outpoint, _)| TxIn {
+                previous_output: *outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: vec![TxOut { value: Amount::ZERO, script_pubkey: change_script }],
+    };
+    let prevouts: Vec<TxOut> = inputs.into_iter().map(|(_, txout)| txout).collect();
+
+    let dummies = vec![Witness::from_slice(&[[0u8; 64]]); prevouts.len()];
+    let child_fee = fee_with_witnesses(&tx, &dummies, package_fee_rate)?;
+    let parent_target = package_fee_rate.fee_vb(parent.vsize() as u64).ok_or_else(|| anyhow!("Fee overflow"))?;
+    let fee = child_fee + parent_target.checked_sub(parent_fee).unwrap_or(Amount::ZERO);
+    pay_remainder_to_first_output(&mut tx, prevouts.iter().map(|p| p.value).sum(), fee)?;
+
+    for index in 0..prevouts.len() {
+        sign_taproot_key_spend(&mut tx, index, &prevouts, signer, None, TapSighashType::Default).await?;
+    }
+    validate_transaction(&tx, &prevouts, MIN_RELAY_FEE)?;
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    fn tx(inputs: &[OutPoint], sequence: Sequence, outputs: usize) -> Transaction {
+        Transaction {
+            version: Version(2),
+            lock_time: LockTime::ZERO,
+            input: inputs.iter().map(|&previous_output| TxIn { previous_output, sequence, ..Default::default() }).collect(),
+            output: vec![TxOut { value: Amount::from_sat(10_000), script_pubkey: ScriptBuf::new_op_return([0u8; 32]) }; outputs],
+        }
+    }
+
+    fn outpoint(vout: u32) -> OutPoint {
+        OutPoint { txid: Txid::from_byte_array([1; 32]), vout }
+    }
+
+    #[test]
+    fn replacement_needs_signal_conflict_and_fee() {
+        let original = tx(&[outpoint(0)], Sequence::ENABLE_RBF_NO_LOCKTIME, 1);
+        let vsize = original.vsize() as u64;
+        let original_fee = Amount::from_sat(2 * vsize);
+
+        let same_size = tx(&[outpoint(0)], Sequence::ENABLE_RBF_NO_LOCKTIME, 1);
+        let rate = replacement_fee_rate(&original, original_fee, FeeRate::ZERO);
+        let fee = rate.fee_vb(vsize).unwrap();
+        check_replacement(&original, original_fee, &same_size, fee).unwrap();
+        assert!(check_replacement(&original, original_fee, &same_size, fee - Amount::from_sat(1)).is_err());
+
+        let final_original = tx(&[outpoint(0)], Sequence::MAX, 1);
+        assert!(check_replacement(&final_original, original_fee, &same_size, fee).is_err());
+
+        let unrelated = tx(&[outpoint(1)], Sequence::ENABLE_RBF_NO_LOCKTIME, 1);
+        assert!(check_replacement(&original, original_fee, &unrelated, fee).is_err());
+    }
+
+    #[test]
+    fn replacement_must_pay_a_higher_feerate() {
+        let original = tx(&[outpoint(0)], Sequence::ENABLE_RBF_NO_LOCKTIME, 1);
+        let original_fee = Amount::from_sat(10 * original.vsize() as u64);
+
+        // Pays more in total and covers the incremental fee, but at a lower feerate.
+        let larger = tx(&[outpoint(0), outpoint(1)], Sequence::ENABLE_RBF_NO_LOCKTIME, 6);
+        let fee = original_fee + Amount::from_sat(larger.vsize() as u64);
+        assert!(fee.to_sat() * (original.vsize() as u64) < original_fee.to_sat() * larger.vsize() as u64);
+        let err = check_replacement(&original, original_fee, &larger, fee).unwrap_err();
+        assert!(err.to_string().contains("feerate"), "{}", err);
+
+        let fee = Amount::from_sat(11 * larger.vsize() as u64);
+        check_replacement(&original, original_fee, &larger, fee).unwrap();
+    }
+}
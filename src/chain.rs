@@ -0,0 +1,731 @@
+use bitcoin::{relative, Amount, BlockHash, FeeRate, OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid};
+use bitcoin::consensus::encode::{deserialize, serialize_hex};
+use bitcoin_hashes::{sha256, Hash};
+use secp256k1::{Secp256k1, SecretKey};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+
+/// An unspent output owned by a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    pub confirmations: u32,
+}
+
+/// Read and write access to the Bitcoin chain.
+/// Confirmations are `None` for unknown transactions and `Some(0)` while in the mempool.
+pub trait ChainBackend {
+    fn get_utxos(&self, script_pubkey: &Script) -> impl Future<Output = Result<Vec<Utxo>>> + Send;
+    fn broadcast(&self, tx: &Transaction) -> impl Future<Output = Result<Txid>> + Send;
+    fn get_tx(&self, txid: &Txid) -> impl Future<Output = Result<Option<Transaction>>> + Send;
+    fn get_confirmations(&self, txid: &Txid) -> impl Future<Output = Result<Option<u32>>> + Send;
+    /// The transaction spending `outpoint`, if it has been seen in a block or the mempool.
+    fn get_spending_tx(&self, outpoint: &OutPoint) -> impl Future<Output = Result<Option<Transaction>>> + Send;
+    /// Feerate expected to confirm within `target_blocks`.
+    fn estimate_fee_rate(&self, target_blocks: u16) -> impl Future<Output = Result<FeeRate>> + Send;
+}
+
+/// Poll until `txid` has at least `confirmations` confirmations. Returns the final count.
+pub async fn wait_for_confirmations<B: ChainBackend>(backend: &B, txid: &Txid, confirmations: u32, poll_interval: Duration) -> Result<u32> {
+    loop {
+        if let Some(count) = backend.get_confirmations(txid).await? {
+            if count >= confirmations {
+                return Ok(count);
+            }
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+/// Poll until `outpoint` is spent and return the spending transaction.
+pub async fn watch_outpoint_spend<B: ChainBackend>(backend: &B, outpoint: &OutPoint, poll_interval: Duration) -> Result<Transaction> {
+    loop {
+        if let Some(tx) = backend.get_spending_tx(outpoint).await? {
+            return Ok(tx);
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+/// Follow a swap outpoint until it is spent, then extract the adaptor secret `t`
+/// from the claim transaction's taproot witness. Only a claim completing `adaptor_sig`,
+/// as built by `bitcoin_utils::presign_claim_transaction`, reveals `t`.
+pub async fn watch_for_adaptor_secret<B: ChainBackend>(
+    backend: &B,
+    outpoint: &OutPoint,
+    adaptor_sig: &crate::adaptor::AdaptorSignature,
+    poll_interval: Duration,
+) -> Result<SecretKey> {
+    let claim_tx = watch_outpoint_spend(backend, outpoint, poll_interval).await?;
+    crate::bitcoin_utils::extract_secret_from_claim_tx(&Secp256k1::new(), &claim_tx, outpoint, adaptor_sig)
+}
+
+fn tx_from_hex(hex_str: &str) -> Result<Transaction> {
+    Ok(deserialize(&hex::decode(hex_str.trim())?)?)
+}
+
+/// `RPC_INVALID_ADDRESS_OR_KEY`: bitcoind's "No such mempool or blockchain transaction".
+pub const RPC_NO_SUCH_TX: i64 = -5;
+
+/// An error reply from bitcoind, as opposed to a transport or authentication failure.
+#[derive(Debug, Error)]
+#[error("bitcoind {method} failed with code {code}: {message}")]
+pub struct RpcError {
+    pub method: String,
+    pub code: i64,
+    pub message: String,
+}
+
+/// `BitcoindRpc::get_spending_tx` knows `outpoint` is spent but could not find the spend:
+/// without `-txindex` it cannot locate the funding block, so it only scans the last
+/// `blocks` blocks. This is not "unspent"; the spend may be older than the window.
+#[derive(Debug, Error)]
+#[error("{outpoint} is spent or unknown, but no spend is in the last {blocks} blocks; enable -txindex to scan from its funding block")]
+pub struct SpendNotFound {
+    pub outpoint: OutPoint,
+    pub blocks: u32,
+}
+
+fn is_no_such_tx(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<RpcError>().is_some_and(|e| e.code == RPC_NO_SUCH_TX)
+}
+
+/// bitcoind JSON-RPC backend. `get_tx` for confirmed transactions needs `-txindex`, and
+/// `get_spending_tx` needs `gettxspendingprevout` (Bitcoin Core 24+).
+#[derive(Debug, Clone)]
+pub struct BitcoindRpc {
+    url: String,
+    user: String,
+    password: String,
+    client: reqwest::Client,
+    /// How many blocks back from the tip `get_spending_tx` scans when it cannot find the
+    /// block that created the outpoint; a spend outside them is `SpendNotFound`.
+    pub spend_scan_depth: u32,
+    /// Next block height to scan for a spend of each watched outpoint.
+    spend_scan_heights: Arc<Mutex<HashMap<OutPoint, u32>>>,
+}
+
+impl BitcoindRpc {
+    pub fn new(url: &str, user: &str, password: &str) -> Self {
+        BitcoindRpc {
+            url: url.to_string(),
+            user: user.to_string(),
+            password: password.to_string(),
+            client: reqwest::Client::new(),
+            spend_scan_depth: 144,
+            spend_scan_heights: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Call `method`. Error replies come back as `RpcError`; anything else that goes wrong,
+    /// such as a refused connection or bad credentials, as another error.
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let body = json!({ "jsonrpc": "1.0", "id": "tapstr", "method": method, "params": params });
+        let resp = self.client.post(&self.url).basic_auth(&self.user, Some(&self.password)).json(&body).send().await?;
+        let status = resp.status();
+        // bitcoind answers RPC errors with HTTP 404 or 500 and a JSON body.
+        let Ok(resp) = resp.json::<Value>().await else {
+            return Err(anyhow!("bitcoind {} failed with HTTP {}", method, status));
+        };
+        let error = &resp["error"];
+        if !error.is_null() {
+            return Err(RpcError {
+                method: method.to_string(),
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            }
+            .into());
+        }
+        Ok(resp["result"].clone())
+    }
+
+    pub async fn tip_height(&self) -> Result<u32> {
+        let height = self.call("getblockcount", json!([])).await?;
+        height.as_u64().map(|h| h as u32).ok_or_else(|| anyhow!("Invalid block count: {}", height))
+    }
+
+    pub async fn block_hash(&self, height: u32) -> Result<BlockHash> {
+        let hash = self.call("getblockhash", json!([height])).await?;
+        Ok(BlockHash::from_str(hash.as_str().ok_or_else(|| anyhow!("Invalid block hash: {}", hash))?)?)
+    }
+
+    /// Height of the block that created `outpoint`, if bitcoind can tell.
+    async fn funding_height(&self, outpoint: &OutPoint) -> Result<Option<u32>> {
+        let tx = match self.call("getrawtransaction", json!([outpoint.txid.to_string(), true])).await {
+            Ok(tx) => tx,
+            Err(e) if is_no_such_tx(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let Some(block_hash) = tx["blockhash"].as_str() else {
+            return Ok(None);
+        };
+        let header = self.call("getblockheader", json!([block_hash])).await?;
+        Ok(header["height"].as_u64().map(|h| h as u32))
+    }
+
+    fn find_spend(txs: &[Value], outpoint: &OutPoint) -> Result<Option<Transaction>> {
+        for tx in txs {
+            let spends = tx["vin"].as_array().is_some_and(|vin| {
+                vin.iter().any(|input| input["txid"].as_str() == Some(&outpoint.txid.to_string()) && input["vout"].as_u64() == Some(outpoint.vout as u64))
+            });
+            if spends {
+                return tx["hex"].as_str().map(tx_from_hex).transpose();
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl ChainBackend for BitcoindRpc {
+    async fn get_utxos(&self, script_pubkey: &Script) -> Result<Vec<Utxo>> {
+        let descriptor = format!("raw({})", script_pubkey.to_hex_string());
+        let result = self.call("scantxoutset", json!(["start", [descriptor]])).await?;
+        let tip = self.tip_height().await?;
+        let unspents = result["unspents"].as_array().cloned().unwrap_or_default();
+        unspents
+            .iter()
+            .map(|u| {
+                let txid = Txid::from_str(u["txid"].as_str().unwrap_or_default())?;
+                let vout = u["vout"].as_u64().ok_or_else(|| anyhow!("Missing vout"))? as u32;
+                let value = Amount::from_btc(u["amount"].as_f64().ok_or_else(|| anyhow!("Missing amount"))?)?;
+                let height = u["height"].as_u64().unwrap_or(0) as u32;
+                Ok(Utxo {
+                    outpoint: OutPoint { txid, vout },
+                    txout: TxOut { value, script_pubkey: script_pubkey.to_owned() },
+                    confirmations: if height == 0 { 0 } else { tip.saturating_sub(height) + 1 },
+                })
+            })
+            .collect()
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let txid = self.call("sendrawtransaction", json!([serialize_hex(tx)])).await?;
+        Ok(Txid::from_str(txid.as_str().ok_or_else(|| anyhow!("Invalid txid: {}", txid))?)?)
+    }
+
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>> {
+        match self.call("getrawtransaction", json!([txid.to_string()])).await {
+            Ok(hex_str) => Ok(Some(tx_from_hex(hex_str.as_str().unwrap_or_default())?)),
+            Err(e) if is_no_such_tx(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_confirmations(&self, txid: &Txid) -> Result<Option<u32>> {
+        match self.call("getrawtransaction", json!([txid.to_string(), true])).await {
+            Ok(tx) => Ok(Some(tx["confirmations"].as_u64().unwrap_or(0) as u32)),
+            Err(e) if is_no_such_tx(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Looks in the mempool with `gettxspendingprevout`, then scans each block once: from
+    /// the outpoint's funding block on the first call, and only blocks mined since on later
+    /// calls. Without `-txindex` the funding block is unknown for confirmed transactions, so
+    /// only the last `spend_scan_depth` blocks are scanned, and a spend not among them is a
+    /// `SpendNotFound` error rather than `None`.
+    async fn get_spending_tx(&self, outpoint: &OutPoint) -> Result<Option<Transaction>> {
+        // Still unspent (including mempool spends)?
+        let txout = self.call("gettxout", json!([outpoint.txid.to_string(), outpoint.vout, true])).await?;
+        if !txout.is_null() {
+            return Ok(None);
+        }
+
+        let prevout = json!([{ "txid": outpoint.txid.to_string(), "vout": outpoint.vout }]);
+        let spending = self.call("gettxspendingprevout", json!([prevout])).await?;
+        if let Some(txid) = spending[0]["spendingtxid"].as_str() {
+            if let Some(tx) = self.get_tx(&Txid::from_str(txid)?).await? {
+                return Ok(Some(tx));
+            }
+        }
+
+        let tip = self.tip_height().await?;
+        let remembered = self.spend_scan_heights.lock().unwrap().get(outpoint).copied();
+        let (start, windowed) = match remembered {
+            Some(height) => (height, false),
+            None => match self.funding_height(outpoint).await? {
+                Some(height) => (height, false),
+                None => (tip.saturating_sub(self.spend_scan_depth), true),
+            },
+        };
+        for height in start..=tip {
+            let hash = self.call("getblockhash", json!([height])).await?;
+            let block = self.call("getblock", json!([hash, 2])).await?;
+            let txs = block["tx"].as_array().cloned().unwrap_or_default();
+            if let Some(spend) = Self::find_spend(&txs, outpoint)? {
+                self.spend_scan_heights.lock().unwrap().remove(outpoint);
+                return Ok(Some(spend));
+            }
+            if !windowed {
+                self.spend_scan_heights.lock().unwrap().insert(*outpoint, height + 1);
+            }
+        }
+        if windowed {
+            return Err(SpendNotFound { outpoint: *outpoint, blocks: tip - start + 1 }.into());
+        }
+        Ok(None)
+    }
+
+    async fn estimate_fee_rate(&self, target_blocks: u16) -> Result<FeeRate> {
+        let estimate = self.call("estimatesmartfee", json!([target_blocks])).await?;
+        // Regtest and fresh nodes have no fee history; fall back to the relay minimum.
+        let Some(btc_per_kvb) = estimate["feerate"].as_f64() else {
+            return Ok(FeeRate::BROADCAST_MIN);
+        };
+        let sat_per_kvb = Amount::from_btc(btc_per_kvb)?.to_sat();
+        Ok(FeeRate::from_sat_per_kwu(sat_per_kvb / 4))
+    }
+}
+
+/// Esplora REST backend, e.g. `https://blockstream.info/testnet/api`.
+#[derive(Debug, Clone)]
+pub struct EsploraClient {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl EsploraClient {
+    pub fn new(base_url: &str) -> Self {
+        EsploraClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<Option<reqwest::Response>> {
+        let resp = self.client.get(format!("{}{}", self.base_url, path)).send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?))
+    }
+
+    async fn get_json(&self, path: &str) -> Result<Option<Value>> {
+        match self.get(path).await? {
+            Some(resp) => Ok(Some(resp.json().await?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn tip_height(&self) -> Result<u32> {
+        let resp = self.get("/blocks/tip/height").await?.ok_or_else(|| anyhow!("No chain tip"))?;
+        Ok(resp.text().await?.trim().parse()?)
+    }
+
+    pub async fn block_hash(&self, height: u32) -> Result<BlockHash> {
+        let resp = self.get(&format!("/block-height/{}", height)).await?.ok_or_else(|| anyhow!("No block at height {}", height))?;
+        Ok(BlockHash::from_str(resp.text().await?.trim())?)
+    }
+}
+
+impl ChainBackend for EsploraClient {
+    async fn get_utxos(&self, script_pubkey: &Script) -> Result<Vec<Utxo>> {
+        // Esplora indexes scripts by their reversed SHA256, like Electrum.
+        let mut script_hash = sha256::Hash::hash(script_pubkey.as_bytes()).to_byte_array();
+        script_hash.reverse();
+        let utxos = self.get_json(&format!("/scripthash/{}/utxo", hex::encode(script_hash))).await?.unwrap_or_default();
+        let tip = self.tip_height().await?;
+        utxos
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .map(|u| {
+                let txid = Txid::from_str(u["txid"].as_str().unwrap_or_default())?;
+                let vout = u["vout"].as_u64().ok_or_else(|| anyhow!("Missing vout"))? as u32;
+                let value = Amount::from_sat(u["value"].as_u64().ok_or_else(|| anyhow!("Missing value"))?);
+                let confirmations = match u["status"]["block_height"].as_u64() {
+                    Some(height) => tip.saturating_sub(height as u32) + 1,
+                    None => 0,
+                };
+                Ok(Utxo {
+                    outpoint: OutPoint { txid, vout },
+                    txout: TxOut { value, script_pubkey: script_pubkey.to_owned() },
+                    confirmations,
+                })
+            })
+            .collect()
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let resp = self.client.post(format!("{}/tx", self.base_url)).body(serialize_hex(tx)).send().await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("Esplora rejected transaction: {}", resp.text().await?));
+        }
+        Ok(Txid::from_str(resp.text().await?.trim())?)
+    }
+
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>> {
+        match self.get(&format!("/tx/{}/hex", txid)).await? {
+            Some(resp) => Ok(Some(tx_from_hex(&resp.text().await?)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_confirmations(&self, txid: &Txid) -> Result<Option<u32>> {
+        let Some(status) = self.get_json(&format!("/tx/{}/status", txid)).await? else {
+            return Ok(None);
+        };
+        match status["block_height"].as_u64() {
+            Some(height) => Ok(Some(self.tip_height().await?.saturating_sub(height as u32) + 1)),
+            None => Ok(Some(0)),
+        }
+    }
+
+    async fn get_spending_tx(&self, outpoint: &OutPoint) -> Result<Option<Transaction>> {
+        let Some(outspend) = self.get_json(&format!("/tx/{}/outspend/{}", outpoint.txid, outpoint.vout)).await? else {
+            return Ok(None);
+        };
+        match outspend["txid"].as_str() {
+            Some(txid) if outspend["spent"].as_bool() == Some(true) => self.get_tx(&Txid::from_str(txid)?).await,
+            _ => Ok(None),
+        }
+    }
+
+    async fn estimate_fee_rate(&self, target_blocks: u16) -> Result<FeeRate> {
+        let estimates = self.get_json("/fee-estimates").await?.unwrap_or_default();
+        let estimates = estimates.as_object().cloned().unwrap_or_default();
+        // Use the closest estimate that confirms no later than the target.
+        let sat_per_vb = estimates
+            .iter()
+            .filter_map(|(target, rate)| Some((target.parse::<u16>().ok()?, rate.as_f64()?)))
+            .filter(|(target, _)| *target <= target_blocks)
+            .max_by_key(|(target, _)| *target)
+            .map(|(_, rate)| rate);
+        match sat_per_vb {
+            Some(rate) => Ok(FeeRate::from_sat_per_kwu((rate * 250.0).ceil() as u64)),
+            None => Ok(FeeRate::BROADCAST_MIN),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockChainState {
+    txs: HashMap<Txid, (Transaction, Option<u32>)>,
+    height: u32,
+    fee_rate: Option<FeeRate>,
+}
+
+/// In-memory chain for tests and demos. Broadcast transactions sit in the
+/// mempool until `mine` is called. Relative height timelocks are enforced.
+#[derive(Debug, Clone, Default)]
+pub struct MockChain {
+    state: Arc<Mutex<MockChainState>>,
+}
+
+impl MockChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a confirmed coinbase-like output paying `amount` to `script_pubkey`.
+    pub fn fund(&self, script_pubkey: ScriptBuf, amount: Amount) -> OutPoint {
+        let mut state = self.state.lock().unwrap();
+        let tx = Transaction {
+            version: bitcoin::transaction::Version(2),
+            // Vary the lock time so repeated fundings get distinct txids.
+            lock_time: bitcoin::absolute::LockTime::from_consensus(state.txs.len() as u32),
+            input: vec![],
+            output: vec![TxOut { value: amount, script_pubkey }],
+        };
+        let txid = tx.txid();
+        state.height += 1;
+        let height = state.height;
+        state.txs.insert(txid, (tx, Some(height)));
+        OutPoint { txid, vout: 0 }
+    }
+
+    /// Mine `blocks` blocks, confirming everything in the mempool.
+    pub fn mine(&self, blocks: u32) {
+        let mut state = self.state.lock().unwrap();
+        if blocks == 0 {
+            return;
+        }
+        let next = state.height + 1;
+        for (_, height) in state.txs.values_mut() {
+            if height.is_none() {
+                *height = Some(next);
+            }
+        }
+        state.height += blocks;
+    }
+
+    pub fn height(&self) -> u32 {
+        self.state.lock().unwrap().height
+    }
+
+    pub fn set_fee_rate(&self, fee_rate: FeeRate) {
+        self.state.lock().unwrap().fee_rate = Some(fee_rate);
+    }
+
+    fn spender(state: &MockChainState, outpoint: &OutPoint) -> Option<Transaction> {
+        state.txs.values().map(|(tx, _)| tx).find(|tx| tx.input.iter().any(|i| i.previous_output == *outpoint)).cloned()
+    }
+}
+
+impl ChainBackend for MockChain {
+    async fn get_utxos(&self, script_pubkey: &Script) -> Result<Vec<Utxo>> {
+        let state = self.state.lock().unwrap();
+        let mut utxos = Vec::new();
+        for (txid, (tx, height)) in &state.txs {
+            for (vout, txout) in tx.output.iter().enumerate() {
+                let outpoint = OutPoint { txid: *txid, vout: vout as u32 };
+                if txout.script_pubkey.as_script() == script_pubkey && Self::spender(&state, &outpoint).is_none() {
+                    let confirmations = height.map_or(0, |h| state.height - h + 1);
+                    utxos.push(Utxo { outpoint, txout: txout.clone(), confirmations });
+                }
+            }
+        }
+        Ok(utxos)
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let mut state = self.state.lock().unwrap();
+        for input in &tx.input {
+            let prev = state.txs.get(&input.previous_output.txid).ok_or_else(|| anyhow!("Missing input {}", input.previous_output))?;
+            if input.previous_output.vout as usize >= prev.0.output.len() {
+                return Err(anyhow!("Missing input {}", input.previous_output));
+            }
+            if Self::spender(&state, &input.previous_output).is_some() {
+                return Err(anyhow!("Input {} already spent", input.previous_output));
+            }
+            // BIP68: a height-locked input needs that many confirmations to enter the next block.
+            if let (true, Some(relative::LockTime::Blocks(blocks))) = (tx.version.0 >= 2, input.sequence.to_relative_lock_time()) {
+                let confirmations = prev.1.map_or(0, |h| state.height - h + 1);
+                if confirmations < u32::from(blocks.value()) {
+                    return Err(anyhow!(
+                        "Input {} is timelocked for {} block(s) but has {} confirmation(s)",
+                        input.previous_output,
+                        blocks.value(),
+                        confirmations
+                    ));
+                }
+            }
+        }
+        let txid = tx.txid();
+        state.txs.insert(txid, (tx.clone(), None));
+        Ok(txid)
+    }
+
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>> {
+        Ok(self.state.lock().unwrap().txs.get(txid).map(|(tx, _)| tx.clone()))
+    }
+
+    async fn get_confirmations(&self, txid: &Txid) -> Result<Option<u32>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.txs.get(txid).map(|(_, height)| height.map_or(0, |h| state.height - h + 1)))
+    }
+
+    async fn get_spending_tx(&self, outpoint: &OutPoint) -> Result<Option<Transaction>> {
+        Ok(Self::spender(&self.state.lock().unwrap(), outpoint))
+    }
+
+    async fn estimate_fee_rate(&self, _target_blocks: u16) -> Result<FeeRate> {
+        Ok(self.state.lock().unwrap().fee_rate.unwrap_or(FeeRate::BROADCAST_MIN))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+
+    type Handler = dyn Fn(&str, &Value) -> std::result::Result<Value, i64> + Send + Sync;
+
+    #[derive(Clone)]
+    struct Node {
+        handler: Arc<Handler>,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    /// A stand-in bitcoind answering JSON-RPC with `handler`; `Err(code)` is an RPC error.
+    async fn node(handler: impl Fn(&str, &Value) -> std::result::Result<Value, i64> + Send + Sync + 'static) -> (BitcoindRpc, Arc<Mutex<Vec<String>>>) {
+        let node = Node { handler: Arc::new(handler), calls: Arc::default() };
+        let calls = node.calls.clone();
+        let app = Router::new()
+            .route(
+                "/",
+                post(|State(node): State<Node>, Json(body): Json<Value>| async move {
+                    let method = body["method"].as_str().unwrap_or_default().to_string();
+                    node.calls.lock().unwrap().push(method.clone());
+                    match (node.handler)(&method, &body["params"]) {
+                        Ok(result) => (StatusCode::OK, Json(json!({ "result": result, "error": null, "id": "tapstr" }))),
+                        Err(code) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "result": null, "error": { "code": code, "message": "error" }, "id": "tapstr" }))),
+                    }
+                }),
+            )
+            .with_state(node);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (BitcoindRpc::new(&url, "user", "password"), calls)
+    }
+
+    fn spend_of(outpoint: OutPoint) -> Transaction {
+        Transaction {
+            version: bitcoin::transaction::Version(2),
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn { previous_output: outpoint, ..Default::default() }],
+            output: vec![TxOut { value: Amount::from_sat(1_000), script_pubkey: ScriptBuf::new() }],
+        }
+    }
+
+    fn verbose(tx: &Transaction) -> Value {
+        let vin: Vec<Value> = tx.input.iter().map(|i| json!({ "txid": i.previous_output.txid.to_string(), "vout": i.previous_output.vout })).collect();
+        json!({ "txid": tx.txid().to_string(), "vin": vin, "hex": serialize_hex(tx) })
+    }
+
+    #[tokio::test]
+    async fn only_missing_transactions_are_none() {
+        let txid = Txid::all_zeros();
+        let (missing, _) = node(|_, _| Err(RPC_NO_SUCH_TX)).await;
+        assert!(missing.get_tx(&txid).await.unwrap().is_none());
+        assert!(missing.get_confirmations(&txid).await.unwrap().is_none());
+
+        // -28: still loading the block index.
+        let (warming_up, _) = node(|_, _| Err(-28)).await;
+        let err = warming_up.get_confirmations(&txid).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<RpcError>().map(|e| e.code), Some(-28));
+        assert!(wait_for_confirmations(&warming_up, &txid, 1, Duration::from_millis(1)).await.is_err());
+
+        let unreachable = BitcoindRpc::new("http://127.0.0.1:1/", "user", "password");
+        assert!(unreachable.get_tx(&txid).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unparseable_replies_are_errors() {
+        let app = Router::new().route("/", post(|| async { StatusCode::UNAUTHORIZED }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let err = BitcoindRpc::new(&url, "user", "wrong").get_tx(&Txid::all_zeros()).await.unwrap_err();
+        assert!(err.to_string().contains("401"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn mempool_spend_is_found_without_scanning_blocks() {
+        let outpoint = OutPoint { txid: Txid::all_zeros(), vout: 1 };
+        let spend = spend_of(outpoint);
+        let spend_hex = serialize_hex(&spend);
+        let spend_txid = spend.txid().to_string();
+        let (rpc, calls) = node(move |method, _| match method {
+            "gettxout" => Ok(Value::Null),
+            "gettxspendingprevout" => Ok(json!([{ "txid": Txid::all_zeros().to_string(), "vout": 1, "spendingtxid": spend_txid }])),
+            "getrawtransaction" => Ok(json!(spend_hex)),
+            _ => Err(-32601),
+        })
+        .await;
+        assert_eq!(rpc.get_spending_tx(&outpoint).await.unwrap().map(|tx| tx.txid()), Some(spend.txid()));
+        assert!(!calls.lock().unwrap().iter().any(|m| m == "getblock" || m == "getrawmempool"));
+    }
+
+    #[tokio::test]
+    async fn blocks_are_scanned_once_from_the_funding_block() {
+        let outpoint = OutPoint { txid: Txid::all_zeros(), vout: 0 };
+        let spend = spend_of(outpoint);
+        let tip = Arc::new(Mutex::new(110u64));
+        let node_tip = tip.clone();
+        let spend_json = verbose(&spend);
+        let (rpc, calls) = node(move |method, params| {
+            let tip = *node_tip.lock().unwrap();
+            match method {
+                "gettxout" => Ok(Value::Null),
+                "gettxspendingprevout" => Ok(json!([{ "txid": Txid::all_zeros().to_string(), "vout": 0 }])),
+                "getrawtransaction" => Ok(json!({ "blockhash": "funding" })),
+                "getblockheader" => Ok(json!({ "height": 100 })),
+                "getblockcount" => Ok(json!(tip)),
+                "getblockhash" => Ok(params[0].clone()),
+                "getblock" if params[0] == json!(112) => Ok(json!({ "tx": [spend_json] })),
+                "getblock" => Ok(json!({ "tx": [] })),
+                _ => Err(-32601),
+            }
+        })
+        .await;
+        let scanned = |calls: &Mutex<Vec<String>>| calls.lock().unwrap().iter().filter(|m| *m == "getblock").count();
+
+        assert!(rpc.get_spending_tx(&outpoint).await.unwrap().is_none());
+        assert_eq!(scanned(&calls), 11);
+
+        *tip.lock().unwrap() = 112;
+        assert_eq!(rpc.get_spending_tx(&outpoint).await.unwrap().map(|tx| tx.txid()), Some(spend.txid()));
+        assert_eq!(scanned(&calls), 13);
+    }
+
+    #[tokio::test]
+    async fn spend_outside_the_window_is_not_reported_unspent() {
+        let outpoint = OutPoint { txid: Txid::all_zeros(), vout: 0 };
+        let (mut rpc, calls) = node(move |method, params| match method {
+            "gettxout" => Ok(Value::Null),
+            "gettxspendingprevout" => Ok(json!([{ "txid": Txid::all_zeros().to_string(), "vout": 0 }])),
+            // No -txindex: the confirmed funding transaction cannot be looked up.
+            "getrawtransaction" => Err(RPC_NO_SUCH_TX),
+            "getblockcount" => Ok(json!(500)),
+            "getblockhash" => Ok(params[0].clone()),
+            "getblock" => Ok(json!({ "tx": [] })),
+            _ => Err(-32601),
+        })
+        .await;
+        rpc.spend_scan_depth = 9;
+
+        let err = rpc.get_spending_tx(&outpoint).await.unwrap_err();
+        let not_found = err.downcast_ref::<SpendNotFound>().unwrap();
+        assert_eq!((not_found.outpoint, not_found.blocks), (outpoint, 10));
+        assert_eq!(calls.lock().unwrap().iter().filter(|m| *m == "getblock").count(), 10);
+        assert!(watch_outpoint_spend(&rpc, &outpoint, Duration::from_millis(1)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn mock_chain_enforces_relative_timelocks() {
+        let chain = MockChain::new();
+        let funding = chain.fund(ScriptBuf::new(), Amount::from_sat(10_000));
+        let mut spend = spend_of(funding);
+        spend.input[0].sequence = bitcoin::Sequence::from_height(3);
+
+        assert!(chain.broadcast(&spend).await.is_err());
+        chain.mine(2);
+        let txid = chain.broadcast(&spend).await.unwrap();
+        assert_eq!(chain.get_confirmations(&txid).await.unwrap(), Some(0));
+        assert_eq!(chain.get_spending_tx(&funding).await.unwrap().map(|tx| tx.txid()), Some(txid));
+        assert!(chain.broadcast(&spend).await.is_err());
+        chain.mine(1);
+        assert_eq!(wait_for_confirmations(&chain, &txid, 1, Duration::from_millis(1)).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn watcher_extracts_t_from_an_adaptor_claim() {
+        use crate::bitcoin_utils::{complete_claim_transaction, create_claim_transaction, presign_claim_transaction, SwapOutput};
+        use bitcoin::{Address, Network, TapSighashType};
+        use secp256k1::Keypair;
+
+        let secp = Secp256k1::new();
+        let chain = MockChain::new();
+        let (claim, buyer) = (Keypair::new(&secp, &mut rand::rngs::OsRng), Keypair::new(&secp, &mut rand::rngs::OsRng));
+        let t = SecretKey::new(&mut rand::rngs::OsRng);
+        let swap = SwapOutput::new(claim.x_only_public_key().0, buyer.x_only_public_key().0, 6).unwrap();
+        let lock_txout = TxOut { value: Amount::from_sat(50_000), script_pubkey: swap.script_pubkey().unwrap() };
+        let lock = chain.fund(lock_txout.script_pubkey.clone(), lock_txout.value);
+        let recipient = Address::p2tr(&secp, claim.x_only_public_key().0, None, Network::Regtest).as_unchecked().clone();
+        let fee_rate = FeeRate::from_sat_per_vb(2).unwrap();
+
+        let plain = create_claim_transaction(lock, &lock_txout, &swap, recipient.clone(), Network::Regtest, fee_rate, None, &claim, TapSighashType::Default).await.unwrap();
+        let (unsigned, adaptor_sig) = presign_claim_transaction(lock, &lock_txout, &swap, recipient, Network::Regtest, fee_rate, None, &claim, &t.public_key(&secp)).await.unwrap();
+        assert!(adaptor_sig.verify(&secp));
+        assert!(crate::bitcoin_utils::extract_secret_from_claim_tx(&secp, &plain, &lock, &adaptor_sig).is_err());
+
+        let claim_tx = complete_claim_transaction(unsigned, &lock_txout, &adaptor_sig, &t).unwrap();
+        let sighash = crate::bitcoin_utils::taproot_sighash(&claim_tx, 0, std::slice::from_ref(&lock_txout), None, TapSighashType::Default).unwrap();
+        let signature = bitcoin::secp256k1::schnorr::Signature::from_slice(&claim_tx.input[0].witness.nth(0).unwrap()[..64]).unwrap();
+        let output_key = swap.spend_info().unwrap().output_key().to_inner();
+        secp.verify_schnorr(&signature, &secp256k1::Message::from(sighash), &output_key).unwrap();
+
+        chain.broadcast(&claim_tx).await.unwrap();
+        assert_eq!(watch_for_adaptor_secret(&chain, &lock, &adaptor_sig, Duration::from_millis(1)).await.unwrap(), t);
+    }
+}
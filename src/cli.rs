@@ -0,0 +1,1032 @@
+use anyhow::{anyhow, Context, Result};
+use bitcoin::{
+    bip32::DerivationPath, consensus::encode::serialize_hex, Address, Amount, FeeRate, Network, OutPoint, Psbt, ScriptBuf, TapSighashType, TxOut,
+};
+use clap::{Args, Parser, Subcommand};
+use nostr::{Event, Kind, Timestamp, UnsignedEvent};
+use secp256k1::{hashes::{sha256, Hash}, Keypair, Secp256k1, SecretKey, XOnlyPublicKey};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use ::tapstr::adaptor::AdaptorSignature;
+use ::tapstr::api::{self, AcceptOfferRequest, AcceptOfferResponse, CreateOfferRequest, PreSignature, PreSignatureResponse, SubmitLockRequest};
+use ::tapstr::attestation::{self, Attestation, Claim};
+use ::tapstr::bitcoin_utils::{self, SwapOutput};
+use ::tapstr::chain::MockChain;
+use ::tapstr::content::EncryptedContent;
+use ::tapstr::keys::KeyManager;
+use ::tapstr::keystore::{Keystore, KeystoreContents, ScryptParams, SwapSecrets};
+use ::tapstr::nostr_utils::{self, RelayClient, WebSocketTransport};
+use ::tapstr::remote_signer::{Bunker, BunkerUri, RemoteSigner};
+use ::tapstr::signer::AdaptorSigner;
+use ::tapstr::spend::{InputSigner, SpendBuilder, SpendInput};
+use crate::config::ServeArgs;
+
+/// How long to wait for a bunker to answer.
+const BUNKER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Atomic swaps of Bitcoin for Nostr event signatures.
+///
+/// Files holding protocol messages, keys and PSBTs may be given as `-` to read stdin.
+/// Output goes to stdout unless `--out` is set.
+#[derive(Parser)]
+#[command(name = "tapstr", version)]
+pub struct Cli {
+    /// TOML server config. Its network also applies to the other subcommands.
+    #[arg(long, global = true, env = "TAPSTR_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Bitcoin network for addresses, parameters and validation [default: regtest].
+    #[arg(long, global = true, env = "TAPSTR_NETWORK")]
+    pub network: Option<Network>,
+    /// Server settings when no subcommand is given.
+    #[command(flatten)]
+    pub serve: ServeArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the web demo and JSON API (the default).
+    Serve(ServeArgs),
+    /// Generate a BIP39 mnemonic to derive keys from with `keygen --mnemonic`.
+    Mnemonic(OutArgs),
+    /// Generate or derive a secret key: a party key or the seller's adaptor secret `t`.
+    Keygen(KeygenArgs),
+    /// Create or accept an offer.
+    #[command(subcommand)]
+    Offer(OfferCommand),
+    /// Build the buyer's lock transaction.
+    #[command(subcommand)]
+    Lock(LockCommand),
+    /// Create or verify an adaptor pre-signature.
+    #[command(subcommand)]
+    Presig(PresigCommand),
+    /// Seller: spend the swap output through the key path.
+    Claim(ClaimArgs),
+    /// Buyer: spend the swap output through the timelocked refund leaf.
+    Refund(RefundArgs),
+    /// Buyer: recover `t` from the completed event and the pre-signature.
+    ExtractSecret(ExtractSecretArgs),
+    /// Buyer: decrypt the content sold with an offer.
+    #[command(subcommand)]
+    Content(ContentCommand),
+    /// Buyer: check a NIP-58 badge award sold through a swap.
+    #[command(subcommand)]
+    Badge(BadgeCommand),
+    /// Buyer: check an attestation sold through a swap and prove it was paid for.
+    #[command(subcommand)]
+    Attestation(AttestationCommand),
+    /// Complete and publish the seller's event.
+    #[command(subcommand)]
+    Event(EventCommand),
+    /// Run a stand-in NIP-46 remote signer for a seller key.
+    Bunker(BunkerArgs),
+    /// Manage an encrypted keystore of identity keys and per-swap secrets.
+    #[command(subcommand)]
+    Keystore(KeystoreCommand),
+}
+
+// Parsed once per run, so boxing the larger variant buys nothing.
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+pub enum OfferCommand {
+    /// Seller: draft the event, pre-sign it with `t` and write the offer.
+    Create(OfferCreateArgs),
+    /// Buyer: check an offer and fix the swap output for our refund key.
+    Accept(OfferAcceptArgs),
+}
+
+#[derive(Subcommand)]
+pub enum LockCommand {
+    /// Buyer: fund the swap address from P2TR key-path UTXOs.
+    Build(LockBuildArgs),
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+pub enum PresigCommand {
+    /// Pre-sign an event id with the seller key and adaptor secret `t`.
+    Create(PresigCreateArgs),
+    /// Check a pre-signature against its event.
+    Verify(PresigVerifyArgs),
+}
+
+#[derive(Subcommand)]
+pub enum ContentCommand {
+    /// Before locking: check that `t` will recover the content key.
+    Verify(ContentVerifyArgs),
+    /// Decrypt with `t` and check the result against the offer's commitment.
+    Decrypt(ContentDecryptArgs),
+}
+
+#[derive(Subcommand)]
+pub enum BadgeCommand {
+    /// Check the pre-signed or completed award is of the badge and names the buyer.
+    Verify(BadgeVerifyArgs),
+}
+
+#[derive(Subcommand)]
+pub enum AttestationCommand {
+    /// Check the offered attestation, or recover `t` from the completed one as proof of payment.
+    Verify(AttestationVerifyArgs),
+}
+
+#[derive(Subcommand)]
+pub enum EventCommand {
+    /// Seller: complete the pre-signed event with `t` and send it to relays.
+    Publish(EventPublishArgs),
+}
+
+#[derive(Args)]
+pub struct BunkerArgs {
+    /// Seller key file to sign with.
+    #[arg(long)]
+    key: PathBuf,
+    /// Relay URL to serve requests on. Repeatable.
+    #[arg(long = "relay", required = true)]
+    relays: Vec<String>,
+    /// Token clients must present on `connect`. Random unless given, and printed in the URI.
+    #[arg(long, env = "TAPSTR_BUNKER_SECRET", hide_env_values = true)]
+    secret: Option<String>,
+    /// Client key allowed to connect without the secret. Repeatable; with it, no secret is generated.
+    #[arg(long = "allow")]
+    allowed: Vec<XOnlyPublicKey>,
+}
+
+#[derive(Subcommand)]
+pub enum KeystoreCommand {
+    /// Create an empty keystore, optionally holding a mnemonic.
+    Create {
+        #[command(flatten)]
+        keystore: KeystoreArgs,
+        /// BIP39 mnemonic file to store.
+        #[arg(long)]
+        mnemonic: Option<PathBuf>,
+    },
+    /// Store a key file as a named identity.
+    Import {
+        #[command(flatten)]
+        keystore: KeystoreArgs,
+        #[arg(long)]
+        name: String,
+        /// Key file from `keygen`.
+        #[arg(long)]
+        key: PathBuf,
+    },
+    /// Write a named identity out as a key file.
+    Export {
+        #[command(flatten)]
+        keystore: KeystoreArgs,
+        #[arg(long)]
+        name: String,
+        #[command(flatten)]
+        out: OutArgs,
+    },
+    /// List stored identities and swaps, without their secrets.
+    List {
+        #[command(flatten)]
+        keystore: KeystoreArgs,
+    },
+    /// Re-encrypt under the passphrase in `TAPSTR_KEYSTORE_NEW_PASSPHRASE`.
+    Passwd {
+        #[command(flatten)]
+        keystore: KeystoreArgs,
+        #[arg(long, env = "TAPSTR_KEYSTORE_NEW_PASSPHRASE", hide_env_values = true)]
+        new_passphrase: String,
+    },
+}
+
+/// The keystore file and its passphrase. The passphrase is best set in the environment.
+#[derive(Args)]
+pub struct KeystoreArgs {
+    #[arg(long, env = "TAPSTR_KEYSTORE")]
+    keystore: PathBuf,
+    #[arg(long, env = "TAPSTR_KEYSTORE_PASSPHRASE", hide_env_values = true)]
+    passphrase: String,
+}
+
+#[derive(Args)]
+pub struct OutArgs {
+    /// Write here instead of stdout.
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct KeygenArgs {
+    /// File with the BIP39 mnemonic to derive from; without it the key is random.
+    #[arg(long, requires = "key_path")]
+    mnemonic: Option<PathBuf>,
+    /// BIP39 passphrase of the mnemonic.
+    #[arg(long, env = "TAPSTR_PASSPHRASE", hide_env_values = true, requires = "mnemonic")]
+    passphrase: Option<String>,
+    /// Account level of the derivation path.
+    #[arg(long, default_value_t = 0, requires = "mnemonic")]
+    account: u32,
+    #[command(flatten)]
+    key_path: KeyPathArgs,
+    /// With `--swap`: the adaptor secret `t` instead of the Bitcoin key.
+    #[arg(long)]
+    adaptor: bool,
+    #[command(flatten)]
+    out: OutArgs,
+}
+
+/// Which key to derive from the mnemonic.
+#[derive(Args)]
+#[group(id = "key_path", multiple = false, requires = "mnemonic")]
+pub struct KeyPathArgs {
+    /// NIP-06 Nostr key, m/44'/1237'/account'/0/0.
+    #[arg(long)]
+    nostr: bool,
+    /// BIP86 wallet key at this index of the external chain.
+    #[arg(long, value_name = "INDEX")]
+    wallet: Option<u32>,
+    /// Key of the swap with this id (the offer event id).
+    #[arg(long, value_name = "SWAP_ID")]
+    swap: Option<String>,
+    /// Any other BIP32 path, e.g. m/86'/1'/0'/1/0.
+    #[arg(long)]
+    path: Option<DerivationPath>,
+}
+
+/// Where the seller's Nostr key is: a key file, or a NIP-46 bunker that pre-signs for us.
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct SellerKeyArgs {
+    /// Seller key file.
+    #[arg(long)]
+    key: Option<PathBuf>,
+    /// `bunker://` URI of a remote signer holding the seller key, e.g. from `tapstr bunker`.
+    #[arg(long)]
+    bunker: Option<BunkerUri>,
+}
+
+/// An event to pre-sign: an unsigned event file, content for a new one, a badge award or a label.
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct EventSource {
+    /// Unsigned event JSON.
+    #[arg(long)]
+    event: Option<PathBuf>,
+    /// Content of a new event by the seller.
+    #[arg(long)]
+    content: Option<String>,
+    /// Badge definition event JSON; the seller's NIP-58 award of it to `--awardee`.
+    #[arg(long, requires = "awardee")]
+    badge: Option<PathBuf>,
+    /// `NAMESPACE:LABEL`; a NIP-32 label attestation about `--awardee`.
+    #[arg(long, requires = "awardee", value_parser = parse_label)]
+    label: Option<Claim>,
+}
+
+/// Settings for a new event drafted from `EventSource`.
+#[derive(Args)]
+pub struct EventTemplateArgs {
+    /// Kind of a new event.
+    #[arg(long, default_value_t = 1)]
+    kind: u64,
+    /// The buyer's x-only key: the badge awardee, or the subject of an attestation.
+    /// With `--content`, the event becomes an attestation of `--kind` about this key.
+    #[arg(long)]
+    awardee: Option<XOnlyPublicKey>,
+    /// Seconds until an attestation expires (NIP-40).
+    #[arg(long, default_value_t = 30 * 24 * 60 * 60)]
+    expires_in: u64,
+}
+
+#[derive(Args)]
+pub struct OfferCreateArgs {
+    #[command(flatten)]
+    seller: SellerKeyArgs,
+    /// Adaptor secret key file.
+    #[arg(long)]
+    secret: PathBuf,
+    #[command(flatten)]
+    source: EventSource,
+    #[command(flatten)]
+    template: EventTemplateArgs,
+    /// Price in satoshis.
+    #[arg(long)]
+    amount: u64,
+    /// File the buyer can decrypt once the swap reveals `t`, sent encrypted with the offer.
+    #[arg(long)]
+    payload: Option<PathBuf>,
+    /// Keystore to record `t` and the pre-signature nonce in, under the swap id.
+    #[arg(long, env = "TAPSTR_KEYSTORE")]
+    keystore: Option<PathBuf>,
+    #[command(flatten)]
+    out: OutArgs,
+}
+
+#[derive(Args)]
+pub struct OfferAcceptArgs {
+    /// Offer JSON from `offer create`.
+    #[arg(long)]
+    offer: PathBuf,
+    /// Buyer key file; its x-only key gets the refund leaf.
+    #[arg(long)]
+    key: PathBuf,
+    #[command(flatten)]
+    out: OutArgs,
+}
+
+#[derive(Args)]
+pub struct LockBuildArgs {
+    /// Accepted offer JSON from `offer accept`.
+    #[arg(long)]
+    accept: PathBuf,
+    /// Funding UTXO as `txid:vout:amount_sat`, paying the funding key's P2TR address. Repeatable.
+    #[arg(long = "utxo", required = true)]
+    utxos: Vec<String>,
+    /// Funding key file. Without it the PSBT is left for an external signer.
+    #[arg(long, conflicts_with = "funding_pubkey")]
+    key: Option<PathBuf>,
+    /// X-only funding key of an external signer.
+    #[arg(long, required_unless_present = "key")]
+    funding_pubkey: Option<XOnlyPublicKey>,
+    /// Change address; defaults to the funding key's P2TR address.
+    #[arg(long)]
+    change: Option<Address<bitcoin::address::NetworkUnchecked>>,
+    /// Feerate in sat/vB.
+    #[arg(long, default_value_t = 2)]
+    fee_rate: u64,
+    /// Finalize this externally signed PSBT instead of building a new one.
+    #[arg(long)]
+    signed_psbt: Option<PathBuf>,
+    /// Write the finalized transaction hex instead of a PSBT.
+    #[arg(long)]
+    raw: bool,
+    /// Also write the lock message for the seller here.
+    #[arg(long)]
+    lock_out: Option<PathBuf>,
+    #[command(flatten)]
+    out: OutArgs,
+}
+
+#[derive(Args)]
+pub struct PresigCreateArgs {
+    #[command(flatten)]
+    seller: SellerKeyArgs,
+    /// Adaptor secret key file.
+    #[arg(long)]
+    secret: PathBuf,
+    #[command(flatten)]
+    source: EventSource,
+    #[command(flatten)]
+    template: EventTemplateArgs,
+    #[command(flatten)]
+    out: OutArgs,
+}
+
+#[derive(Args)]
+pub struct PresigVerifyArgs {
+    /// Pre-signature JSON from `presig create`.
+    #[arg(long)]
+    presig: PathBuf,
+    /// Require the pre-signature to be by this x-only key.
+    #[arg(long)]
+    seller_pubkey: Option<XOnlyPublicKey>,
+}
+
+#[derive(Args)]
+pub struct ClaimArgs {
+    /// Seller key file.
+    #[arg(long)]
+    key: PathBuf,
+    /// Offer JSON from `offer create`.
+    #[arg(long)]
+    offer: PathBuf,
+    /// Accepted offer JSON from `offer accept`.
+    #[arg(long)]
+    accept: PathBuf,
+    /// Lock message JSON from `lock build --lock-out`.
+    #[arg(long)]
+    lock: PathBuf,
+    /// Where the swap output goes.
+    #[arg(long)]
+    to: Address<bitcoin::address::NetworkUnchecked>,
+    /// Feerate in sat/vB.
+    #[arg(long, default_value_t = 2)]
+    fee_rate: u64,
+    #[command(flatten)]
+    out: OutArgs,
+}
+
+#[derive(Args)]
+pub struct RefundArgs {
+    /// Buyer key file.
+    #[arg(long)]
+    key: PathBuf,
+    /// Accepted offer JSON from `offer accept`.
+    #[arg(long)]
+    accept: PathBuf,
+    /// Lock message JSON from `lock build --lock-out`.
+    #[arg(long)]
+    lock: PathBuf,
+    /// Where the refund goes.
+    #[arg(long)]
+    to: Address<bitcoin::address::NetworkUnchecked>,
+    /// Feerate in sat/vB.
+    #[arg(long, default_value_t = 2)]
+    fee_rate: u64,
+    #[command(flatten)]
+    out: OutArgs,
+}
+
+#[derive(Args)]
+pub struct ExtractSecretArgs {
+    /// Pre-signature JSON from `presig create`, or an offer from `offer create`.
+    #[arg(long)]
+    presig: PathBuf,
+    /// Completed event JSON as published by the seller.
+    #[arg(long)]
+    event: PathBuf,
+    #[command(flatten)]
+    out: OutArgs,
+}
+
+#[derive(Args)]
+pub struct ContentVerifyArgs {
+    /// Offer JSON from `offer create --payload`.
+    #[arg(long)]
+    offer: PathBuf,
+}
+
+#[derive(Args)]
+pub struct ContentDecryptArgs {
+    /// Offer JSON from `offer create --payload`, or the swap status from the API.
+    #[arg(long)]
+    offer: PathBuf,
+    /// Adaptor secret key file, e.g. from `extract-secret`.
+    #[arg(long)]
+    secret: PathBuf,
+    /// Write the content here instead of stdout.
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct BadgeVerifyArgs {
+    /// Badge definition event JSON.
+    #[arg(long)]
+    definition: PathBuf,
+    /// The buyer's x-only key.
+    #[arg(long)]
+    awardee: XOnlyPublicKey,
+    /// Before locking: the pre-signature JSON or offer with the drafted award.
+    #[arg(long, conflicts_with = "event")]
+    presig: Option<PathBuf>,
+    /// After the swap: the completed award event JSON.
+    #[arg(long, required_unless_present = "presig")]
+    event: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct AttestationVerifyArgs {
+    /// Pre-signature JSON or offer with the drafted attestation.
+    #[arg(long)]
+    presig: PathBuf,
+    /// The buyer's x-only key; required before locking.
+    #[arg(long, required_unless_present = "event")]
+    awardee: Option<XOnlyPublicKey>,
+    /// After the swap: the completed attestation event JSON.
+    #[arg(long)]
+    event: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct EventPublishArgs {
+    /// Pre-signature JSON from `presig create`, or an offer from `offer create`.
+    #[arg(long, conflicts_with = "event")]
+    presig: Option<PathBuf>,
+    /// Adaptor secret key file.
+    #[arg(long, conflicts_with = "keystore")]
+    secret: Option<PathBuf>,
+    /// Keystore holding `t` for the swap, as recorded by `offer create --keystore`.
+    #[arg(long, env = "TAPSTR_KEYSTORE")]
+    keystore: Option<PathBuf>,
+    /// An already signed event JSON to publish as is.
+    #[arg(long, required_unless_present = "presig")]
+    event: Option<PathBuf>,
+    /// Relay URL. Repeatable; without any the event is only written out.
+    #[arg(long = "relay")]
+    relays: Vec<String>,
+    /// Seconds to wait for relays to accept the event.
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+    #[command(flatten)]
+    out: OutArgs,
+}
+
+/// A secret key and its x-only public key, as written by `keygen`.
+#[derive(Serialize, Deserialize)]
+pub struct KeyFile {
+    pub secret_key: SecretKey,
+    pub public_key: XOnlyPublicKey,
+    /// BIP32 path of a derived key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<DerivationPath>,
+}
+
+impl KeyFile {
+    fn new(secret_key: SecretKey) -> Self {
+        let public_key = secret_key.x_only_public_key(&Secp256k1::new()).0;
+        KeyFile { secret_key, public_key, path: None }
+    }
+}
+
+/// An event and its pre-signature, from either a pre-signature or an offer message.
+#[allow(clippy::large_enum_variant)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PresignedEvent {
+    Presig(PreSignatureResponse),
+    Offer(CreateOfferRequest),
+}
+
+/// The encrypted content of an offer or a swap status response.
+#[derive(Deserialize)]
+struct OfferContent {
+    encrypted_content: Option<EncryptedContent>,
+}
+
+impl PresignedEvent {
+    fn into_parts(self) -> (UnsignedEvent, PreSignature) {
+        match self {
+            PresignedEvent::Presig(p) => (p.event, p.presignature),
+            PresignedEvent::Offer(o) => (o.event, o.presignature),
+        }
+    }
+}
+
+pub async fn run(command: Command, network: Network) -> Result<()> {
+    match command {
+        Command::Serve(_) => unreachable!("serve is handled by main"),
+        Command::Mnemonic(out) => write_text(&out, &KeyManager::generate(network)?.mnemonic().to_string()),
+        Command::Keygen(args) => keygen(args, network),
+        Command::Offer(OfferCommand::Create(args)) => offer_create(args).await,
+        Command::Offer(OfferCommand::Accept(args)) => offer_accept(args, network).await,
+        Command::Lock(LockCommand::Build(args)) => lock_build(args, network).await,
+        Command::Presig(PresigCommand::Create(args)) => presig_create(args).await,
+        Command::Presig(PresigCommand::Verify(args)) => presig_verify(args),
+        Command::Claim(args) => claim(args, network).await,
+        Command::Refund(args) => refund(args, network).await,
+        Command::ExtractSecret(args) => extract_secret(args),
+        Command::Content(ContentCommand::Verify(args)) => content_verify(args),
+        Command::Content(ContentCommand::Decrypt(args)) => content_decrypt(args),
+        Command::Badge(BadgeCommand::Verify(args)) => badge_verify(args),
+        Command::Attestation(AttestationCommand::Verify(args)) => attestation_verify(args),
+        Command::Event(EventCommand::Publish(args)) => event_publish(args).await,
+        Command::Bunker(args) => bunker(args).await,
+        Command::Keystore(command) => keystore(command),
+    }
+}
+
+fn keygen(args: KeygenArgs, network: Network) -> Result<()> {
+    if args.adaptor && args.key_path.swap.is_none() {
+        return Err(anyhow!("--adaptor needs --swap"));
+    }
+    let Some(mnemonic) = &args.mnemonic else {
+        return write_json(&args.out, &KeyFile::new(SecretKey::new(&mut rand::rngs::OsRng)));
+    };
+    let keys = KeyManager::from_mnemonic(read_input(mnemonic)?.trim(), args.passphrase.as_deref().unwrap_or_default(), network)?;
+    let key_path = args.key_path;
+    let path = match (key_path.nostr, key_path.wallet, key_path.swap, key_path.path) {
+        (true, ..) => KeyManager::nostr_path(args.account)?,
+        (_, Some(index), ..) => keys.bip86_path(args.account, false, index)?,
+        (_, _, Some(swap_id), _) => {
+            let (bitcoin, adaptor) = keys.swap_paths(args.account, &swap_id)?;
+            if args.adaptor { adaptor } else { bitcoin }
+        }
+        (_, _, _, Some(path)) => path,
+        _ => return Err(anyhow!("One of --nostr, --wallet, --swap or --path is required with --mnemonic")),
+    };
+    let key_file = KeyFile { path: Some(path.clone()), ..KeyFile::new(keys.derive(&path)?.secret_key()) };
+    write_json(&args.out, &key_file)
+}
+
+async fn offer_create(args: OfferCreateArgs) -> Result<()> {
+    let t = read_key(&args.secret)?;
+    let (event, adaptor_sig) = presign(&args.seller, &t, &args.source, &args.template).await?;
+    if let Some(path) = &args.keystore {
+        let secrets = SwapSecrets { adaptor_secret: Some(t), presignature_nonce: adaptor_sig.nonce(), ..SwapSecrets::default() };
+        unlock_keystore(path)?.update(|contents| contents.swaps.insert(event.id.to_hex(), secrets))?;
+    }
+    let seller_pubkey = adaptor_sig.pubkey.x_only_public_key().0;
+    let claim_pubkey = bitcoin_utils::create_nostr_signature_lock_script(offer_commitment(&adaptor_sig.s), seller_pubkey)?;
+    let encrypted_content = match &args.payload {
+        Some(path) => {
+            let payload = std::fs::read(path).with_context(|| format!("Cannot read {}", path.display()))?;
+            Some(EncryptedContent::encrypt(&Secp256k1::new(), &t, &payload))
+        }
+        None => None,
+    };
+    let offer = CreateOfferRequest {
+        event,
+        presignature: PreSignature::from(&adaptor_sig),
+        amount_sat: args.amount,
+        claim_pubkey,
+        encrypted_content,
+        buyer_pubkey: args.template.awardee,
+    };
+    write_json(&args.out, &offer)
+}
+
+/// Runs the offer through the same checks as the JSON API, then accepts it.
+async fn offer_accept(args: OfferAcceptArgs, network: Network) -> Result<()> {
+    let offer: CreateOfferRequest = read_json(&args.offer)?;
+    let swaps = api::SwapApi::new(network, MockChain::new());
+    let created = swaps.create_offer(offer)?;
+    let request = AcceptOfferRequest::sign(&read_keypair(&args.key)?, &created.swap_id).await?;
+    let accepted = swaps.accept_offer(&created.swap_id, request)?;
+    write_json(&args.out, &accepted)
+}
+
+async fn lock_build(args: LockBuildArgs, network: Network) -> Result<()> {
+    let secp = Secp256k1::new();
+    let accepted: AcceptOfferResponse = read_json(&args.accept)?;
+    let swap = SwapOutput::from_descriptor(&accepted.descriptor)?;
+    let keypair = args.key.as_deref().map(read_keypair).transpose()?;
+    let funding_pubkey = match (&keypair, args.funding_pubkey) {
+        (Some(keypair), _) => keypair.x_only_public_key().0,
+        (None, Some(pubkey)) => pubkey,
+        (None, None) => return Err(anyhow!("Either --key or --funding-pubkey is required")),
+    };
+    let funding_script = ScriptBuf::new_p2tr(&secp, funding_pubkey, None);
+    let change = match args.change {
+        Some(change) => change,
+        None => Address::p2tr(&secp, funding_pubkey, None, network).as_unchecked().clone(),
+    };
+
+    let mut builder = SpendBuilder::new(network);
+    for utxo in &args.utxos {
+        let (outpoint, value) = parse_utxo(utxo)?;
+        let signer = match keypair {
+            Some(keypair) => InputSigner::KeyPath { signer: keypair, merkle_root: None },
+            None => InputSigner::ExternalKeyPath { internal_key: funding_pubkey, merkle_root: None },
+        };
+        builder.add_input(SpendInput::new(outpoint, TxOut { value, script_pubkey: funding_script.clone() }, signer));
+    }
+    builder
+        .add_output(swap.address(network)?.as_unchecked().clone(), Amount::from_sat(accepted.amount_sat))?
+        .drain_to(change, fee_rate(args.fee_rate)?)?;
+
+    if let Some(path) = &args.lock_out {
+        let lock = SubmitLockRequest { txid: builder.unsigned_tx().txid(), vout: 0 };
+        write_file(Some(path), &serde_json::to_string_pretty(&lock)?)?;
+    }
+    let psbt = match &args.signed_psbt {
+        Some(path) => Psbt::from_str(read_input(path)?.trim()).context("Invalid PSBT")?,
+        None => builder.sign().await?,
+    };
+    if args.raw || args.signed_psbt.is_some() {
+        write_text(&args.out, &serialize_hex(&builder.finalize(&psbt)?))
+    } else {
+        write_text(&args.out, &psbt.to_string())
+    }
+}
+
+async fn presig_create(args: PresigCreateArgs) -> Result<()> {
+    let (event, adaptor_sig) = presign(&args.seller, &read_key(&args.secret)?, &args.source, &args.template).await?;
+    let presig = PreSignatureResponse { swap_id: event.id.to_hex(), event, presignature: PreSignature::from(&adaptor_sig) };
+    write_json(&args.out, &presig)
+}
+
+fn presig_verify(args: PresigVerifyArgs) -> Result<()> {
+    let presig: PreSignatureResponse = read_json(&args.presig)?;
+    let event = &presig.event;
+    nostr_utils::check_presigned_event(event, &presig.presignature.to_adaptor_signature(&event.id))?;
+    let signer = presig.presignature.pubkey.x_only_public_key().0;
+    if args.seller_pubkey.is_some_and(|expected| expected != signer) {
+        return Err(anyhow!("Pre-signature is by {}, not the expected seller", signer));
+    }
+    println!("Pre-signature by {} over event {} is valid", signer, event.id);
+    Ok(())
+}
+
+async fn claim(args: ClaimArgs, network: Network) -> Result<()> {
+    let offer: CreateOfferRequest = read_json(&args.offer)?;
+    let (lock_outpoint, lock_txout, swap) = swap_prevout(&args.accept, &args.lock)?;
+    if swap.claim_key != offer.claim_pubkey {
+        return Err(anyhow!("Swap output does not pay the offer's claim key"));
+    }
+    let claim_keypair = bitcoin_utils::nostr_signature_claim_keypair(offer_commitment(&offer.presignature.s), &read_keypair(&args.key)?)?;
+    let tx = bitcoin_utils::create_claim_transaction(
+        lock_outpoint,
+        &lock_txout,
+        &swap,
+        args.to,
+        network,
+        fee_rate(args.fee_rate)?,
+        None,
+        &claim_keypair,
+        TapSighashType::Default,
+    )
+    .await?;
+    write_text(&args.out, &serialize_hex(&tx))
+}
+
+async fn refund(args: RefundArgs, network: Network) -> Result<()> {
+    let (lock_outpoint, lock_txout, swap) = swap_prevout(&args.accept, &args.lock)?;
+    let tx = bitcoin_utils::create_refund_transaction(
+        lock_outpoint,
+        &lock_txout,
+        &swap,
+        args.to,
+        network,
+        fee_rate(args.fee_rate)?,
+        None,
+        &read_keypair(&args.key)?,
+        TapSighashType::Default,
+    )
+    .await?;
+    write_text(&args.out, &serialize_hex(&tx))
+}
+
+fn extract_secret(args: ExtractSecretArgs) -> Result<()> {
+    let (unsigned, presignature) = read_json::<PresignedEvent>(&args.presig)?.into_parts();
+    let adaptor_sig = presignature.to_adaptor_signature(&unsigned.id);
+    nostr_utils::check_presigned_event(&unsigned, &adaptor_sig)?;
+    let event: Event = read_json(&args.event)?;
+    let t = nostr_utils::extract_secret_from_event(&adaptor_sig, &event)?;
+    write_json(&args.out, &KeyFile::new(t))
+}
+
+fn content_verify(args: ContentVerifyArgs) -> Result<()> {
+    let offer: CreateOfferRequest = read_json(&args.offer)?;
+    let content = offer.encrypted_content.ok_or_else(|| anyhow!("Offer has no encrypted content"))?;
+    if content.adaptor_point() != offer.presignature.adaptor_point {
+        return Err(anyhow!("Content is encrypted to {}, not the pre-signature's adaptor point", content.adaptor_point()));
+    }
+    if !content.verify(&Secp256k1::new()) {
+        return Err(anyhow!("Content key is not recoverable with the adaptor secret"));
+    }
+    println!("Content key {} is recoverable with t; content commitment {}", content.key.key_point, content.commitment);
+    Ok(())
+}
+
+fn content_decrypt(args: ContentDecryptArgs) -> Result<()> {
+    let offer: OfferContent = read_json(&args.offer)?;
+    let content = offer.encrypted_content.ok_or_else(|| anyhow!("Offer has no encrypted content"))?;
+    let plaintext = content.decrypt(&Secp256k1::new(), &read_key(&args.secret)?)?;
+    match &args.out {
+        Some(path) => std::fs::write(path, plaintext).with_context(|| format!("Cannot write {}", path.display())),
+        None => Ok(std::io::stdout().write_all(&plaintext)?),
+    }
+}
+
+fn badge_verify(args: BadgeVerifyArgs) -> Result<()> {
+    let definition: Event = read_json(&args.definition)?;
+    match (&args.presig, &args.event) {
+        (Some(presig), _) => {
+            let (award, presignature) = read_json::<PresignedEvent>(presig)?.into_parts();
+            nostr_utils::check_badge_award(&award, &presignature.to_adaptor_signature(&award.id), &definition, &args.awardee)?;
+            println!("Pre-signed award {} of badge {} to {} is valid", award.id, definition.id, args.awardee);
+        }
+        (None, Some(event)) => {
+            let award: Event = read_json(event)?;
+            nostr_utils::verify_badge_award(&award, &definition, &args.awardee)?;
+            println!("Award {} of badge {} to {} is valid", award.id, definition.id, args.awardee);
+        }
+        (None, None) => return Err(anyhow!("Either --presig or --event is required")),
+    }
+    Ok(())
+}
+
+fn attestation_verify(args: AttestationVerifyArgs) -> Result<()> {
+    let (unsigned, presignature) = read_json::<PresignedEvent>(&args.presig)?.into_parts();
+    let adaptor_sig = presignature.to_adaptor_signature(&unsigned.id);
+    nostr_utils::check_presigned_event(&unsigned, &adaptor_sig)?;
+    let (attestation, t) = match (&args.event, &args.awardee) {
+        (Some(event), _) => {
+            let (attestation, t) = attestation::prove_attestation_payment(&read_json(event)?, &adaptor_sig)?;
+            if args.awardee.is_some_and(|awardee| awardee != attestation.subject) {
+                return Err(anyhow!("Attestation is about {}, not the expected buyer", attestation.subject));
+            }
+            (attestation, Some(t))
+        }
+        (None, Some(awardee)) => (attestation::check_attestation_offer(&unsigned, &adaptor_sig, awardee)?, None),
+        (None, None) => return Err(anyhow!("Either --awardee or --event is required")),
+    };
+    let claim = match &attestation.claim {
+        Claim::Label { namespace, label } => format!("label {}:{}", namespace, label),
+        Claim::Custom { kind, content } => format!("kind {} {:?}", kind, content),
+    };
+    println!("Attestation ({}) about {} expires at {}", claim, attestation.subject, attestation.expiration);
+    if let Some(t) = t {
+        println!("Paid through swap {}: t = {}", attestation.adaptor_point, t.display_secret());
+    }
+    Ok(())
+}
+
+async fn event_publish(args: EventPublishArgs) -> Result<()> {
+    let event: Event = match (&args.presig, &args.event) {
+        (Some(presig), _) => {
+            let (unsigned, presignature) = read_json::<PresignedEvent>(presig)?.into_parts();
+            let adaptor_sig = presignature.to_adaptor_signature(&unsigned.id);
+            nostr_utils::check_presigned_event(&unsigned, &adaptor_sig)?;
+            let t = match (&args.secret, &args.keystore) {
+                (Some(secret), _) => read_key(secret)?,
+                (None, Some(path)) => unlock_keystore(path)?
+                    .contents()?
+                    .swaps
+                    .get(&unsigned.id.to_hex())
+                    .and_then(|swap| swap.adaptor_secret)
+                    .ok_or_else(|| anyhow!("Keystore has no adaptor secret for swap {}", unsigned.id))?,
+                (None, None) => return Err(anyhow!("--presig needs --secret or --keystore")),
+            };
+            nostr_utils::complete_adaptor_event(unsigned, &adaptor_sig, &t)?
+        }
+        (None, Some(event)) => read_json(event)?,
+        (None, None) => return Err(anyhow!("Either --presig or --event is required")),
+    };
+    event.verify().context("Event signature does not verify")?;
+
+    if !args.relays.is_empty() {
+        let mut client = RelayClient::new(WebSocketTransport, args.relays.clone());
+        client.connect().await?;
+        let accepted = client.publish(&event, Duration::from_secs(args.timeout)).await?;
+        eprintln!("Event {} accepted by {} of {} relay(s)", event.id, accepted.len(), args.relays.len());
+    }
+    write_json(&args.out, &event)
+}
+
+fn keystore(command: KeystoreCommand) -> Result<()> {
+    match command {
+        KeystoreCommand::Create { keystore, mnemonic } => {
+            let mut contents = KeystoreContents::default();
+            if let Some(path) = mnemonic {
+                let words = bip39::Mnemonic::parse_normalized(read_input(&path)?.trim()).map_err(|e| anyhow!("Invalid mnemonic: {}", e))?;
+                contents.mnemonic = Some(words.to_string());
+            }
+            Keystore::create(&keystore.keystore, &keystore.passphrase, ScryptParams::default(), contents)?;
+            eprintln!("Created keystore {}", keystore.keystore.display());
+        }
+        KeystoreCommand::Import { keystore, name, key } => {
+            let secret_key = read_key(&key)?;
+            open_keystore(&keystore)?.update(|contents| contents.identities.insert(name, secret_key))?;
+        }
+        KeystoreCommand::Export { keystore, name, out } => {
+            let mut store = open_keystore(&keystore)?;
+            let secret_key = *store.contents()?.identities.get(&name).ok_or_else(|| anyhow!("No identity named {}", name))?;
+            write_json(&out, &KeyFile::new(secret_key))?;
+        }
+        KeystoreCommand::List { keystore } => {
+            let mut store = open_keystore(&keystore)?;
+            let contents = store.contents()?;
+            let mut listing = serde_json::json!({
+                "mnemonic": contents.mnemonic.is_some(),
+                "identities": contents.identities.iter().map(|(name, key)| (name.clone(), key.x_only_public_key(&Secp256k1::new()).0.to_string())).collect::<std::collections::BTreeMap<_, _>>(),
+                "swaps": {},
+            });
+            for (swap_id, swap) in &contents.swaps {
+                let held: Vec<&str> = [
+                    ("adaptor_secret", swap.adaptor_secret.is_some()),
+                    ("presignature_nonce", swap.presignature_nonce.is_some()),
+                    ("swap_key", swap.swap_key.is_some()),
+                ]
+                .into_iter()
+                .filter_map(|(name, held)| held.then_some(name))
+                .collect();
+                listing["swaps"][swap_id] = serde_json::json!(held);
+            }
+            write_file(None, &serde_json::to_string_pretty(&listing)?)?;
+        }
+        KeystoreCommand::Passwd { keystore, new_passphrase } => open_keystore(&keystore)?.change_passphrase(&new_passphrase)?,
+    }
+    Ok(())
+}
+
+fn open_keystore(args: &KeystoreArgs) -> Result<Keystore> {
+    let mut keystore = Keystore::open(&args.keystore)?;
+    keystore.unlock(&args.passphrase)?;
+    Ok(keystore)
+}
+
+/// Open `path` with the passphrase from `TAPSTR_KEYSTORE_PASSPHRASE`.
+fn unlock_keystore(path: &Path) -> Result<Keystore> {
+    let passphrase = std::env::var("TAPSTR_KEYSTORE_PASSPHRASE").context("Set TAPSTR_KEYSTORE_PASSPHRASE to unlock the keystore")?;
+    open_keystore(&KeystoreArgs { keystore: path.to_path_buf(), passphrase })
+}
+
+async fn bunker(args: BunkerArgs) -> Result<()> {
+    let mut bunker = Bunker::new(read_keypair(&args.key)?);
+    for client in &args.allowed {
+        bunker = bunker.allow_client(*client)?;
+    }
+    let secret = match args.secret {
+        Some(secret) => Some(secret),
+        None if args.allowed.is_empty() => Some(hex::encode(rand::random::<[u8; 16]>())),
+        None => None,
+    };
+    if let Some(secret) = secret {
+        bunker = bunker.with_secret(secret);
+    }
+    write_file(None, &bunker.uri(args.relays.clone()).to_string())?;
+    bunker.run(WebSocketTransport, args.relays).await
+}
+
+/// Pre-sign with the key file, or through the bunker so the key never enters this process.
+async fn presign(seller: &SellerKeyArgs, t: &SecretKey, source: &EventSource, template: &EventTemplateArgs) -> Result<(UnsignedEvent, AdaptorSignature)> {
+    match (&seller.key, &seller.bunker) {
+        (Some(key), _) => presign_with(&read_keypair(key)?, t, source, template).await,
+        (None, Some(uri)) => presign_with(&RemoteSigner::connect(WebSocketTransport, uri, BUNKER_TIMEOUT).await?, t, source, template).await,
+        (None, None) => Err(anyhow!("Either --key or --bunker is required")),
+    }
+}
+
+async fn presign_with(signer: &impl AdaptorSigner, t: &SecretKey, source: &EventSource, template: &EventTemplateArgs) -> Result<(UnsignedEvent, AdaptorSignature)> {
+    let adaptor_point = t.public_key(&Secp256k1::new());
+    if let Some(awardee) = &template.awardee {
+        let claim = match (&source.badge, &source.label, &source.content) {
+            (Some(badge), ..) => return nostr_utils::presign_badge_award(signer, &read_json(badge)?, awardee, &adaptor_point).await,
+            (None, Some(label), _) => label.clone(),
+            (None, None, Some(content)) => Claim::Custom { kind: template.kind, content: content.clone() },
+            (None, None, None) => return Err(anyhow!("--awardee needs --badge, --label or --content")),
+        };
+        let expiration = Timestamp::now() + template.expires_in;
+        return Attestation { claim, subject: *awardee, expiration, adaptor_point }.presign(signer).await;
+    }
+    let seller_pubkey = signer.public_key().await?;
+    let event = match (&source.event, &source.content) {
+        (Some(path), _) => read_json::<UnsignedEvent>(path)?,
+        (None, Some(content)) => nostr_utils::create_unsigned_event(&seller_pubkey, Kind::from(template.kind), content, vec![])?,
+        (None, None) => return Err(anyhow!("One of --event, --content, --badge or --label is required")),
+    };
+    if event.pubkey.serialize() != seller_pubkey.serialize() {
+        return Err(anyhow!("Event author {} is not the key {}", event.pubkey, seller_pubkey));
+    }
+    let adaptor_sig = signer.presign_event(&event, &adaptor_point).await?;
+    Ok((event, adaptor_sig))
+}
+
+fn parse_label(s: &str) -> Result<Claim> {
+    let (namespace, label) = s.split_once(':').ok_or_else(|| anyhow!("Label {} is not NAMESPACE:LABEL", s))?;
+    Ok(Claim::Label { namespace: namespace.to_string(), label: label.to_string() })
+}
+
+/// Tweak committed into the claim key: the hash of the adaptor `s`, as in the demo.
+fn offer_commitment(s: &SecretKey) -> [u8; 32] {
+    *sha256::Hash::const_hash(s.as_ref()).as_byte_array()
+}
+
+fn swap_prevout(accept: &Path, lock: &Path) -> Result<(OutPoint, TxOut, SwapOutput)> {
+    let accepted: AcceptOfferResponse = read_json(accept)?;
+    let lock: SubmitLockRequest = read_json(lock)?;
+    let swap = SwapOutput::from_descriptor(&accepted.descriptor)?;
+    let txout = TxOut { value: Amount::from_sat(accepted.amount_sat), script_pubkey: swap.script_pubkey()? };
+    Ok((OutPoint { txid: lock.txid, vout: lock.vout }, txout, swap))
+}
+
+fn parse_utxo(utxo: &str) -> Result<(OutPoint, Amount)> {
+    let (outpoint, amount) = utxo.rsplit_once(':').ok_or_else(|| anyhow!("UTXO {} is not txid:vout:amount_sat", utxo))?;
+    let outpoint = OutPoint::from_str(outpoint).with_context(|| format!("Invalid outpoint in {}", utxo))?;
+    let amount = Amount::from_sat(amount.parse().with_context(|| format!("Invalid amount in {}", utxo))?);
+    Ok((outpoint, amount))
+}
+
+fn fee_rate(sat_per_vb: u64) -> Result<FeeRate> {
+    FeeRate::from_sat_per_vb(sat_per_vb).ok_or_else(|| anyhow!("Feerate {} sat/vB is too high", sat_per_vb))
+}
+
+fn read_key(path: &Path) -> Result<SecretKey> {
+    Ok(read_json::<KeyFile>(path)?.secret_key)
+}
+
+fn read_keypair(path: &Path) -> Result<Keypair> {
+    Ok(Keypair::from_secret_key(&Secp256k1::new(), &read_key(path)?))
+}
+
+/// Contents of `path`, or of stdin when `path` is `-`.
+fn read_input(path: &Path) -> Result<String> {
+    let mut contents = String::new();
+    if path == Path::new("-") {
+        std::io::stdin().read_to_string(&mut contents)?;
+    } else {
+        contents = std::fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+    }
+    Ok(contents)
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    serde_json::from_str(&read_input(path)?).with_context(|| format!("Cannot parse {}", path.display()))
+}
+
+fn write_file(path: Option<&Path>, contents: &str) -> Result<()> {
+    match path {
+        Some(path) => std::fs::write(path, format!("{}\n", contents)).with_context(|| format!("Cannot write {}", path.display())),
+        None => Ok(writeln!(std::io::stdout(), "{}", contents)?),
+    }
+}
+
+fn write_text(out: &OutArgs, contents: &str) -> Result<()> {
+    write_file(out.out.as_deref(), contents)
+}
+
+fn write_json<T: Serialize>(out: &OutArgs, value: &T) -> Result<()> {
+    write_text(out, &serde_json::to_string_pretty(value)?)
+}
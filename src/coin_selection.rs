@@ -0,0 +1,255 @@
+use bitcoin::{Amount, FeeRate, Script, TxOut, Weight};
+use bitcoin::consensus::encode::VarInt;
+use anyhow::{anyhow, Result};
+use crate::chain::Utxo;
+
+/// Version + lock time, in weight units.
+const TX_FIXED_WEIGHT: u64 = (4 + 4) * 4;
+/// Segwit marker and flag bytes.
+const SEGWIT_MARKER_WEIGHT: u64 = 2;
+/// Outpoint + empty script_sig length + sequence, in weight units.
+const TXIN_BASE_WEIGHT: u64 = (36 + 1 + 4) * 4;
+/// Give up branch-and-bound after this many steps and fall back to largest-first.
+const BNB_MAX_TRIES: u32 = 100_000;
+
+/// Witness weight needed to spend an output, including the witness item count.
+/// Taproot key path: one 64-byte signature (65 with a non-default sighash type).
+/// P2WPKH: a DER signature of up to 72 bytes and a 33-byte key.
+pub fn satisfaction_weight(script_pubkey: &Script) -> Result<Weight> {
+    if script_pubkey.is_p2tr() {
+        Ok(Weight::from_wu(1 + 1 + 64))
+    } else if script_pubkey.is_p2wpkh() {
+        Ok(Weight::from_wu(1 + 1 + 72 + 1 + 33))
+    } else {
+        Err(anyhow!("Unsupported input script for fee estimation: {}", script_pubkey))
+    }
+}
+
+/// Full weight of an input spending `script_pubkey`.
+pub fn input_weight(script_pubkey: &Script) -> Result<Weight> {
+    Ok(Weight::from_wu(TXIN_BASE_WEIGHT) + satisfaction_weight(script_pubkey)?)
+}
+
+pub fn output_weight(script_pubkey: &Script) -> Weight {
+    let len = script_pubkey.len();
+    Weight::from_wu((8 + VarInt(len as u64).size() + len) as u64 * 4)
+}
+
+/// Weight of a segwit transaction with inputs spending `input_scripts` and `outputs`.
+pub fn estimate_tx_weight(input_scripts: &[&Script], outputs: &[TxOut]) -> Result<Weight> {
+    let mut weight = TX_FIXED_WEIGHT + SEGWIT_MARKER_WEIGHT;
+    weight += (VarInt(input_scripts.len() as u64).size() + VarInt(outputs.len() as u64).size()) as u64 * 4;
+    for script in input_scripts {
+        weight += input_weight(script)?.to_wu();
+    }
+    for output in outputs {
+        weight += output_weight(&output.script_pubkey).to_wu();
+    }
+    Ok(Weight::from_wu(weight))
+}
+
+fn fee_for(fee_rate: FeeRate, weight: Weight) -> Result<Amount> {
+    fee_rate.fee_vb(weight.to_vbytes_ceil()).ok_or_else(|| anyhow!("Fee overflow"))
+}
+
+/// Result of coin selection: the inputs to spend, the fee they pay and any change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoinSelection {
+    pub selected: Vec<Utxo>,
+    pub fee: Amount,
+    pub change: Option<Amount>,
+}
+
+/// Select wallet UTXOs paying `outputs` at `fee_rate`.
+/// Tries branch-and-bound for a changeless solution first, then falls back to
+/// largest-first with a change output. Change below the dust threshold goes to fees.
+pub fn select_coins(utxos: &[Utxo], outputs: &[TxOut], change_script: &Script, fee_rate: FeeRate) -> Result<CoinSelection> {
+    let payment = outputs.iter().map(|o| o.value.to_sat()).sum::<u64>();
+    let no_change_weight = estimate_tx_weight(&[], outputs)?;
+    let change_output = TxOut { value: Amount::ZERO, script_pubkey: change_script.to_owned() };
+    let change_weight = output_weight(change_script);
+    // Creating change costs its output now and its input later.
+    // Unknown change script types are assumed to be spent like a taproot key path.
+    let change_spend_weight = input_weight(change_script).unwrap_or(Weight::from_wu(TXIN_BASE_WEIGHT + 66));
+    let cost_of_change = fee_for(fee_rate, change_weight + change_spend_weight)?.to_sat();
+
+    // Effective value: what a coin contributes after paying for its own input.
+    let mut pool = Vec::new();
+    for (i, utxo) in utxos.iter().enumerate() {
+        let fee = fee_for(fee_rate, input_weight(&utxo.txout.script_pubkey)?)?.to_sat();
+        let effective = utxo.txout.value.to_sat() as i64 - fee as i64;
+        if effective > 0 {
+            pool.push((i, effective));
+        }
+    }
+    pool.sort_by_key(|(_, value)| std::cmp::Reverse(*value));
+
+    let target = (payment + fee_for(fee_rate, no_change_weight)?.to_sat()) as i64;
+    let mut best = None;
+    let mut tries = 0;
+    let available = pool.iter().map(|(_, v)| v).sum();
+    branch_and_bound(&pool, 0, 0, available, target, target + cost_of_change as i64, &mut Vec::new(), &mut best, &mut tries);
+
+    let indices = match best {
+        Some((_, indices)) => indices,
+        None => {
+            // Largest-first until the payment plus a change output is covered
+            let target_with_change = target + fee_for(fee_rate, change_weight)?.to_sat() as i64;
+            let mut total = 0;
+            let mut indices = Vec::new();
+            for (i, value) in &pool {
+                if total >= target_with_change {
+                    break;
+                }
+                total += value;
+                indices.push(*i);
+            }
+            if total < target {
+                return Err(anyhow!("Insufficient funds: need {} sat plus fees, have {} sat", payment, utxos.iter().map(|u| u.txout.value.to_sat()).sum::<u64>()));
+            }
+            indices
+        }
+    };
+
+    let selected: Vec<Utxo> = indices.iter().map(|i| utxos[*i].clone()).collect();
+    let input_total = selected.iter().map(|u| u.txout.value.to_sat()).sum::<u64>();
+    let input_scripts: Vec<&Script> = selected.iter().map(|u| u.txout.script_pubkey.as_script()).collect();
+
+    // Exact fee with and without change for the final input count
+    let mut with_change = outputs.to_vec();
+    with_change.push(change_output);
+    let fee_with_change = fee_for(fee_rate, estimate_tx_weight(&input_scripts, &with_change)?)?.to_sat();
+    let fee_without_change = fee_for(fee_rate, estimate_tx_weight(&input_scripts, outputs)?)?.to_sat();
+
+    let change = input_total.checked_sub(payment + fee_with_change).map(Amount::from_sat);
+    match change {
+        Some(change) if change >= change_script.dust_value() => Ok(CoinSelection {
+            selected,
+            fee: Amount::from_sat(fee_with_change),
+            change: Some(change),
+        }),
+        _ => {
+            if input_total < payment + fee_without_change {
+                return Err(anyhow!("Insufficient funds for fee of {} sat", fee_without_change));
+            }
+            Ok(CoinSelection {
+                selected,
+                fee: Amount::from_sat(input_total - payment),
+                change: None,
+            })
+        }
+    }
+}
+
+/// Depth-first search for the subset whose effective value lands in `[target, upper]`
+/// with the least excess.
+#[allow(clippy::too_many_arguments)]
+fn branch_and_bound(
+    pool: &[(usize, i64)],
+    idx: usize,
+    current: i64,
+    remaining: i64,
+    target: i64,
+    upper: i64,
+    selection: &mut Vec<usize>,
+    best: &mut Option<(i64, Vec<usize>)>,
+    tries: &mut u32,
+) {
+    *tries += 1;
+    if *tries > BNB_MAX_TRIES || current > upper {
+        return;
+    }
+    if current >= target {
+        let excess = current - target;
+        if best.as_ref().is_none_or(|(best_excess, _)| excess < *best_excess) {
+            *best = Some((excess, selection.clone()));
+        }
+        return;
+    }
+    if idx == pool.len() || current + remaining < target {
+        return;
+    }
+
+    let (utxo, value) = pool[idx];
+    selection.push(utxo);
+    branch_and_bound(pool, idx + 1, current + value, remaining - value, target, upper, selection, best, tries);
+    selection.pop();
+    branch_and_bound(pool, idx + 1, current, remaining - value, target, upper, selection, best, tries);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, ScriptBuf, Txid};
+    use secp256k1::{Keypair, Secp256k1};
+
+    fn p2tr() -> ScriptBuf {
+        let keypair = Keypair::new(&Secp256k1::new(), &mut rand::thread_rng());
+        ScriptBuf::new_p2tr(&Secp256k1::new(), keypair.x_only_public_key().0, None)
+    }
+
+    fn utxos(script: &Script, values: &[u64]) -> Vec<Utxo> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(vout, value)| Utxo {
+                outpoint: OutPoint { txid: Txid::all_zeros(), vout: vout as u32 },
+                txout: TxOut { value: Amount::from_sat(*value), script_pubkey: script.to_owned() },
+                confirmations: 1,
+            })
+            .collect()
+    }
+
+    fn pays_for_itself(selection: &CoinSelection, payment: u64) {
+        let inputs = selection.selected.iter().map(|u| u.txout.value.to_sat()).sum::<u64>();
+        assert_eq!(inputs, payment + selection.fee.to_sat() + selection.change.map_or(0, Amount::to_sat));
+    }
+
+    #[test]
+    fn taproot_key_spend_weight() {
+        // One key-path input and one P2TR output: 111 vbytes.
+        let script = p2tr();
+        let output = TxOut { value: Amount::from_sat(10_000), script_pubkey: p2tr() };
+        assert_eq!(estimate_tx_weight(&[&script], &[output]).unwrap(), Weight::from_wu(444));
+    }
+
+    #[test]
+    fn exact_match_needs_no_change() {
+        let wallet = p2tr();
+        let payment = 50_000;
+        let outputs = [TxOut { value: Amount::from_sat(payment), script_pubkey: p2tr() }];
+        // 54 sat for the transaction without inputs and 58 sat for the input at 1 sat/vB.
+        let coins = utxos(&wallet, &[200_000, payment + 54 + 58, 30_000]);
+        let selection = select_coins(&coins, &outputs, &p2tr(), FeeRate::from_sat_per_vb_unchecked(1)).unwrap();
+        assert_eq!(selection.selected, vec![coins[1].clone()]);
+        assert_eq!(selection.change, None);
+        pays_for_itself(&selection, payment);
+    }
+
+    #[test]
+    fn surplus_goes_to_change_and_dust_to_fees() {
+        let wallet = p2tr();
+        let payment = 50_000;
+        let outputs = [TxOut { value: Amount::from_sat(payment), script_pubkey: p2tr() }];
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+
+        let selection = select_coins(&utxos(&wallet, &[200_000]), &outputs, &p2tr(), fee_rate).unwrap();
+        assert!(selection.change.is_some_and(|change| change >= p2tr().dust_value()));
+        pays_for_itself(&selection, payment);
+
+        // A surplus above the exact-match window but below dust once change is paid for.
+        let coin = payment + 500;
+        let selection = select_coins(&utxos(&wallet, &[coin]), &outputs, &p2tr(), fee_rate).unwrap();
+        assert_eq!(selection.change, None);
+        pays_for_itself(&selection, payment);
+    }
+
+    #[test]
+    fn insufficient_funds_fail() {
+        let wallet = p2tr();
+        let outputs = [TxOut { value: Amount::from_sat(50_000), script_pubkey: p2tr() }];
+        let coins = utxos(&wallet, &[20_000, 30_000]);
+        assert!(select_coins(&coins, &outputs, &p2tr(), FeeRate::from_sat_per_vb_unchecked(1)).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+use bitcoin::{blockdata::constants::genesis_block, BlockHash, FeeRate, Network, OutPoint, Script, Transaction, Txid};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+use tracing_subscriber::EnvFilter;
+use ::tapstr::chain::{BitcoindRpc, ChainBackend, EsploraClient, MockChain, Utxo};
+
+/// Settings of the web server. Read from a TOML file, then overridden by
+/// `TAPSTR_*` environment variables and command-line flags.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub listen: SocketAddr,
+    pub tls: Option<TlsConfig>,
+    pub network: Network,
+    pub chain: ChainConfig,
+    /// Relays the API forwards completed events to, besides the embedded one.
+    pub relays: Vec<String>,
+    /// `tracing` filter, e.g. `info` or `tapstr=debug,info`.
+    pub log_level: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainConfig {
+    pub backend: BackendKind,
+    /// Esplora base URL or bitcoind RPC URL.
+    pub url: Option<String>,
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    /// In-memory chain; required by the demo pages, regtest only.
+    #[default]
+    Mock,
+    Esplora,
+    Bitcoind,
+}
+
+impl std::fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.to_possible_value().expect("no variant is skipped").get_name())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: SocketAddr::from(([127, 0, 0, 1], 3000)),
+            tls: None,
+            network: Network::Regtest,
+            chain: ChainConfig::default(),
+            relays: vec![],
+            log_level: "info".to_string(),
+        }
+    }
+}
+
+/// Overrides for `Config`, as flags or environment variables.
+#[derive(Args, Debug, Default)]
+pub struct ServeArgs {
+    /// Address to listen on.
+    #[arg(long, env = "TAPSTR_LISTEN")]
+    listen: Option<SocketAddr>,
+    /// PEM certificate chain; serve HTTPS together with `--tls-key`.
+    #[arg(long, env = "TAPSTR_TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key for `--tls-cert`.
+    #[arg(long, env = "TAPSTR_TLS_KEY")]
+    tls_key: Option<PathBuf>,
+    /// Chain backend.
+    #[arg(long, env = "TAPSTR_BACKEND")]
+    backend: Option<BackendKind>,
+    /// Esplora base URL or bitcoind RPC URL.
+    #[arg(long, env = "TAPSTR_BACKEND_URL")]
+    backend_url: Option<String>,
+    #[arg(long, env = "TAPSTR_RPC_USER")]
+    rpc_user: Option<String>,
+    #[arg(long, env = "TAPSTR_RPC_PASSWORD", hide_env_values = true)]
+    rpc_password: Option<String>,
+    /// Relay URLs, comma-separated in the environment. Replaces the configured list.
+    #[arg(long = "relay", env = "TAPSTR_RELAYS", value_delimiter = ',')]
+    relays: Vec<String>,
+    /// `tracing` filter such as `info` or `tapstr=debug`.
+    #[arg(long, env = "TAPSTR_LOG")]
+    log_level: Option<String>,
+}
+
+/// Why the server refused to start.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("cannot read config {path}: {source}")]
+    Read { path: PathBuf, source: std::io::Error },
+    #[error("invalid config {path}: {source}")]
+    Parse { path: PathBuf, source: toml::de::Error },
+    #[error("TLS needs both a certificate and a key")]
+    TlsIncomplete,
+    #[error("TLS {what} {path} does not exist")]
+    TlsMissing { what: &'static str, path: PathBuf },
+    #[error("the mock chain only runs on regtest, not {0}")]
+    MockNetwork(Network),
+    #[error("the {0} backend needs a URL")]
+    MissingUrl(BackendKind),
+    #[error("backend URL {0} must start with http:// or https://")]
+    BackendUrl(String),
+    #[error("the bitcoind backend needs an RPC user and password")]
+    MissingRpcAuth,
+    #[error("relay URL {0} must start with ws:// or wss://")]
+    RelayUrl(String),
+    #[error("invalid log level {level:?}: {reason}")]
+    LogLevel { level: String, reason: String },
+    #[error("backend at {url} is unreachable: {reason}")]
+    BackendUnreachable { url: String, reason: String },
+    #[error("backend at {url} is on another network than {network} (genesis {genesis})")]
+    BackendNetwork { url: String, network: Network, genesis: BlockHash },
+}
+
+impl Config {
+    /// The file at `path`, or the defaults when there is none.
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let Some(path) = path else {
+            return Ok(Config::default());
+        };
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Read { path: path.to_path_buf(), source })?;
+        toml::from_str(&contents).map_err(|source| ConfigError::Parse { path: path.to_path_buf(), source })
+    }
+
+    /// Layer flags and environment variables (already merged by clap) over the file.
+    pub fn apply(&mut self, args: ServeArgs, network: Option<Network>) {
+        if let Some(listen) = args.listen {
+            self.listen = listen;
+        }
+        if args.tls_cert.is_some() || args.tls_key.is_some() {
+            let current = self.tls.take();
+            let cert = args.tls_cert.or_else(|| current.as_ref().map(|tls| tls.cert.clone()));
+            let key = args.tls_key.or_else(|| current.as_ref().map(|tls| tls.key.clone()));
+            // Half a pair is kept as an empty path so `validate` reports it.
+            self.tls = Some(TlsConfig { cert: cert.unwrap_or_default(), key: key.unwrap_or_default() });
+        }
+        if let Some(network) = network {
+            self.network = network;
+        }
+        if let Some(backend) = args.backend {
+            self.chain.backend = backend;
+        }
+        self.chain.url = args.backend_url.or(self.chain.url.take());
+        self.chain.rpc_user = args.rpc_user.or(self.chain.rpc_user.take());
+        self.chain.rpc_password = args.rpc_password.or(self.chain.rpc_password.take());
+        if !args.relays.is_empty() {
+            self.relays = args.relays;
+        }
+        if let Some(log_level) = args.log_level {
+            self.log_level = log_level;
+        }
+    }
+
+    /// Check every setting that can be checked without the network.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(tls) = &self.tls {
+            if tls.cert.as_os_str().is_empty() || tls.key.as_os_str().is_empty() {
+                return Err(ConfigError::TlsIncomplete);
+            }
+            for (what, path) in [("certificate", &tls.cert), ("key", &tls.key)] {
+                if !path.is_file() {
+                    return Err(ConfigError::TlsMissing { what, path: path.clone() });
+                }
+            }
+        }
+
+        match self.chain.backend {
+            BackendKind::Mock if self.network != Network::Regtest => return Err(ConfigError::MockNetwork(self.network)),
+            BackendKind::Mock => {}
+            backend => {
+                let url = self.chain.url.as_deref().ok_or(ConfigError::MissingUrl(backend))?;
+                if !(url.starts_with("http://") || url.starts_with("https://")) {
+                    return Err(ConfigError::BackendUrl(url.to_string()));
+                }
+                if backend == BackendKind::Bitcoind && (self.chain.rpc_user.is_none() || self.chain.rpc_password.is_none()) {
+                    return Err(ConfigError::MissingRpcAuth);
+                }
+            }
+        }
+
+        if let Some(relay) = self.relays.iter().find(|r| !(r.starts_with("ws://") || r.starts_with("wss://"))) {
+            return Err(ConfigError::RelayUrl(relay.clone()));
+        }
+
+        self.log_filter()?;
+        Ok(())
+    }
+
+    pub fn log_filter(&self) -> Result<EnvFilter, ConfigError> {
+        EnvFilter::from_str(&self.log_level).map_err(|e| ConfigError::LogLevel { level: self.log_level.clone(), reason: e.to_string() })
+    }
+
+    /// Scheme-qualified base URL the server is reachable at.
+    pub fn base_url(&self, scheme: &str) -> String {
+        let secure = if self.tls.is_some() { "s" } else { "" };
+        format!("{}{}://{}", scheme, secure, self.listen)
+    }
+
+    /// Connect to the configured chain backend and check that it follows `network`.
+    pub async fn connect_backend(&self) -> Result<Backend, ConfigError> {
+        let url = self.chain.url.clone().unwrap_or_default();
+        let backend = match self.chain.backend {
+            BackendKind::Mock => return Ok(Backend::Mock(MockChain::new())),
+            BackendKind::Esplora => Backend::Esplora(EsploraClient::new(&url)),
+            BackendKind::Bitcoind => Backend::Bitcoind(BitcoindRpc::new(
+                &url,
+                self.chain.rpc_user.as_deref().unwrap_or_default(),
+                self.chain.rpc_password.as_deref().unwrap_or_default(),
+            )),
+        };
+        let genesis = backend
+            .block_hash(0)
+            .await
+            .map_err(|e| ConfigError::BackendUnreachable { url: url.clone(), reason: e.to_string() })?;
+        if genesis != genesis_block(self.network).block_hash() {
+            return Err(ConfigError::BackendNetwork { url, network: self.network, genesis });
+        }
+        Ok(backend)
+    }
+}
+
+/// The chain backend chosen in the config.
+#[derive(Debug, Clone)]
+pub enum Backend {
+    Mock(MockChain),
+    Esplora(EsploraClient),
+    Bitcoind(BitcoindRpc),
+}
+
+impl Backend {
+    pub async fn tip_height(&self) -> anyhow::Result<u32> {
+        match self {
+            Backend::Mock(chain) => Ok(chain.height()),
+            Backend::Esplora(client) => client.tip_height().await,
+            Backend::Bitcoind(rpc) => rpc.tip_height().await,
+        }
+    }
+
+    async fn block_hash(&self, height: u32) -> anyhow::Result<BlockHash> {
+        match self {
+            Backend::Mock(_) => Ok(genesis_block(Network::Regtest).block_hash()),
+            Backend::Esplora(client) => client.block_hash(height).await,
+            Backend::Bitcoind(rpc) => rpc.block_hash(height).await,
+        }
+    }
+
+    pub fn mock(&self) -> Option<&MockChain> {
+        match self {
+            Backend::Mock(chain) => Some(chain),
+            _ => None,
+        }
+    }
+}
+
+impl ChainBackend for Backend {
+    async fn get_utxos(&self, script_pubkey: &Script) -> anyhow::Result<Vec<Utxo>> {
+        match self {
+            Backend::Mock(chain) => chain.get_utxos(script_pubkey).await,
+            Backend::Esplora(client) => client.get_utxos(script_pubkey).await,
+            Backend::Bitcoind(rpc) => rpc.get_utxos(script_pubkey).await,
+        }
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> anyhow::Result<Txid> {
+        match self {
+            Backend::Mock(chain) => chain.broadcast(tx).await,
+            Backend::Esplora(client) => client.broadcast(tx).await,
+            Backend::Bitcoind(rpc) => rpc.broadcast(tx).await,
+        }
+    }
+
+    async fn get_tx(&self, txid: &Txid) -> anyhow::Result<Option<Transaction>> {
+        match self {
+            Backend::Mock(chain) => chain.get_tx(txid).await,
+            Backend::Esplora(client) => client.get_tx(txid).await,
+            Backend::Bitcoind(rpc) => rpc.get_tx(txid).await,
+        }
+    }
+
+    async fn get_confirmations(&self, txid: &Txid) -> anyhow::Result<Option<u32>> {
+        match self {
+            Backend::Mock(chain) => chain.get_confirmations(txid).await,
+            Backend::Esplora(client) => client.get_confirmations(txid).await,
+            Backend::Bitcoind(rpc) => rpc.get_confirmations(txid).await,
+        }
+    }
+
+    async fn get_spending_tx(&self, outpoint: &OutPoint) -> anyhow::Result<Option<Transaction>> {
+        match self {
+            Backend::Mock(chain) => chain.get_spending_tx(outpoint).await,
+            Backend::Esplora(client) => client.get_spending_tx(outpoint).await,
+            Backend::Bitcoind(rpc) => rpc.get_spending_tx(outpoint).await,
+        }
+    }
+
+    async fn estimate_fee_rate(&self, target_blocks: u16) -> anyhow::Result<FeeRate> {
+        match self {
+            Backend::Mock(chain) => chain.estimate_fee_rate(target_blocks).await,
+            Backend::Esplora(client) => client.estimate_fee_rate(target_blocks).await,
+            Backend::Bitcoind(rpc) => rpc.estimate_fee_rate(target_blocks).await,
+        }
+    }
+}
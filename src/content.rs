@@ -0,0 +1,214 @@
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use chacha20poly1305::{aead::{Aead, KeyInit, Payload}, ChaCha20Poly1305, Key, Nonce};
+use rand::{rngs::OsRng, RngCore};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+const KEY_TAG: &[u8] = b"tapstr-content/v1";
+const PAD_TAG: &[u8] = b"tapstr-content-pad/v1";
+/// Length of the random salt sealed ahead of the plaintext.
+const SALT_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum ContentError {
+    #[error("secret does not match the content's adaptor point")]
+    WrongSecret,
+    #[error("content does not decrypt")]
+    Decrypt,
+    #[error("decrypted content does not match its commitment")]
+    Commitment,
+    #[error("content key is not recoverable with the adaptor secret")]
+    KeyProof,
+    #[error("invalid encrypted content: {0}")]
+    Format(String),
+}
+
+/// A secret scalar `k` encrypted to the adaptor point `T = t * G`, verifiably.
+///
+/// The key is padded with a multiple of `t`: `encrypted_key = k + h * t` with
+/// `h = H(T || K)`. Anyone can check `encrypted_key * G == K + h * T`, which proves that
+/// `t` recovers the discrete log of `key_point`; until `t` is revealed the pad hides `k`.
+/// Because the seller chose `t`, this public equation does the job of an ElGamal
+/// ciphertext with a DLEQ proof. On its own it sells any secret scalar with a known
+/// public key, such as a private key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiableKey {
+    /// `K = k * G`, the commitment to the key.
+    pub key_point: PublicKey,
+    /// `T`; must equal the offer pre-signature's adaptor point.
+    pub adaptor_point: PublicKey,
+    pub encrypted_key: SecretKey,
+}
+
+impl VerifiableKey {
+    pub fn encrypt(secp: &Secp256k1<secp256k1::All>, t: &SecretKey, k: &SecretKey) -> Self {
+        let key_point = k.public_key(secp);
+        let adaptor_point = t.public_key(secp);
+        let pad = t.mul_tweak(&pad_factor(&adaptor_point, &key_point)).expect("hash is a valid scalar");
+        let encrypted_key = k.add_tweak(&Scalar::from(pad)).expect("k + h * t is not zero");
+        VerifiableKey { key_point, adaptor_point, encrypted_key }
+    }
+
+    /// Check `encrypted_key * G == K + h * T`.
+    pub fn verify(&self, secp: &Secp256k1<secp256k1::All>) -> bool {
+        let Ok(padded) = self.adaptor_point.mul_tweak(secp, &pad_factor(&self.adaptor_point, &self.key_point)) else {
+            return false;
+        };
+        self.key_point.combine(&padded).is_ok_and(|expected| self.encrypted_key.public_key(secp) == expected)
+    }
+
+    pub fn decrypt(&self, secp: &Secp256k1<secp256k1::All>, t: &SecretKey) -> Result<SecretKey, ContentError> {
+        if t.public_key(secp) != self.adaptor_point {
+            return Err(ContentError::WrongSecret);
+        }
+        let pad = t.mul_tweak(&pad_factor(&self.adaptor_point, &self.key_point)).map_err(|_| ContentError::KeyProof)?;
+        let k = self.encrypted_key.add_tweak(&Scalar::from(pad.negate())).map_err(|_| ContentError::KeyProof)?;
+        if k.public_key(secp) != self.key_point {
+            return Err(ContentError::KeyProof);
+        }
+        Ok(k)
+    }
+}
+
+/// Content sold through a swap, sealed under a random key that is verifiably encrypted
+/// to the adaptor secret `t`.
+///
+/// Before locking, the buyer runs `verify` to check the key is recoverable with `t`; once
+/// `t` is learned from the completed event or the seller's claim, `decrypt` recovers the key
+/// and the content. `commitment` is `sha256(salt || plaintext)`, checked after decrypting:
+/// the key proof cannot show that the ciphertext holds the promised content. The random
+/// salt is sealed in front of the plaintext, so the commitment does not let anyone confirm
+/// a guess of short or predictable content before paying.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedContent {
+    pub key: VerifiableKey,
+    pub commitment: sha256::Hash,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+impl EncryptedContent {
+    pub fn encrypt(secp: &Secp256k1<secp256k1::All>, t: &SecretKey, plaintext: &[u8]) -> Self {
+        let k = SecretKey::new(&mut OsRng);
+        let mut salted = Zeroizing::new(vec![0u8; SALT_LEN]);
+        OsRng.fill_bytes(&mut salted);
+        salted.extend_from_slice(plaintext);
+        let mut content = EncryptedContent {
+            key: VerifiableKey::encrypt(secp, t, &k),
+            commitment: sha256::Hash::hash(&salted),
+            nonce: String::new(),
+            ciphertext: String::new(),
+        };
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let aad = content.associated_data();
+        let ciphertext = cipher(&k)
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &salted, aad: &aad })
+            .expect("content fits in one ChaCha20-Poly1305 message");
+        content.nonce = hex::encode(nonce);
+        content.ciphertext = hex::encode(ciphertext);
+        content
+    }
+
+    pub fn adaptor_point(&self) -> PublicKey {
+        self.key.adaptor_point
+    }
+
+    /// Whether `t` will recover the content key.
+    pub fn verify(&self, secp: &Secp256k1<secp256k1::All>) -> bool {
+        self.key.verify(secp)
+    }
+
+    /// Decrypt with `t` and check the plaintext against the commitment.
+    pub fn decrypt(&self, secp: &Secp256k1<secp256k1::All>, t: &SecretKey) -> Result<Vec<u8>, ContentError> {
+        let k = self.key.decrypt(secp, t)?;
+        let nonce = hex::decode(&self.nonce).map_err(|e| ContentError::Format(format!("nonce: {}", e)))?;
+        let ciphertext = hex::decode(&self.ciphertext).map_err(|e| ContentError::Format(format!("ciphertext: {}", e)))?;
+        if nonce.len() != 12 {
+            return Err(ContentError::Format(format!("{}-byte nonce", nonce.len())));
+        }
+        let aad = self.associated_data();
+        let salted = cipher(&k)
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &aad })
+            .map(Zeroizing::new)
+            .map_err(|_| ContentError::Decrypt)?;
+        if salted.len() < SALT_LEN || sha256::Hash::hash(&salted) != self.commitment {
+            return Err(ContentError::Commitment);
+        }
+        Ok(salted[SALT_LEN..].to_vec())
+    }
+
+    fn associated_data(&self) -> Vec<u8> {
+        [&self.key.key_point.serialize()[..], self.commitment.as_byte_array()].concat()
+    }
+}
+
+/// `h = sha256("tapstr-content-pad/v1" || T || K)`
+fn pad_factor(adaptor_point: &PublicKey, key_point: &PublicKey) -> Scalar {
+    let mut engine = sha256::Hash::engine();
+    engine.input(PAD_TAG);
+    engine.input(&adaptor_point.serialize());
+    engine.input(&key_point.serialize());
+    Scalar::from_be_bytes(sha256::Hash::from_engine(engine).to_byte_array()).expect("hash is below the curve order")
+}
+
+/// `sha256("tapstr-content/v1" || k)`
+fn cipher(k: &SecretKey) -> ChaCha20Poly1305 {
+    let mut engine = sha256::Hash::engine();
+    engine.input(KEY_TAG);
+    engine.input(&k.secret_bytes());
+    let key = Zeroizing::new(sha256::Hash::from_engine(engine).to_byte_array());
+    ChaCha20Poly1305::new(Key::from_slice(&key[..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_decrypts_with_t_and_matches_its_commitment() {
+        let secp = Secp256k1::new();
+        let t = SecretKey::new(&mut OsRng);
+        let content = EncryptedContent::encrypt(&secp, &t, b"yes");
+        assert!(content.verify(&secp));
+        assert_eq!(content.decrypt(&secp, &t).unwrap(), b"yes");
+        assert!(matches!(content.decrypt(&secp, &SecretKey::new(&mut OsRng)), Err(ContentError::WrongSecret)));
+    }
+
+    #[test]
+    fn commitment_does_not_reveal_guessable_content() {
+        let secp = Secp256k1::new();
+        let t = SecretKey::new(&mut OsRng);
+        let content = EncryptedContent::encrypt(&secp, &t, b"yes");
+        assert_ne!(content.commitment, sha256::Hash::hash(b"yes"));
+        assert_ne!(content.commitment, EncryptedContent::encrypt(&secp, &t, b"yes").commitment);
+    }
+
+    #[test]
+    fn key_is_verifiably_encrypted_to_t() {
+        let secp = Secp256k1::new();
+        let (t, k) = (SecretKey::new(&mut OsRng), SecretKey::new(&mut OsRng));
+        let key = VerifiableKey::encrypt(&secp, &t, &k);
+        assert!(key.verify(&secp));
+        assert_eq!(key.decrypt(&secp, &t).unwrap(), k);
+
+        let other = SecretKey::new(&mut OsRng).public_key(&secp);
+        assert!(!VerifiableKey { adaptor_point: other, ..key.clone() }.verify(&secp));
+        assert!(!VerifiableKey { key_point: other, ..key.clone() }.verify(&secp));
+        let tweaked = key.encrypted_key.add_tweak(&Scalar::ONE).unwrap();
+        assert!(!VerifiableKey { encrypted_key: tweaked, ..key }.verify(&secp));
+    }
+
+    #[test]
+    fn tampered_ciphertext_does_not_decrypt() {
+        let secp = Secp256k1::new();
+        let t = SecretKey::new(&mut OsRng);
+        let mut content = EncryptedContent::encrypt(&secp, &t, b"the goods");
+        let mut ciphertext = hex::decode(&content.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        content.ciphertext = hex::encode(ciphertext);
+        assert!(matches!(content.decrypt(&secp, &t), Err(ContentError::Decrypt)));
+    }
+}
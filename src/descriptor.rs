@@ -0,0 +1,147 @@
+use secp256k1::XOnlyPublicKey;
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use crate::bitcoin_utils::SwapOutput;
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+
+fn polymod(chk: u64, value: u64) -> u64 {
+    let top = chk >> 35;
+    let mut chk = ((chk & 0x7ffffffff) << 5) ^ value;
+    for (i, generator) in GENERATOR.iter().enumerate() {
+        if (top >> i) & 1 == 1 {
+            chk ^= generator;
+        }
+    }
+    chk
+}
+
+/// BIP380 descriptor checksum (the 8 characters after `#`).
+pub fn descriptor_checksum(desc: &str) -> Result<String> {
+    let mut chk = 1;
+    let mut groups = Vec::with_capacity(3);
+    for c in desc.chars() {
+        let pos = INPUT_CHARSET.find(c).ok_or_else(|| anyhow!("Invalid descriptor character {:?}", c))? as u64;
+        chk = polymod(chk, pos & 31);
+        groups.push(pos >> 5);
+        if groups.len() == 3 {
+            chk = polymod(chk, groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match groups.len() {
+        1 => chk = polymod(chk, groups[0]),
+        2 => chk = polymod(chk, groups[0] * 3 + groups[1]),
+        _ => {}
+    }
+    for _ in 0..8 {
+        chk = polymod(chk, 0);
+    }
+    chk ^= 1;
+    Ok((0..8).map(|i| CHECKSUM_CHARSET[((chk >> (5 * (7 - i))) & 31) as usize] as char).collect())
+}
+
+/// Append `#checksum` to a descriptor.
+pub fn add_descriptor_checksum(desc: &str) -> Result<String> {
+    Ok(format!("{}#{}", desc, descriptor_checksum(desc)?))
+}
+
+/// Split off and verify the checksum, if there is one.
+pub fn strip_descriptor_checksum(desc: &str) -> Result<&str> {
+    match desc.split_once('#') {
+        Some((body, checksum)) => {
+            let expected = descriptor_checksum(body)?;
+            if checksum != expected {
+                return Err(anyhow!("Descriptor checksum mismatch: got {}, expected {}", checksum, expected));
+            }
+            Ok(body)
+        }
+        None => Ok(desc),
+    }
+}
+
+fn strip_wrapper<'a>(s: &'a str, prefix: &str) -> Result<&'a str> {
+    s.strip_prefix(prefix)
+        .and_then(|rest| rest.strip_suffix(')'))
+        .ok_or_else(|| anyhow!("Expected {}...) in {}", prefix, s))
+}
+
+impl SwapOutput {
+    /// Watch-only descriptor for the swap output:
+    /// `tr(CLAIM_KEY,and_v(v:pk(BUYER_KEY),older(N)))#checksum`.
+    pub fn descriptor(&self) -> String {
+        let desc = format!(
+            "tr({},and_v(v:pk({}),older({})))",
+            self.claim_key,
+            self.buyer_pubkey,
+            self.refund_sequence.to_consensus_u32(),
+        );
+        add_descriptor_checksum(&desc).expect("descriptor uses only hex and ASCII punctuation")
+    }
+
+    /// Parse a descriptor produced by [`SwapOutput::descriptor`]. The checksum is optional
+    /// but verified when present; a single leaf wrapped in `{}` is accepted too.
+    pub fn from_descriptor(desc: &str) -> Result<Self> {
+        let body = strip_descriptor_checksum(desc.trim())?;
+        let inner = strip_wrapper(body, "tr(")?;
+        let (key, tree) = inner.split_once(',').ok_or_else(|| anyhow!("Swap descriptor has no refund leaf"))?;
+        let tree = tree.strip_prefix('{').and_then(|t| t.strip_suffix('}')).unwrap_or(tree);
+
+        let leaf = strip_wrapper(tree, "and_v(")?;
+        let (pk, older) = leaf.split_once(',').ok_or_else(|| anyhow!("Unrecognized refund leaf {}", tree))?;
+        let buyer = strip_wrapper(pk, "v:pk(")?;
+        let sequence: u32 = strip_wrapper(older, "older(")?.parse()?;
+        // Only BIP68 block counts: no time-based or disabled locks, and not 0.
+        let refund_blocks = u16::try_from(sequence)
+            .ok()
+            .filter(|blocks| *blocks > 0)
+            .ok_or_else(|| anyhow!("older({}) is not a relative timelock of 1 to 65535 blocks", sequence))?;
+
+        SwapOutput::new(XOnlyPublicKey::from_str(key)?, XOnlyPublicKey::from_str(buyer)?, refund_blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+    use bitcoin::Sequence;
+    use secp256k1::{Keypair, Secp256k1};
+
+    fn key() -> XOnlyPublicKey {
+        Keypair::new(&Secp256k1::new(), &mut OsRng).x_only_public_key().0
+    }
+
+    #[test]
+    fn checksum_matches_bip380() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(strip_descriptor_checksum("raw(deadbeef)#89f8spxm").unwrap(), "raw(deadbeef)");
+        assert!(strip_descriptor_checksum("raw(deadbeef)#89f8spxn").is_err());
+        assert!(descriptor_checksum("raw(deadbeef)\n").is_err());
+    }
+
+    #[test]
+    fn swap_descriptor_round_trips() {
+        let swap = SwapOutput::new(key(), key(), 144).unwrap();
+        let desc = swap.descriptor();
+        assert_eq!(SwapOutput::from_descriptor(&desc).unwrap(), swap);
+
+        let body = desc.split_once('#').unwrap().0;
+        assert_eq!(SwapOutput::from_descriptor(body).unwrap(), swap);
+        let wrapped = body.replacen(",and_v(", ",{and_v(", 1).replacen(")))", "))})", 1);
+        assert_eq!(SwapOutput::from_descriptor(&wrapped).unwrap(), swap);
+    }
+
+    #[test]
+    fn refund_timelock_must_be_a_block_count() {
+        assert!(SwapOutput::new(key(), key(), 0).is_err());
+        for older in [0u32, 65536, 0x0040_0001, 0x8000_0001] {
+            let desc = format!("tr({},and_v(v:pk({}),older({})))", key(), key(), older);
+            assert!(SwapOutput::from_descriptor(&desc).is_err(), "older({}) accepted", older);
+        }
+        let desc = format!("tr({},and_v(v:pk({}),older(65535)))", key(), key());
+        assert_eq!(SwapOutput::from_descriptor(&desc).unwrap().refund_sequence, Sequence::from_height(65535));
+    }
+}
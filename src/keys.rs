@@ -0,0 +1,181 @@
+use bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::Network;
+use bip39::Mnemonic;
+use rand::{rngs::OsRng, RngCore};
+use secp256k1::{Keypair, Secp256k1, SecretKey};
+use anyhow::{anyhow, Result};
+
+/// NIP-06 coin type for Nostr keys (`m/44'/1237'/...`).
+pub const NOSTR_COIN_TYPE: u32 = 1237;
+/// Change-level branch holding per-swap keys, next to BIP86's external (0) and internal (1) chains.
+pub const SWAP_BRANCH: u32 = 2;
+
+/// Keys of one swap, derived from its id.
+#[derive(Debug, Clone)]
+pub struct SwapKeys {
+    /// The buyer's key in the swap output (refund path).
+    pub bitcoin: Keypair,
+    /// The seller's adaptor secret `t`.
+    pub adaptor_secret: SecretKey,
+}
+
+/// Every key a swap party needs, derived from one BIP39 seed.
+///
+/// Wallet keys follow BIP86, the Nostr identity follows NIP-06, and swap keys are
+/// hardened children of both trees indexed by a hash of the swap id, so the
+/// mnemonic and the swap ids are enough to restore in-flight swaps.
+#[derive(Debug, Clone)]
+pub struct KeyManager {
+    mnemonic: Mnemonic,
+    master: Xpriv,
+    network: Network,
+}
+
+impl KeyManager {
+    /// A fresh 24-word mnemonic.
+    pub fn generate(network: Network) -> Result<Self> {
+        let mut entropy = [0u8; 32];
+        OsRng.fill_bytes(&mut entropy);
+        Self::new(Mnemonic::from_entropy(&entropy)?, "", network)
+    }
+
+    pub fn from_mnemonic(words: &str, passphrase: &str, network: Network) -> Result<Self> {
+        let mnemonic = Mnemonic::parse_normalized(words).map_err(|e| anyhow!("invalid mnemonic: {}", e))?;
+        Self::new(mnemonic, passphrase, network)
+    }
+
+    fn new(mnemonic: Mnemonic, passphrase: &str, network: Network) -> Result<Self> {
+        let master = Xpriv::new_master(network, &mnemonic.to_seed(passphrase))?;
+        Ok(KeyManager { mnemonic, master, network })
+    }
+
+    pub fn mnemonic(&self) -> &Mnemonic {
+        &self.mnemonic
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn derive(&self, path: &DerivationPath) -> Result<Keypair> {
+        let secp = Secp256k1::new();
+        Ok(self.master.derive_priv(&secp, path)?.to_keypair(&secp))
+    }
+
+    /// BIP86 coin type: 0 on mainnet, 1 on every test network.
+    pub fn coin_type(&self) -> u32 {
+        match self.network {
+            Network::Bitcoin => 0,
+            _ => 1,
+        }
+    }
+
+    /// `m/86'/coin'/account'/change/index`
+    pub fn bip86_path(&self, account: u32, change: bool, index: u32) -> Result<DerivationPath> {
+        Ok(DerivationPath::from(vec![
+            ChildNumber::from_hardened_idx(86)?,
+            ChildNumber::from_hardened_idx(self.coin_type())?,
+            ChildNumber::from_hardened_idx(account)?,
+            ChildNumber::from_normal_idx(change as u32)?,
+            ChildNumber::from_normal_idx(index)?,
+        ]))
+    }
+
+    /// `m/44'/1237'/account'/0/0` (NIP-06)
+    pub fn nostr_path(account: u32) -> Result<DerivationPath> {
+        Ok(DerivationPath::from(vec![
+            ChildNumber::from_hardened_idx(44)?,
+            ChildNumber::from_hardened_idx(NOSTR_COIN_TYPE)?,
+            ChildNumber::from_hardened_idx(account)?,
+            ChildNumber::from_normal_idx(0)?,
+            ChildNumber::from_normal_idx(0)?,
+        ]))
+    }
+
+    /// Key of a wallet address on the external BIP86 chain, e.g. for funding a lock.
+    pub fn wallet_keypair(&self, account: u32, index: u32) -> Result<Keypair> {
+        self.derive(&self.bip86_path(account, false, index)?)
+    }
+
+    /// Nostr identity of `account`, as in NIP-06.
+    pub fn nostr_keypair(&self, account: u32) -> Result<Keypair> {
+        self.derive(&Self::nostr_path(account)?)
+    }
+
+    /// Paths of the swap keys: `m/86'/coin'/account'/2'/a'/b'` for the Bitcoin key and
+    /// `m/44'/1237'/account'/2'/a'/b'` for the adaptor secret, where `a` and `b` are
+    /// 31-bit words of `sha256(swap_id)`.
+    pub fn swap_paths(&self, account: u32, swap_id: &str) -> Result<(DerivationPath, DerivationPath)> {
+        let mut engine = sha256::Hash::engine();
+        engine.input(swap_id.as_bytes());
+        let digest = sha256::Hash::from_engine(engine).to_byte_array();
+        let word = |i: usize| u32::from_be_bytes(digest[i..i + 4].try_into().expect("4 bytes")) & 0x7fff_ffff;
+        let suffix = [
+            ChildNumber::from_hardened_idx(SWAP_BRANCH)?,
+            ChildNumber::from_hardened_idx(word(0))?,
+            ChildNumber::from_hardened_idx(word(4))?,
+        ];
+        let bitcoin = DerivationPath::from(vec![
+            ChildNumber::from_hardened_idx(86)?,
+            ChildNumber::from_hardened_idx(self.coin_type())?,
+            ChildNumber::from_hardened_idx(account)?,
+        ])
+        .extend(suffix);
+        let nostr = DerivationPath::from(vec![
+            ChildNumber::from_hardened_idx(44)?,
+            ChildNumber::from_hardened_idx(NOSTR_COIN_TYPE)?,
+            ChildNumber::from_hardened_idx(account)?,
+        ])
+        .extend(suffix);
+        Ok((bitcoin, nostr))
+    }
+
+    /// Keys of the swap with id `swap_id` (the offer event id).
+    pub fn swap_keys(&self, account: u32, swap_id: &str) -> Result<SwapKeys> {
+        let (bitcoin_path, adaptor_path) = self.swap_paths(account, swap_id)?;
+        Ok(SwapKeys {
+            bitcoin: self.derive(&bitcoin_path)?,
+            adaptor_secret: self.derive(&adaptor_path)?.secret_key(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{Address, XOnlyPublicKey};
+    use std::str::FromStr;
+
+    #[test]
+    fn wallet_keys_match_bip86() {
+        let words = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let keys = KeyManager::from_mnemonic(words, "", Network::Bitcoin).unwrap();
+        let internal_key = keys.wallet_keypair(0, 0).unwrap().x_only_public_key().0;
+        let expected = XOnlyPublicKey::from_str("cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115").unwrap();
+        assert_eq!(internal_key, expected);
+        let address = Address::p2tr(&Secp256k1::new(), internal_key, None, Network::Bitcoin);
+        assert_eq!(address.to_string(), "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr");
+    }
+
+    #[test]
+    fn nostr_key_matches_nip06() {
+        let words = "leader monkey parrot ring guide accident before fence cannon height naive bean";
+        let keys = KeyManager::from_mnemonic(words, "", Network::Bitcoin).unwrap();
+        let secret = keys.nostr_keypair(0).unwrap().secret_key();
+        assert_eq!(hex::encode(secret.secret_bytes()), "7f7ff03d123792d6ac594bfa67bf6d0c0ab55b6b1fdb6249303fe861f1ccba9a");
+    }
+
+    #[test]
+    fn swap_keys_are_restored_from_the_mnemonic_and_swap_id() {
+        let keys = KeyManager::generate(Network::Regtest).unwrap();
+        let restored = KeyManager::from_mnemonic(&keys.mnemonic().to_string(), "", Network::Regtest).unwrap();
+        let (swap, again) = (keys.swap_keys(0, "swap a").unwrap(), restored.swap_keys(0, "swap a").unwrap());
+        assert_eq!((swap.bitcoin, swap.adaptor_secret), (again.bitcoin, again.adaptor_secret));
+
+        let other = keys.swap_keys(0, "swap b").unwrap();
+        assert_ne!(swap.bitcoin, other.bitcoin);
+        assert_ne!(swap.adaptor_secret, other.adaptor_secret);
+        assert_ne!(swap.bitcoin.secret_key(), swap.adaptor_secret);
+    }
+}
@@ -0,0 +1,387 @@
+use chacha20poly1305::{aead::{Aead, KeyInit, Payload}, ChaCha20Poly1305, Key, Nonce};
+use rand::{rngs::OsRng, RngCore};
+use secp256k1::SecretKey;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Idle time after which an unlocked keystore wipes its secrets from memory.
+pub const DEFAULT_AUTO_LOCK: Duration = Duration::from_secs(300);
+
+/// Longest the auto-lock thread sleeps before checking the keystore again.
+const AUTO_LOCK_POLL: Duration = Duration::from_secs(1);
+
+const VERSION: u32 = 1;
+const KDF: &str = "scrypt";
+const CIPHER: &str = "chacha20-poly1305";
+
+#[derive(Debug, Error)]
+pub enum KeystoreError {
+    #[error("keystore is locked")]
+    Locked,
+    #[error("wrong passphrase or corrupted keystore")]
+    Decrypt,
+    #[error("keystore {0} already exists")]
+    Exists(PathBuf),
+    #[error("unsupported keystore: {0}")]
+    Unsupported(String),
+    #[error("invalid scrypt parameters: {0}")]
+    Kdf(String),
+    #[error("keystore I/O: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid keystore: {0}")]
+    Format(#[from] serde_json::Error),
+}
+
+/// Cost of the scrypt key derivation. Stored in the file, so it can differ per keystore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScryptParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for ScryptParams {
+    fn default() -> Self {
+        ScryptParams {
+            log_n: scrypt::Params::RECOMMENDED_LOG_N,
+            r: scrypt::Params::RECOMMENDED_R,
+            p: scrypt::Params::RECOMMENDED_P,
+        }
+    }
+}
+
+/// Everything the keystore protects.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct KeystoreContents {
+    /// BIP39 mnemonic the party's keys derive from (see `KeyManager`).
+    pub mnemonic: Option<String>,
+    /// Long-lived keys by name, e.g. `nostr`.
+    pub identities: BTreeMap<String, SecretKey>,
+    /// Secrets of each swap, by swap id.
+    pub swaps: BTreeMap<String, SwapSecrets>,
+}
+
+/// Secrets of one swap.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SwapSecrets {
+    /// The seller's adaptor secret `t`.
+    pub adaptor_secret: Option<SecretKey>,
+    /// Nonce `k` of the seller's pre-signature. Reusing it for another message leaks the signing key.
+    pub presignature_nonce: Option<SecretKey>,
+    /// The buyer's key in the swap output.
+    pub swap_key: Option<SecretKey>,
+}
+
+impl Drop for KeystoreContents {
+    fn drop(&mut self) {
+        self.mnemonic.zeroize();
+        for key in self.identities.values_mut() {
+            key.non_secure_erase();
+        }
+        for swap in self.swaps.values_mut() {
+            for key in [&mut swap.adaptor_secret, &mut swap.presignature_nonce, &mut swap.swap_key].into_iter().flatten() {
+                key.non_secure_erase();
+            }
+        }
+    }
+}
+
+/// On-disk format. Only the KDF parameters are in the clear; they are bound to the ciphertext as associated data.
+#[derive(Clone, Serialize, Deserialize)]
+struct KeystoreFile {
+    version: u32,
+    kdf: String,
+    scrypt: ScryptParams,
+    salt: String,
+    cipher: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl KeystoreFile {
+    fn associated_data(&self) -> String {
+        format!("tapstr-keystore/{}/{}/{}/{}/{}/{}", self.version, self.kdf, self.scrypt.log_n, self.scrypt.r, self.scrypt.p, self.salt)
+    }
+}
+
+struct Unlocked {
+    key: Zeroizing<[u8; 32]>,
+    contents: KeystoreContents,
+    last_used: Instant,
+}
+
+/// A passphrase-encrypted file of identity keys and per-swap secrets.
+///
+/// The file is sealed with ChaCha20-Poly1305 under a key stretched from the passphrase
+/// with scrypt. While unlocked the secrets are held in memory until `lock`, or until
+/// the keystore has been idle for its auto-lock time. An idle keystore notices on its next
+/// use; `spawn_auto_lock` wipes a shared one on time even if nothing uses it again.
+pub struct Keystore {
+    path: PathBuf,
+    file: KeystoreFile,
+    auto_lock: Duration,
+    unlocked: Option<Unlocked>,
+}
+
+impl std::fmt::Debug for Keystore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keystore").field("path", &self.path).field("unlocked", &self.unlocked.is_some()).finish()
+    }
+}
+
+impl Keystore {
+    /// Write a new keystore at `path` and leave it unlocked.
+    pub fn create(path: impl Into<PathBuf>, passphrase: &str, params: ScryptParams, contents: KeystoreContents) -> Result<Self, KeystoreError> {
+        let path = path.into();
+        // Claim the path atomically; `save` then replaces the empty file.
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Err(KeystoreError::Exists(path)),
+            opened => drop(opened?),
+        }
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let file = KeystoreFile {
+            version: VERSION,
+            kdf: KDF.to_string(),
+            scrypt: params,
+            salt: hex::encode(salt),
+            cipher: CIPHER.to_string(),
+            nonce: String::new(),
+            ciphertext: String::new(),
+        };
+        let created = derive_key(passphrase, &salt, &params).and_then(|key| {
+            let mut keystore = Keystore {
+                path: path.clone(),
+                file,
+                auto_lock: DEFAULT_AUTO_LOCK,
+                unlocked: Some(Unlocked { key, contents, last_used: Instant::now() }),
+            };
+            keystore.save()?;
+            Ok(keystore)
+        });
+        if created.is_err() {
+            let _ = std::fs::remove_file(&path);
+        }
+        created
+    }
+
+    /// Read the keystore at `path`. It starts locked.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, KeystoreError> {
+        let path = path.into();
+        let file: KeystoreFile = serde_json::from_slice(&std::fs::read(&path)?)?;
+        if file.version != VERSION || file.kdf != KDF || file.cipher != CIPHER {
+            return Err(KeystoreError::Unsupported(format!("version {} with {} and {}", file.version, file.kdf, file.cipher)));
+        }
+        Ok(Keystore { path, file, auto_lock: DEFAULT_AUTO_LOCK, unlocked: None })
+    }
+
+    /// Idle time before the secrets are wiped, `DEFAULT_AUTO_LOCK` unless set.
+    pub fn with_auto_lock(mut self, auto_lock: Duration) -> Self {
+        self.auto_lock = auto_lock;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn unlock(&mut self, passphrase: &str) -> Result<(), KeystoreError> {
+        let salt = hex::decode(&self.file.salt).map_err(|e| KeystoreError::Unsupported(format!("salt: {}", e)))?;
+        let nonce = hex::decode(&self.file.nonce).map_err(|e| KeystoreError::Unsupported(format!("nonce: {}", e)))?;
+        let ciphertext = hex::decode(&self.file.ciphertext).map_err(|e| KeystoreError::Unsupported(format!("ciphertext: {}", e)))?;
+        if nonce.len() != 12 {
+            return Err(KeystoreError::Unsupported(format!("{}-byte nonce", nonce.len())));
+        }
+        let key = derive_key(passphrase, &salt, &self.file.scrypt)?;
+        let aad = self.file.associated_data();
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key[..]))
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: aad.as_bytes() })
+            .map(Zeroizing::new)
+            .map_err(|_| KeystoreError::Decrypt)?;
+        let contents = serde_json::from_slice(&plaintext)?;
+        self.unlocked = Some(Unlocked { key, contents, last_used: Instant::now() });
+        Ok(())
+    }
+
+    /// Wipe the decrypted secrets and the derived key from memory.
+    pub fn lock(&mut self) {
+        self.unlocked = None;
+    }
+
+    /// Lock if the keystore has been idle for longer than the auto-lock time. Returns whether it locked.
+    pub fn lock_if_idle(&mut self) -> bool {
+        let idle = self.unlocked.as_ref().is_some_and(|u| u.last_used.elapsed() >= self.auto_lock);
+        if idle {
+            self.lock();
+        }
+        idle
+    }
+
+    /// Lock `keystore` once it has been idle for its auto-lock time, without waiting for the
+    /// next access. The thread exits once every other handle to the keystore is dropped.
+    pub fn spawn_auto_lock(keystore: &Arc<Mutex<Keystore>>) -> std::thread::JoinHandle<()> {
+        let keystore = Arc::downgrade(keystore);
+        std::thread::spawn(move || loop {
+            let Some(keystore) = keystore.upgrade() else { return };
+            let wait = {
+                let mut keystore = keystore.lock().unwrap();
+                keystore.lock_if_idle();
+                let idle = keystore.unlocked.as_ref().map(|u| u.last_used.elapsed());
+                idle.map_or(AUTO_LOCK_POLL, |idle| keystore.auto_lock.saturating_sub(idle))
+            };
+            drop(keystore);
+            std::thread::sleep(wait.min(AUTO_LOCK_POLL));
+        })
+    }
+
+    pub fn is_locked(&mut self) -> bool {
+        self.lock_if_idle();
+        self.unlocked.is_none()
+    }
+
+    pub fn contents(&mut self) -> Result<&KeystoreContents, KeystoreError> {
+        Ok(&self.touch()?.contents)
+    }
+
+    /// Change the secrets and write the keystore back, encrypted under a fresh nonce.
+    pub fn update<T>(&mut self, f: impl FnOnce(&mut KeystoreContents) -> T) -> Result<T, KeystoreError> {
+        let result = f(&mut self.touch()?.contents);
+        self.save()?;
+        Ok(result)
+    }
+
+    /// Re-encrypt under a new passphrase and salt. The keystore must be unlocked.
+    pub fn change_passphrase(&mut self, passphrase: &str) -> Result<(), KeystoreError> {
+        self.touch()?;
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt, &self.file.scrypt)?;
+        self.file.salt = hex::encode(salt);
+        self.touch()?.key = key;
+        self.save()
+    }
+
+    fn touch(&mut self) -> Result<&mut Unlocked, KeystoreError> {
+        self.lock_if_idle();
+        let unlocked = self.unlocked.as_mut().ok_or(KeystoreError::Locked)?;
+        unlocked.last_used = Instant::now();
+        Ok(unlocked)
+    }
+
+    fn save(&mut self) -> Result<(), KeystoreError> {
+        let unlocked = self.unlocked.as_ref().ok_or(KeystoreError::Locked)?;
+        let plaintext = Zeroizing::new(serde_json::to_vec(&unlocked.contents)?);
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let aad = self.file.associated_data();
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&unlocked.key[..]))
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plaintext, aad: aad.as_bytes() })
+            .expect("keystore fits in one ChaCha20-Poly1305 message");
+        self.file.nonce = hex::encode(nonce);
+        self.file.ciphertext = hex::encode(ciphertext);
+
+        // Write a sibling file and rename it over the old one, so a crash never leaves half a keystore.
+        let tmp = self.path.with_extension("tmp");
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut out = options.open(&tmp)?;
+        out.write_all(&serde_json::to_vec_pretty(&self.file)?)?;
+        out.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: &ScryptParams) -> Result<Zeroizing<[u8; 32]>, KeystoreError> {
+    let params = scrypt::Params::new(params.log_n, params.r, params.p, 32).map_err(|e| KeystoreError::Kdf(e.to_string()))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key[..]).map_err(|e| KeystoreError::Kdf(e.to_string()))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap scrypt so the tests stay fast.
+    const TEST_PARAMS: ScryptParams = ScryptParams { log_n: 4, r: 8, p: 1 };
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("tapstr-keystore-{}.json", hex::encode(rand::random::<[u8; 8]>())))
+    }
+
+    fn contents() -> KeystoreContents {
+        let mut contents = KeystoreContents::default();
+        contents.identities.insert("nostr".to_string(), SecretKey::new(&mut OsRng));
+        contents
+    }
+
+    #[test]
+    fn create_never_overwrites_a_keystore() {
+        let path = temp_path();
+        Keystore::create(&path, "first", TEST_PARAMS, contents()).unwrap();
+        let before = std::fs::read(&path).unwrap();
+        assert!(matches!(Keystore::create(&path, "second", TEST_PARAMS, contents()), Err(KeystoreError::Exists(_))));
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn idle_keystore_locks_without_being_used() {
+        let path = temp_path();
+        let keystore = Keystore::create(&path, "passphrase", TEST_PARAMS, contents()).unwrap().with_auto_lock(Duration::from_millis(50));
+        let keystore = Arc::new(Mutex::new(keystore));
+        let watcher = Keystore::spawn_auto_lock(&keystore);
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(keystore.lock().unwrap().unlocked.is_none());
+
+        drop(keystore);
+        watcher.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn secrets_round_trip_only_with_the_passphrase() {
+        let path = temp_path();
+        let contents = contents();
+        let nostr_key = contents.identities["nostr"];
+        Keystore::create(&path, "passphrase", TEST_PARAMS, contents).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains(&hex::encode(nostr_key.secret_bytes())));
+
+        let mut keystore = Keystore::open(&path).unwrap();
+        assert!(matches!(keystore.contents(), Err(KeystoreError::Locked)));
+        assert!(matches!(keystore.unlock("wrong"), Err(KeystoreError::Decrypt)));
+        keystore.unlock("passphrase").unwrap();
+        assert_eq!(keystore.contents().unwrap().identities["nostr"], nostr_key);
+
+        keystore.change_passphrase("new passphrase").unwrap();
+        let mut reopened = Keystore::open(&path).unwrap();
+        assert!(matches!(reopened.unlock("passphrase"), Err(KeystoreError::Decrypt)));
+        reopened.unlock("new passphrase").unwrap();
+        assert_eq!(reopened.contents().unwrap().identities["nostr"], nostr_key);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn kdf_parameters_are_authenticated() {
+        let path = temp_path();
+        Keystore::create(&path, "passphrase", TEST_PARAMS, contents()).unwrap();
+        let mut file: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        file["scrypt"]["p"] = serde_json::json!(2);
+        std::fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        assert!(matches!(Keystore::open(&path).unwrap().unlock("passphrase"), Err(KeystoreError::Decrypt)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
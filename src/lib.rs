@@ -1,11 +1,41 @@
 pub mod adaptor;
+pub mod api;
+pub mod attestation;
 pub mod bitcoin_utils;
+pub mod chain;
+pub mod coin_selection;
+pub mod content;
 pub mod crypto;
+pub mod descriptor;
+pub mod keys;
+pub mod keystore;
+pub mod network;
 pub mod nostr_utils;
+#[cfg(feature = "relay")]
+pub mod relay;
+pub mod remote_signer;
+pub mod signer;
+pub mod spend;
 pub mod tapstr;
+pub mod validation;
 
 pub use adaptor::*;
+pub use api::*;
+pub use attestation::*;
 pub use bitcoin_utils::*;
+pub use chain::*;
+pub use coin_selection::*;
+pub use content::*;
 pub use crypto::*;
+pub use descriptor::*;
+pub use keys::*;
+pub use keystore::*;
+pub use network::*;
 pub use nostr_utils::*;
-pub use tapstr::*;
\ No newline at end of file
+#[cfg(feature = "relay")]
+pub use relay::*;
+pub use remote_signer::*;
+pub use signer::*;
+pub use spend::*;
+pub use tapstr::*;
+pub use validation::*;
\ No newline at end of file
@@ -1,41 +1,990 @@
+mod cli;
+mod config;
+
 use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{sse::{Event as SseEvent, KeepAlive, Sse}, Html, Json},
     routing::{get, post},
-    Router, response::Html,
+    Router,
 };
-use secp256k1::{Secp256k1, SecretKey, Message, Keypair, hashes::sha256};
-use bitcoin::{Amount, Txid, hashes::Hash};
-use rand::rngs::OsRng;
-use std::str::FromStr;
-use ::tapstr::adaptor;
+use secp256k1::{Secp256k1, Keypair, hashes::sha256, scalar::Scalar};
+use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
+use clap::Parser;
+use bitcoin::{Amount, FeeRate, Network, ScriptBuf, TapSighashType, hashes::Hash};
+use futures_util::stream::{self, Stream, StreamExt};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use config::{Backend, BackendKind, Config, ServeArgs};
+use ::tapstr::adaptor::AdaptorSignature;
+use ::tapstr::api;
 use ::tapstr::bitcoin_utils;
+use ::tapstr::chain::{ChainBackend, MockChain};
+use ::tapstr::content::EncryptedContent;
+use ::tapstr::keys::{KeyManager, SwapKeys};
+use ::tapstr::network::NetworkParams;
+use ::tapstr::nostr_utils;
+use ::tapstr::signer::AdaptorSigner;
 use tapstr::tapstr;
+use nostr::{Event, Kind, UnsignedEvent};
+#[cfg(feature = "relay")]
+use ::tapstr::relay::Relay;
+
+/// Name of the in-process connection to the embedded relay; the URL itself is not dialled.
+#[cfg(feature = "relay")]
+const LOCAL_RELAY_URL: &str = "ws://127.0.0.1:3000/relay";
+
+/// The demo chain is an in-memory regtest.
+const NETWORK: Network = Network::Regtest;
+
+/// While a lock waits to confirm, the demo chain mines a block this often.
+const DEMO_BLOCK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Feerate of demo refunds.
+const REFUND_FEE_RATE: FeeRate = FeeRate::from_sat_per_vb_unchecked(2);
+
+/// Demo sessions and parties older than this are dropped whenever a new one is created.
+const DEMO_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone)]
+struct AppState {
+    config: Arc<Config>,
+    backend: Backend,
+    #[cfg(feature = "relay")]
+    relay: Relay,
+    sessions: Sessions,
+    /// Coordinator, chain and parties of the two-party demo.
+    api: api::SwapApi<Backend>,
+    chain: MockChain,
+    sellers: Parties<SellerParty>,
+    buyers: Parties<BuyerParty>,
+}
+
+impl AppState {
+    fn new(config: Config, backend: Backend) -> Self {
+        AppState {
+            api: api::SwapApi::new(config.network, backend.clone()).with_relays(config.relays.clone()),
+            chain: backend.mock().cloned().unwrap_or_default(),
+            config: Arc::new(config),
+            backend,
+            #[cfg(feature = "relay")]
+            relay: Relay::new(),
+            sessions: Arc::default(),
+            sellers: Arc::default(),
+            buyers: Arc::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum SessionStatus {
+    Created,
+    Offered,
+    Locked,
+    Confirming,
+    Confirmed,
+    Published,
+    Refunding,
+    Completed,
+    Refunded,
+    Aborted,
+    Failed,
+}
+
+impl SessionStatus {
+    fn is_finished(self) -> bool {
+        matches!(self, SessionStatus::Completed | SessionStatus::Refunded | SessionStatus::Aborted | SessionStatus::Failed)
+    }
+}
+
+/// What goes wrong in a demo run, if anything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Scenario {
+    #[default]
+    HappyPath,
+    InvalidPresignature,
+    SellerNeverPublishes,
+    BuyerNeverLocks,
+    RefundTimelockExpires,
+    WrongEvent,
+}
+
+impl Scenario {
+    const ALL: [Scenario; 6] = [
+        Scenario::HappyPath,
+        Scenario::InvalidPresignature,
+        Scenario::SellerNeverPublishes,
+        Scenario::BuyerNeverLocks,
+        Scenario::RefundTimelockExpires,
+        Scenario::WrongEvent,
+    ];
+
+    fn describe(self) -> &'static str {
+        match self {
+            Scenario::HappyPath => "Happy path",
+            Scenario::InvalidPresignature => "Seller sends an invalid pre-signature",
+            Scenario::SellerNeverPublishes => "Seller never publishes",
+            Scenario::BuyerNeverLocks => "Buyer never locks",
+            Scenario::RefundTimelockExpires => "Refund timelock expires before the seller publishes",
+            Scenario::WrongEvent => "Seller publishes a different event",
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct NewSession {
+    #[serde(default)]
+    scenario: Scenario,
+}
 
+/// What clients see of a session.
+#[derive(Clone, Serialize)]
 struct SwapState {
+    id: String,
+    scenario: Scenario,
+    status: SessionStatus,
     logs: Vec<String>,
-    status: String,
+    details: Vec<(String, String)>,
+}
+
+/// One pushed update: the new status and the log line that came with it.
+#[derive(Clone, Serialize)]
+struct Progress {
+    status: SessionStatus,
+    log: String,
+}
+
+/// Keys, chain and artefacts of one demo swap.
+struct Protocol {
+    scenario: Scenario,
+    /// Each side's seed. `t` and the buyer's swap key derive from it and the event id.
+    seller_keys: KeyManager,
+    buyer_keys: KeyManager,
+    seller_keypair: Keypair,
+    buyer: tapstr::Buyer,
+    chain: MockChain,
+    unsigned_event: Option<UnsignedEvent>,
+    adaptor_sig: Option<AdaptorSignature>,
+    completed_event: Option<Event>,
+    #[cfg(feature = "relay")]
+    relay: Relay,
+}
+
+impl Protocol {
+    /// Keys of this swap for one side, once the seller has drafted the event.
+    fn swap_keys(&self, keys: &KeyManager) -> anyhow::Result<SwapKeys> {
+        let event = self.unsigned_event.as_ref().ok_or_else(|| anyhow::anyhow!("No event"))?;
+        keys.swap_keys(0, &event.id.to_hex())
+    }
+}
+
+type Sessions = Arc<Mutex<HashMap<String, Arc<Session>>>>;
+
+/// A swap advanced one protocol step at a time, with progress broadcast to subscribers.
+struct Session {
+    created: Instant,
+    state: Mutex<SwapState>,
+    progress: broadcast::Sender<Progress>,
+    protocol: Arc<tokio::sync::Mutex<Protocol>>,
+}
+
+impl Session {
+    fn snapshot(&self) -> SwapState {
+        self.state.lock().unwrap().clone()
+    }
+
+    fn status(&self) -> SessionStatus {
+        self.state.lock().unwrap().status
+    }
+
+    /// Record a log line and broadcast it. Sent under the state lock so a subscriber
+    /// taking a snapshot never misses or repeats an update.
+    fn update(&self, status: SessionStatus, log: String) {
+        let mut state = self.state.lock().unwrap();
+        tracing::info!(session = %state.id, "{}", log);
+        state.status = status;
+        state.logs.push(log.clone());
+        let _ = self.progress.send(Progress { status, log });
+    }
+
+    fn detail(&self, name: &str, value: String) {
+        self.state.lock().unwrap().details.push((name.to_string(), value));
+    }
 }
 
 #[tokio::main]
-async fn main() {
-    let app = Router::new()
+async fn main() -> anyhow::Result<()> {
+    let cli = cli::Cli::parse();
+    match cli.command {
+        None => serve(cli.config.as_deref(), cli.network, cli.serve).await,
+        Some(cli::Command::Serve(args)) => serve(cli.config.as_deref(), cli.network, args).await,
+        Some(command) => {
+            let network = match cli.network {
+                Some(network) => network,
+                None => Config::load(cli.config.as_deref())?.network,
+            };
+            cli::run(command, network).await
+        }
+    }
+}
+
+async fn serve(config_path: Option<&std::path::Path>, network: Option<Network>, args: ServeArgs) -> anyhow::Result<()> {
+    let mut config = Config::load(config_path)?;
+    config.apply(args, network);
+    config.validate()?;
+    tracing_subscriber::fmt().with_env_filter(config.log_filter()?).init();
+    let backend = config.connect_backend().await?;
+    let state = AppState::new(config.clone(), backend);
+
+    let app = Router::new().route("/status", get(get_status));
+    let app = if state.backend.mock().is_some() { app.merge(demo_routes()) } else { app };
+    #[cfg(feature = "relay")]
+    let app = app.nest_service("/relay", state.relay.router());
+    let app = app.nest_service(api::API_PREFIX, state.api.router()).with_state(state);
+
+    let tls = match &config.tls {
+        Some(tls) => {
+            let _ = rustls::crypto::ring::default_provider().install_default();
+            let rustls = RustlsConfig::from_pem_file(&tls.cert, &tls.key)
+                .await
+                .with_context(|| format!("Cannot load TLS certificate {} and key {}", tls.cert.display(), tls.key.display()))?;
+            Some(rustls)
+        }
+        None => None,
+    };
+    let listener = tokio::net::TcpListener::bind(config.listen).await.with_context(|| format!("Cannot listen on {}", config.listen))?;
+
+    tracing::info!("Serving {} with the {} chain backend", config.network, config.chain.backend);
+    if config.chain.backend == BackendKind::Mock {
+        tracing::info!("Atomic Swap Demo UI running at {}", config.base_url("http"));
+    } else {
+        tracing::info!("Demo pages are off: they need the mock chain backend");
+    }
+    tracing::info!("JSON API available under {}{}", config.base_url("http"), api::API_PREFIX);
+    #[cfg(feature = "relay")]
+    tracing::info!("Embedded Nostr relay listening at {}/relay", config.base_url("ws"));
+
+    match tls {
+        Some(tls) => axum_server::from_tcp_rustls(listener.into_std()?, tls).serve(app.into_make_service()).await?,
+        None => axum::serve(listener, app).await?,
+    }
+    Ok(())
+}
+
+/// What the server runs with, for operators and health checks.
+#[derive(Serialize)]
+struct ServerStatus {
+    network: Network,
+    backend: BackendKind,
+    tip_height: Option<u32>,
+    relays: Vec<String>,
+    demo: bool,
+}
+
+#[axum::debug_handler]
+async fn get_status(State(state): State<AppState>) -> Json<ServerStatus> {
+    let tip_height = match state.backend.tip_height().await {
+        Ok(height) => Some(height),
+        Err(e) => {
+            tracing::warn!("Chain backend tip unavailable: {}", e);
+            None
+        }
+    };
+    Json(ServerStatus {
+        network: state.config.network,
+        backend: state.config.chain.backend,
+        tip_height,
+        relays: state.config.relays.clone(),
+        demo: state.backend.mock().is_some(),
+    })
+}
+
+/// Pages and endpoints of the demos, which run on the in-memory regtest chain.
+fn demo_routes() -> Router<AppState> {
+    Router::new()
         .route("/", get(get_ui))
-        .route("/start", post(start_swap));
+        .route("/sessions", post(create_session))
+        .route("/sessions/:id", get(get_session))
+        .route("/sessions/:id/advance", post(advance_session))
+        .route("/sessions/:id/run", post(run_session))
+        .route("/sessions/:id/events", get(session_events))
+        .route("/seller", get(get_seller_ui))
+        .route("/seller/parties", post(create_seller))
+        .route("/seller/parties/:id", get(get_seller))
+        .route("/seller/parties/:id/offer", post(seller_offer))
+        .route("/seller/parties/:id/reveal", post(seller_reveal))
+        .route("/buyer", get(get_buyer_ui))
+        .route("/buyer/parties", post(create_buyer))
+        .route("/buyer/parties/:id", get(get_buyer))
+        .route("/buyer/parties/:id/accept", post(buyer_accept))
+        .route("/buyer/parties/:id/lock", post(buyer_lock))
+        .route("/buyer/parties/:id/extract", post(buyer_extract))
+}
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await.unwrap();
-    println!("Atomic Swap Demo UI running at http://127.0.0.1:3000");
-    axum::serve(listener, app).await.unwrap();
+fn find_session(state: &AppState, id: &str) -> Result<Arc<Session>, api::ApiError> {
+    state
+        .sessions
+        .lock()
+        .unwrap()
+        .get(id)
+        .cloned()
+        .ok_or_else(|| api::ApiError::NotFound(id.to_string()))
 }
 
 #[axum::debug_handler]
-async fn get_ui() -> Html<String> {
-    let html = r#"
-    <!DOCTYPE html>
-    <html lang="en">
-    <head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Atomic Swap Demo</title>
-    <style>
+async fn create_session(State(state): State<AppState>, body: Option<Json<NewSession>>) -> (StatusCode, Json<SwapState>) {
+    let scenario = body.map(|Json(body)| body.scenario).unwrap_or_default();
+    let mut rng = OsRng;
+    let mut id_bytes = [0u8; 8];
+    rng.fill_bytes(&mut id_bytes);
+    let id = hex::encode(id_bytes);
+
+    let seller_keys = KeyManager::generate(NETWORK).expect("fresh entropy makes a valid seed");
+    let buyer_keys = KeyManager::generate(NETWORK).expect("fresh entropy makes a valid seed");
+    let protocol = Protocol {
+        scenario,
+        seller_keypair: seller_keys.nostr_keypair(0).expect("NIP-06 path is valid"),
+        seller_keys,
+        buyer_keys,
+        buyer: tapstr::Buyer::new(NETWORK),
+        chain: MockChain::new(),
+        unsigned_event: None,
+        adaptor_sig: None,
+        completed_event: None,
+        #[cfg(feature = "relay")]
+        relay: state.relay.clone(),
+    };
+    let session = Arc::new(Session {
+        created: Instant::now(),
+        state: Mutex::new(SwapState { id: id.clone(), scenario, status: SessionStatus::Created, logs: vec![], details: vec![] }),
+        progress: broadcast::channel(64).0,
+        protocol: Arc::new(tokio::sync::Mutex::new(protocol)),
+    });
+    session.update(SessionStatus::Created, format!("Scenario: {}. Seller and Buyer established on {}.", scenario.describe(), NETWORK));
+    let mut sessions = state.sessions.lock().unwrap();
+    sessions.retain(|_, session| session.created.elapsed() < DEMO_TTL);
+    sessions.insert(id, session.clone());
+    drop(sessions);
+    (StatusCode::CREATED, Json(session.snapshot()))
+}
+
+#[axum::debug_handler]
+async fn get_session(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<SwapState>, api::ApiError> {
+    Ok(Json(find_session(&state, &id)?.snapshot()))
+}
+
+/// Start the next protocol step in the background. Progress arrives on `/events`.
+#[axum::debug_handler]
+async fn advance_session(State(state): State<AppState>, Path(id): Path<String>) -> Result<(StatusCode, Json<SwapState>), api::ApiError> {
+    start_steps(find_session(&state, &id)?, false)
+}
+
+/// Run every remaining step in the background.
+#[axum::debug_handler]
+async fn run_session(State(state): State<AppState>, Path(id): Path<String>) -> Result<(StatusCode, Json<SwapState>), api::ApiError> {
+    start_steps(find_session(&state, &id)?, true)
+}
+
+fn start_steps(session: Arc<Session>, to_completion: bool) -> Result<(StatusCode, Json<SwapState>), api::ApiError> {
+    if session.status().is_finished() {
+        return Err(api::ApiError::Conflict("session has finished".to_string()));
+    }
+    let Ok(mut protocol) = session.protocol.clone().try_lock_owned() else {
+        return Err(api::ApiError::Conflict("a step is already running".to_string()));
+    };
+
+    let runner = session.clone();
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = advance(&runner, &mut protocol).await {
+                runner.update(SessionStatus::Failed, format!("Step failed: {}", e));
+                break;
+            }
+            if !to_completion || runner.status().is_finished() {
+                break;
+            }
+        }
+    });
+    Ok((StatusCode::ACCEPTED, Json(session.snapshot())))
+}
+
+/// Server-Sent Events: a `snapshot` of the session, then one `progress` event per update.
+/// A session that has finished by the time its stream closes is forgotten.
+#[axum::debug_handler]
+async fn session_events(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, api::ApiError> {
+    let session = find_session(&state, &id)?;
+    let (snapshot, receiver) = {
+        let state = session.state.lock().unwrap();
+        (state.clone(), session.progress.subscribe())
+    };
+    let guard = ForgetWhenFinished { sessions: state.sessions.clone(), id, session };
+
+    let first = stream::once(async move { Ok(SseEvent::default().event("snapshot").json_data(snapshot).unwrap()) });
+    let updates = stream::unfold((receiver, guard), |(mut receiver, guard)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(progress) => return Some((Ok(SseEvent::default().event("progress").json_data(progress).unwrap()), (receiver, guard))),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Ok(Sse::new(first.chain(updates)).keep_alive(KeepAlive::default()))
+}
+
+/// Held by a session's event stream: removes the session if it has finished when the stream closes.
+struct ForgetWhenFinished {
+    sessions: Sessions,
+    id: String,
+    session: Arc<Session>,
+}
+
+impl Drop for ForgetWhenFinished {
+    fn drop(&mut self) {
+        if self.session.status().is_finished() {
+            self.sessions.lock().unwrap().remove(&self.id);
+        }
+    }
+}
+
+/// Run the protocol step that follows the session's current status.
+async fn advance(session: &Session, p: &mut Protocol) -> anyhow::Result<()> {
+    let secp = Secp256k1::new();
+    let params = p.buyer.params();
+    let seller_pubkey = p.seller_keypair.x_only_public_key().0;
+
+    match session.status() {
+        // Seller drafts a Nostr event and pre-signs its id
+        SessionStatus::Created => {
+            let content = "Buy this digital item".to_string();
+            let unsigned_event = nostr_utils::create_unsigned_event(&seller_pubkey, Kind::TextNote, &content, vec![])?;
+            let event_id = unsigned_event.id;
+            p.unsigned_event = Some(unsigned_event.clone());
+            let t = p.swap_keys(&p.seller_keys)?.adaptor_secret;
+            let mut adaptor_sig = p.seller_keypair.presign_event(&unsigned_event, &t.public_key(&secp)).await?;
+            if p.scenario == Scenario::InvalidPresignature {
+                adaptor_sig.s = adaptor_sig.s.add_tweak(&Scalar::ONE)?;
+                session.update(SessionStatus::Created, "Seller tampered with s before sending the pre-signature.".to_string());
+            }
+            session.detail("Seller Public Key", seller_pubkey.to_string());
+            session.detail("Seller Mnemonic", p.seller_keys.mnemonic().to_string());
+            session.detail("Buyer Mnemonic", p.buyer_keys.mnemonic().to_string());
+            let (bitcoin_path, adaptor_path) = p.buyer_keys.swap_paths(0, &event_id.to_hex())?;
+            session.detail("Swap Key Paths", format!("buyer key {}, adaptor secret {}", bitcoin_path, adaptor_path));
+            session.detail("Buyer Public Key", p.swap_keys(&p.buyer_keys)?.bitcoin.x_only_public_key().0.to_string());
+            session.detail("Adaptor Signature", format!("Nonce Point: {:?}, s: {:?}", adaptor_sig.nonce_point, adaptor_sig.s));
+            session.update(SessionStatus::Offered, format!("Seller created adaptor signature for event {}.", event_id));
+            p.adaptor_sig = Some(adaptor_sig);
+        }
+        // Buyer funds and broadcasts the swap lock
+        SessionStatus::Offered => {
+            let adaptor_sig = p.adaptor_sig.as_ref().ok_or_else(|| anyhow::anyhow!("No adaptor signature"))?;
+            if !adaptor_sig.verify(&secp) {
+                session.update(
+                    SessionStatus::Aborted,
+                    "Buyer's verify rejected the pre-signature (s·G ≠ R' − T + e·P), so it would never complete to a valid \
+                     event signature. Buyer refuses to lock; no funds were at risk."
+                        .to_string(),
+                );
+                return Ok(());
+            }
+            session.update(SessionStatus::Offered, "Buyer verified the adaptor signature before locking.".to_string());
+            if p.scenario == Scenario::BuyerNeverLocks {
+                session.update(
+                    SessionStatus::Aborted,
+                    "Buyer walked away without locking. With no confirmed lock the seller never completes the signature, \
+                     so the event and t stay private; the seller only gave away a pre-signature."
+                        .to_string(),
+                );
+                return Ok(());
+            }
+            // Commitment: hash of the adaptor s for simplicity
+            let commitment = sha256::Hash::const_hash(adaptor_sig.s.as_ref());
+            let amount = Amount::from_sat(10000);
+            let buyer_pubkey = p.swap_keys(&p.buyer_keys)?.bitcoin.x_only_public_key().0;
+            let buyer_script = ScriptBuf::new_p2tr(&secp, p.buyer_keys.wallet_keypair(0, 0)?.x_only_public_key().0, None);
+            let funding = p.chain.fund(buyer_script, amount);
+
+            let tweaked_key = bitcoin_utils::create_nostr_signature_lock_script(*commitment.as_byte_array(), seller_pubkey)?;
+            let swap_output = bitcoin_utils::SwapOutput::new(tweaked_key, buyer_pubkey, params.refund_blocks)?;
+            p.buyer.create_swap_locking_transaction(funding.txid, funding.vout, amount, swap_output)?;
+            let lock_txid = p.buyer.broadcast_locking_transaction(&p.chain).await?;
+            session.detail("Network", NETWORK.to_string());
+            session.detail("Swap Address", swap_output.address(NETWORK)?.to_string());
+            session.detail("Descriptor", p.buyer.lock_descriptor().unwrap_or_default());
+            session.update(
+                SessionStatus::Locked,
+                format!("Buyer broadcast locking transaction {} for {} (refundable after {} blocks).", lock_txid, amount, params.refund_blocks),
+            );
+        }
+        // Wait for the lock to confirm while the demo chain mines
+        SessionStatus::Locked => {
+            let txid = p.buyer.lock_outpoint().ok_or_else(|| anyhow::anyhow!("No locking transaction"))?.txid;
+            session.update(SessionStatus::Confirming, format!("Waiting for {} confirmation(s) of the lock.", params.min_confirmations));
+            let chain = p.chain.clone();
+            let miner = tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(DEMO_BLOCK_INTERVAL).await;
+                    chain.mine(1);
+                }
+            });
+            let mut seen = 0;
+            while seen < params.min_confirmations {
+                let count = p.chain.get_confirmations(&txid).await?.unwrap_or(0);
+                if count > seen {
+                    seen = count;
+                    session.update(
+                        SessionStatus::Confirming,
+                        format!("Lock has {}/{} confirmation(s) at height {}.", seen, params.min_confirmations, p.chain.height()),
+                    );
+                }
+                tokio::time::sleep(Duration::from_millis(250)).await;
+            }
+            miner.abort();
+            session.update(SessionStatus::Confirmed, format!("Locking transaction {} confirmed.", txid));
+        }
+        // Buyer checks the pre-signature; seller completes and publishes the event
+        SessionStatus::Confirmed => {
+            let adaptor_sig = p.adaptor_sig.as_ref().ok_or_else(|| anyhow::anyhow!("No adaptor signature"))?;
+            let unsigned_event = p.unsigned_event.clone().ok_or_else(|| anyhow::anyhow!("No event"))?;
+            let completed_event = match p.scenario {
+                Scenario::SellerNeverPublishes => {
+                    session.update(SessionStatus::Refunding, "Seller goes silent and never publishes the event.".to_string());
+                    return Ok(());
+                }
+                Scenario::RefundTimelockExpires => {
+                    session.update(SessionStatus::Refunding, "Seller holds the event back, waiting too long to publish.".to_string());
+                    return Ok(());
+                }
+                Scenario::WrongEvent => {
+                    // A valid signature from the seller's key, but over an event nobody pre-signed.
+                    let keys = nostr::Keys::new(nostr::secp256k1::SecretKey::from_slice(&p.seller_keypair.secret_bytes())?);
+                    let event = nostr_utils::create_signed_event(&keys, Kind::TextNote, "Something else entirely", vec![]);
+                    session.update(SessionStatus::Confirmed, format!("Seller signs a different event {} with a fresh nonce.", event.id));
+                    event
+                }
+                _ => nostr_utils::complete_adaptor_event(unsigned_event, adaptor_sig, &p.swap_keys(&p.seller_keys)?.adaptor_secret)?,
+            };
+
+            #[cfg(feature = "relay")]
+            {
+                let mut seller_client = nostr_utils::RelayClient::new(p.relay.clone(), vec![LOCAL_RELAY_URL.to_string()]);
+                seller_client.connect().await?;
+                let accepted = seller_client.publish(&completed_event, Duration::from_secs(5)).await?;
+                session.update(SessionStatus::Published, format!("Seller published event {} to {} relay(s).", completed_event.id, accepted.len()));
+            }
+            #[cfg(not(feature = "relay"))]
+            session.update(SessionStatus::Published, "Seller published the Nostr event.".to_string());
+            p.completed_event = Some(completed_event);
+        }
+        // Buyer observes the event and extracts t
+        SessionStatus::Published => {
+            let adaptor_sig = p.adaptor_sig.as_ref().ok_or_else(|| anyhow::anyhow!("No adaptor signature"))?;
+            #[cfg(feature = "relay")]
+            let observed_event = {
+                let mut buyer_client = nostr_utils::RelayClient::new(p.relay.clone(), vec![LOCAL_RELAY_URL.to_string()]);
+                buyer_client.connect().await?;
+                let filter = nostr_utils::completed_event_filter(&seller_pubkey, Kind::TextNote, &[])?;
+                let event = buyer_client
+                    .wait_for_event(filter, Duration::from_secs(5))
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("Completed event not seen on the relay"))?;
+                session.update(SessionStatus::Published, format!("Buyer received event {} from the relay.", event.id));
+                event
+            };
+            #[cfg(not(feature = "relay"))]
+            let observed_event = p.completed_event.clone().ok_or_else(|| anyhow::anyhow!("No completed event"))?;
+
+            let extracted_t = match nostr_utils::extract_secret_from_event(adaptor_sig, &observed_event) {
+                Ok(t) => t,
+                Err(e) => {
+                    session.update(
+                        SessionStatus::Refunding,
+                        format!("No secret in the published event: {}. Without t the buyer falls back to the refund.", e),
+                    );
+                    return Ok(());
+                }
+            };
+            session.detail("Revealed Secret", format!("t: {:?}", extracted_t));
+            session.update(SessionStatus::Completed, format!("Buyer verified the secret: {}", extracted_t == p.swap_keys(&p.seller_keys)?.adaptor_secret));
+        }
+        // Buyer reclaims the lock through the timelocked leaf
+        SessionStatus::Refunding => refund(session, p).await?,
+        status => return Err(anyhow::anyhow!("No step follows {:?}", status)),
+    }
+    Ok(())
+}
+
+/// Wait out the refund timelock on the demo chain, then refund the lock to the buyer.
+async fn refund(session: &Session, p: &mut Protocol) -> anyhow::Result<()> {
+    let secp = Secp256k1::new();
+    let params = p.buyer.params();
+    let lock_txid = p.buyer.lock_outpoint().ok_or_else(|| anyhow::anyhow!("No locking transaction"))?.txid;
+    let wallet_pubkey = p.buyer_keys.wallet_keypair(0, 1)?.x_only_public_key().0;
+    let recipient = bitcoin::Address::p2tr(&secp, wallet_pubkey, None, NETWORK).as_unchecked().clone();
+    let swap_keypair = p.swap_keys(&p.buyer_keys)?.bitcoin;
+    let refund_tx = p.buyer.create_refund_transaction(recipient, REFUND_FEE_RATE, None, &swap_keypair, TapSighashType::Default).await?;
+
+    if p.scenario == Scenario::SellerNeverPublishes {
+        let Err(e) = p.chain.broadcast(&refund_tx).await else {
+            return Err(anyhow::anyhow!("Chain accepted a refund before the timelock expired"));
+        };
+        session.update(SessionStatus::Refunding, format!("Buyer tries to refund right away; the chain rejects it: {}.", e));
+    }
+
+    let required = u32::from(params.refund_blocks);
+    loop {
+        let confirmations = p.chain.get_confirmations(&lock_txid).await?.unwrap_or(0);
+        if confirmations >= required {
+            break;
+        }
+        tokio::time::sleep(DEMO_BLOCK_INTERVAL).await;
+        p.chain.mine(1);
+        session.update(SessionStatus::Refunding, format!("Refund timelock: {}/{} block(s).", confirmations + 1, required));
+    }
+
+    let refund_txid = p.chain.broadcast(&refund_tx).await?;
+    p.chain.mine(1);
+    session.detail("Refund Transaction", refund_txid.to_string());
+    session.update(
+        SessionStatus::Refunding,
+        format!("Buyer refunded {} through the timelocked leaf in {}.", refund_tx.output[0].value, refund_txid),
+    );
+
+    if p.scenario == Scenario::RefundTimelockExpires {
+        let adaptor_sig = p.adaptor_sig.as_ref().ok_or_else(|| anyhow::anyhow!("No adaptor signature"))?;
+        let unsigned_event = p.unsigned_event.clone().ok_or_else(|| anyhow::anyhow!("No event"))?;
+        let late_event = nostr_utils::complete_adaptor_event(unsigned_event, adaptor_sig, &p.swap_keys(&p.seller_keys)?.adaptor_secret)?;
+        session.update(
+            SessionStatus::Refunded,
+            format!(
+                "Seller finally publishes event {}, but the lock is already refunded. Publishing after the timelock \
+                 only hurts the seller; the buyer was never exposed.",
+                late_event.id
+            ),
+        );
+    } else {
+        session.update(SessionStatus::Refunded, "The timelocked refund leaf returned the buyer's funds.".to_string());
+    }
+    Ok(())
+}
+
+/// What one party of the two-party demo holds, and what it learned from the other side.
+#[derive(Clone, Default, Serialize)]
+struct PartyView {
+    swap_id: Option<String>,
+    status: String,
+    secrets: Vec<(String, String)>,
+    learned: Vec<Learned>,
+}
+
+#[derive(Clone, Serialize)]
+struct Learned {
+    /// Unix time in seconds.
+    at: u64,
+    what: String,
+    value: String,
+}
+
+impl PartyView {
+    fn learn(&mut self, what: &str, value: String) {
+        if self.learned.iter().any(|l| l.what == what) {
+            return;
+        }
+        let at = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        self.learned.push(Learned { at, what: what.to_string(), value });
+    }
+}
+
+/// Each party with the time it was created.
+type Parties<P> = Arc<Mutex<HashMap<String, (Instant, Arc<tokio::sync::Mutex<P>>)>>>;
+
+/// The seller's side: its seed, Nostr key and adaptor secrets never leave this struct.
+struct SellerParty {
+    view: PartyView,
+    keys: KeyManager,
+    keypair: Keypair,
+    offer: Option<(UnsignedEvent, AdaptorSignature)>,
+}
+
+/// The buyer's side: its seed and wallet. It learns everything else from the protocol.
+struct BuyerParty {
+    view: PartyView,
+    keys: KeyManager,
+    buyer: tapstr::Buyer,
+    offer: Option<(UnsignedEvent, AdaptorSignature)>,
+    amount: Amount,
+}
+
+#[derive(serde::Deserialize)]
+struct OfferForm {
+    content: String,
+    amount_sat: u64,
+    /// Sold content, encrypted to `t`; empty for none.
+    #[serde(default)]
+    unlockable: String,
+}
+
+#[derive(serde::Deserialize)]
+struct AcceptForm {
+    swap_id: String,
+}
+
+fn new_party<P>(parties: &Parties<P>, party: P) -> String {
+    let mut id_bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut id_bytes);
+    let id = hex::encode(id_bytes);
+    let mut parties = parties.lock().unwrap();
+    parties.retain(|_, (created, _)| created.elapsed() < DEMO_TTL);
+    parties.insert(id.clone(), (Instant::now(), Arc::new(tokio::sync::Mutex::new(party))));
+    id
+}
+
+fn find_party<P>(parties: &Parties<P>, id: &str) -> Result<Arc<tokio::sync::Mutex<P>>, api::ApiError> {
+    parties.lock().unwrap().get(id).map(|(_, party)| party.clone()).ok_or_else(|| api::ApiError::NotFound(id.to_string()))
+}
+
+fn bad_request(e: impl std::fmt::Display) -> api::ApiError {
+    api::ApiError::BadRequest(e.to_string())
+}
+
+#[axum::debug_handler]
+async fn create_seller(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let keys = KeyManager::generate(NETWORK).expect("fresh entropy makes a valid seed");
+    let keypair = keys.nostr_keypair(0).expect("NIP-06 path is valid");
+    let view = PartyView {
+        status: "Ready to make an offer.".to_string(),
+        secrets: vec![
+            ("Mnemonic".to_string(), keys.mnemonic().to_string()),
+            (format!("Nostr secret key ({})", KeyManager::nostr_path(0).expect("NIP-06 path is valid")), hex::encode(keypair.secret_bytes())),
+        ],
+        ..PartyView::default()
+    };
+    let id = new_party(&state.sellers, SellerParty { view, keys, keypair, offer: None });
+    Json(serde_json::json!({ "id": id }))
+}
+
+/// The seller's view, refreshed with whatever the coordinator has heard from the buyer.
+#[axum::debug_handler]
+async fn get_seller(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<PartyView>, api::ApiError> {
+    let party = find_party(&state.sellers, &id)?;
+    let mut party = party.lock().await;
+    if let Some(swap_id) = party.view.swap_id.clone() {
+        let status = state.api.status(&swap_id)?;
+        if let Some(buyer_pubkey) = status.buyer_pubkey {
+            party.view.learn("Buyer refund key", buyer_pubkey.to_string());
+        }
+        if let Some(address) = status.address {
+            party.view.learn("Swap address", address);
+        }
+        if let Some(outpoint) = status.lock_outpoint {
+            party.view.learn("Lock outpoint", outpoint.to_string());
+            if status.status == api::SwapStatus::Locked {
+                party.view.status = "Buyer locked. Publish the event once the lock confirms.".to_string();
+            }
+        }
+    }
+    Ok(Json(party.view.clone()))
+}
+
+/// Draft the event, pre-sign its id with `t` and post the offer to the coordinator.
+#[axum::debug_handler]
+async fn seller_offer(State(state): State<AppState>, Path(id): Path<String>, Json(form): Json<OfferForm>) -> Result<Json<PartyView>, api::ApiError> {
+    let party = find_party(&state.sellers, &id)?;
+    let mut party = party.lock().await;
+    if party.offer.is_some() {
+        return Err(api::ApiError::Conflict("offer already made".to_string()));
+    }
+    let secp = Secp256k1::new();
+    let seller_pubkey = party.keypair.x_only_public_key().0;
+    let unsigned_event = nostr_utils::create_unsigned_event(&seller_pubkey, Kind::TextNote, &form.content, vec![]).map_err(bad_request)?;
+    let swap_id = unsigned_event.id.to_hex();
+    let t = party.keys.swap_keys(0, &swap_id).map_err(bad_request)?.adaptor_secret;
+    let adaptor_sig = party.keypair.presign_event(&unsigned_event, &t.public_key(&secp)).await.map_err(bad_request)?;
+    // Commitment: hash of the adaptor s for simplicity
+    let commitment = sha256::Hash::const_hash(adaptor_sig.s.as_ref());
+    let claim_pubkey = bitcoin_utils::create_nostr_signature_lock_script(*commitment.as_byte_array(), seller_pubkey).map_err(bad_request)?;
+
+    let offer = state.api.create_offer(api::CreateOfferRequest {
+        event: unsigned_event.clone(),
+        presignature: api::PreSignature::from(&adaptor_sig),
+        amount_sat: form.amount_sat,
+        claim_pubkey,
+        encrypted_content: (!form.unlockable.is_empty()).then(|| EncryptedContent::encrypt(&secp, &t, form.unlockable.as_bytes())),
+        buyer_pubkey: None,
+    })?;
+    let (_, t_path) = party.keys.swap_paths(0, &swap_id).map_err(bad_request)?;
+    party.view.secrets.push((format!("Adaptor secret t ({})", t_path), hex::encode(t.secret_bytes())));
+    party.view.swap_id = Some(offer.swap_id);
+    party.view.status = "Offer posted. Share the swap id with the buyer.".to_string();
+    party.offer = Some((unsigned_event, adaptor_sig));
+    Ok(Json(party.view.clone()))
+}
+
+/// Once the lock has confirmed, complete the signature and publish the event.
+#[axum::debug_handler]
+async fn seller_reveal(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<PartyView>, api::ApiError> {
+    let party = find_party(&state.sellers, &id)?;
+    let mut party = party.lock().await;
+    let (swap_id, (unsigned_event, adaptor_sig)) = match (party.view.swap_id.clone(), party.offer.clone()) {
+        (Some(swap_id), Some(offer)) => (swap_id, offer),
+        _ => return Err(api::ApiError::Conflict("no offer yet".to_string())),
+    };
+    let status = state.api.status(&swap_id)?;
+    let outpoint = status.lock_outpoint.ok_or_else(|| api::ApiError::Conflict("buyer has not locked yet".to_string()))?;
+    let required = NetworkParams::new(NETWORK).min_confirmations;
+    let confirmations = state.chain.get_confirmations(&outpoint.txid).await.map_err(bad_request)?.unwrap_or(0);
+    if confirmations < required {
+        return Err(api::ApiError::Conflict(format!("lock has {}/{} confirmation(s)", confirmations, required)));
+    }
+    party.view.learn("Lock confirmed", format!("{} with {} confirmation(s)", outpoint, confirmations));
+
+    let t = party.keys.swap_keys(0, &swap_id).map_err(bad_request)?.adaptor_secret;
+    let completed_event = nostr_utils::complete_adaptor_event(unsigned_event, &adaptor_sig, &t).map_err(bad_request)?;
+    #[cfg(feature = "relay")]
+    {
+        let mut client = nostr_utils::RelayClient::new(state.relay.clone(), vec![LOCAL_RELAY_URL.to_string()]);
+        client.connect().await.map_err(bad_request)?;
+        client.publish(&completed_event, Duration::from_secs(5)).await.map_err(bad_request)?;
+    }
+    state.api.submit_event(&swap_id, api::SubmitEventRequest { event: completed_event })?;
+    party.view.status = "Event published. Its signature reveals t to the buyer.".to_string();
+    Ok(Json(party.view.clone()))
+}
+
+#[axum::debug_handler]
+async fn create_buyer(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let keys = KeyManager::generate(NETWORK).expect("fresh entropy makes a valid seed");
+    let view = PartyView {
+        status: "Paste a swap id from the seller.".to_string(),
+        secrets: vec![("Mnemonic".to_string(), keys.mnemonic().to_string())],
+        ..PartyView::default()
+    };
+    let party = BuyerParty { view, keys, buyer: tapstr::Buyer::new(NETWORK), offer: None, amount: Amount::ZERO };
+    Json(serde_json::json!({ "id": new_party(&state.buyers, party) }))
+}
+
+#[axum::debug_handler]
+async fn get_buyer(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<PartyView>, api::ApiError> {
+    let party = find_party(&state.buyers, &id)?;
+    let view = party.lock().await.view.clone();
+    Ok(Json(view))
+}
+
+/// Fetch and check the pre-signature, then accept and check the swap output we are given.
+#[axum::debug_handler]
+async fn buyer_accept(State(state): State<AppState>, Path(id): Path<String>, Json(form): Json<AcceptForm>) -> Result<Json<PartyView>, api::ApiError> {
+    let party = find_party(&state.buyers, &id)?;
+    let mut party = party.lock().await;
+    if party.offer.is_some() {
+        return Err(api::ApiError::Conflict("offer already accepted".to_string()));
+    }
+    let swap_id = form.swap_id.trim().to_string();
+    let presigned = state.api.presignature(&swap_id)?;
+    let adaptor_sig = presigned.presignature.to_adaptor_signature(&presigned.event.id);
+    nostr_utils::check_presigned_event(&presigned.event, &adaptor_sig).map_err(|e| bad_request(format!("seller's pre-signature is invalid: {}", e)))?;
+    party.view.learn("Offered event", format!("{} ({:?})", presigned.event.id, presigned.event.content));
+    party.view.learn("Seller pre-signature", format!("R' = {}, T = {}", adaptor_sig.nonce_point, adaptor_sig.adaptor_point));
+    if let Some(content) = state.api.status(&swap_id)?.encrypted_content {
+        if content.adaptor_point() != adaptor_sig.adaptor_point || !content.verify(&Secp256k1::new()) {
+            return Err(bad_request("content key is not recoverable with t"));
+        }
+        party.view.learn("Content key proof", format!("K = {} is recoverable with t; content commitment {}", content.key.key_point, content.commitment));
+    }
+
+    let swap_keypair = party.keys.swap_keys(0, &swap_id).map_err(bad_request)?.bitcoin;
+    let (swap_path, _) = party.keys.swap_paths(0, &swap_id).map_err(bad_request)?;
+    let buyer_pubkey = swap_keypair.x_only_public_key().0;
+    let request = api::AcceptOfferRequest::sign(&swap_keypair, &swap_id).await.map_err(bad_request)?;
+    let accepted = state.api.accept_offer(&swap_id, request)?;
+    let swap_output = bitcoin_utils::SwapOutput::from_descriptor(&accepted.descriptor).map_err(bad_request)?;
+    let address = swap_output.address(NETWORK).map_err(bad_request)?;
+    if swap_output.buyer_pubkey != buyer_pubkey || address.to_string() != accepted.address {
+        return Err(bad_request("swap output does not pay our refund key"));
+    }
+    party.view.learn("Swap address", format!("{} (refund after {} blocks)", address, accepted.refund_blocks));
+    party.view.secrets.push((format!("Swap refund key ({})", swap_path), hex::encode(swap_keypair.secret_bytes())));
+    party.buyer.swap_output = Some(swap_output);
+    party.amount = Amount::from_sat(accepted.amount_sat);
+    party.offer = Some((presigned.event, adaptor_sig));
+    party.view.swap_id = Some(swap_id);
+    party.view.status = format!("Offer accepted. Lock {} to continue.", party.amount);
+    Ok(Json(party.view.clone()))
+}
+
+/// Fund the swap output on the demo chain and tell the coordinator where it is.
+#[axum::debug_handler]
+async fn buyer_lock(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<PartyView>, api::ApiError> {
+    let party = find_party(&state.buyers, &id)?;
+    let mut party = party.lock().await;
+    let (Some(swap_id), Some(swap_output)) = (party.view.swap_id.clone(), party.buyer.swap_output) else {
+        return Err(api::ApiError::Conflict("accept an offer first".to_string()));
+    };
+    if party.buyer.locking_tx.is_some() {
+        return Err(api::ApiError::Conflict("already locked".to_string()));
+    }
+    let wallet_pubkey = party.keys.wallet_keypair(0, 0).map_err(bad_request)?.x_only_public_key().0;
+    let buyer_script = ScriptBuf::new_p2tr(&Secp256k1::new(), wallet_pubkey, None);
+    let amount = party.amount;
+    let funding = state.chain.fund(buyer_script, amount);
+    party.buyer.create_swap_locking_transaction(funding.txid, funding.vout, amount, swap_output).map_err(bad_request)?;
+    let txid = party.buyer.broadcast_locking_transaction(&state.chain).await.map_err(bad_request)?;
+    state.chain.mine(party.buyer.params().min_confirmations);
+    state.api.submit_lock(&swap_id, api::SubmitLockRequest { txid, vout: 0 }).await?;
+    party.view.status = format!("Locked in {}. Waiting for the seller to publish.", txid);
+    Ok(Json(party.view.clone()))
+}
+
+/// Look for the seller's completed event and extract `t` from its signature.
+#[axum::debug_handler]
+async fn buyer_extract(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<PartyView>, api::ApiError> {
+    let party = find_party(&state.buyers, &id)?;
+    let mut party = party.lock().await;
+    let (Some(swap_id), Some((unsigned_event, adaptor_sig))) = (party.view.swap_id.clone(), party.offer.clone()) else {
+        return Err(api::ApiError::Conflict("accept an offer first".to_string()));
+    };
+
+    #[cfg(feature = "relay")]
+    let event = {
+        let mut client = nostr_utils::RelayClient::new(state.relay.clone(), vec![LOCAL_RELAY_URL.to_string()]);
+        client.connect().await.map_err(bad_request)?;
+        let filter = nostr::Filter::new().id(unsigned_event.id).author(unsigned_event.pubkey).kind(unsigned_event.kind);
+        client.wait_for_event(filter, Duration::from_secs(2)).await.map_err(bad_request)?
+    };
+    #[cfg(not(feature = "relay"))]
+    let event = {
+        let _ = &unsigned_event;
+        state.api.status(&swap_id)?.completed_event
+    };
+    let event = event.ok_or_else(|| api::ApiError::Conflict("seller has not published the event yet".to_string()))?;
+
+    let sig = secp256k1::schnorr::Signature::from_slice(event.signature().as_ref()).map_err(bad_request)?;
+    let t = adaptor_sig
+        .try_extract_secret(&Secp256k1::new(), &sig)
+        .ok_or_else(|| bad_request("event signature does not complete the pre-signature"))?;
+    party.view.learn("Completed event signature", sig.to_string());
+    party.view.learn("Adaptor secret t", hex::encode(t.secret_bytes()));
+    if let Some(content) = state.api.status(&swap_id)?.encrypted_content {
+        let plaintext = content.decrypt(&Secp256k1::new(), &t).map_err(bad_request)?;
+        party.view.learn("Unlocked content", String::from_utf8_lossy(&plaintext).into_owned());
+    }
+    party.view.status = "Swap complete: t learned from the published signature.".to_string();
+    Ok(Json(party.view.clone()))
+}
+
+/// Styles shared by the demo pages.
+const PAGE_STYLE: &str = r#"
     body {
         font-family: 'Segoe UI', Tahoma, Geneva, Verdana, sans-serif;
         background: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
@@ -52,7 +1001,7 @@ async fn get_ui() -> Html<String> {
         border-radius: 15px;
         box-shadow: 0 10px 30px rgba(0,0,0,0.3);
         padding: 40px;
-        max-width: 600px;
+        max-width: 800px;
         width: 90%;
         text-align: center;
     }
@@ -69,6 +1018,15 @@ async fn get_ui() -> Html<String> {
         background: #e6fffa;
         color: #2d3748;
     }
+    .status.completed {
+        background: #c6f6d5;
+    }
+    .status.failed {
+        background: #fed7d7;
+    }
+    .status.refunded, .status.aborted {
+        background: #fefcbf;
+    }
     .description {
         font-size: 1.1em;
         margin: 20px 0;
@@ -83,263 +1041,324 @@ async fn get_ui() -> Html<String> {
         border-radius: 25px;
         cursor: pointer;
         transition: transform 0.2s, box-shadow 0.2s;
-        margin-top: 20px;
+        margin: 20px 5px 0;
     }
     button:hover {
         transform: translateY(-2px);
         box-shadow: 0 5px 15px rgba(0,0,0,0.2);
     }
+    button:disabled {
+        opacity: 0.5;
+        cursor: default;
+        transform: none;
+    }
     .logo {
         font-size: 3em;
         margin-bottom: 20px;
     }
+    .logs, .details {
+        text-align: left;
+        margin: 30px 0 0;
+        border: 1px solid #e2e8f0;
+        border-radius: 10px;
+        padding: 20px;
+        background: #f7fafc;
+    }
+    .logs {
+        max-height: 400px;
+        overflow-y: auto;
+    }
+    .log-item {
+        margin-bottom: 10px;
+        padding: 10px;
+        border-radius: 5px;
+        background: white;
+        border-left: 4px solid #667eea;
+    }
+    .step {
+        font-weight: bold;
+        color: #667eea;
+        margin-right: 10px;
+    }
+    .details h4 {
+        color: #4a5568;
+        margin: 10px 0 5px;
+    }
+    .details p {
+        margin: 5px 0;
+        font-family: monospace;
+        background: #ffffff;
+        padding: 5px;
+        border-radius: 3px;
+        word-break: break-all;
+    }
+    .hidden {
+        display: none;
+    }
+    input, textarea, select {
+        width: 100%;
+        box-sizing: border-box;
+        padding: 10px;
+        margin: 5px 0;
+        border: 1px solid #e2e8f0;
+        border-radius: 5px;
+        font-family: monospace;
+    }
+    .time {
+        color: #a0aec0;
+        margin-right: 10px;
+    }
+    a {
+        color: #667eea;
+    }
+"#;
+
+#[axum::debug_handler]
+async fn get_ui() -> Html<String> {
+    let html = r#"
+    <!DOCTYPE html>
+    <html lang="en">
+    <head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Atomic Swap Demo</title>
+    <style>
+    /* PAGE_STYLE */
     </style>
     </head>
     <body>
     <div class="container">
     <div class="logo">⚡🔄📜</div>
     <h1>Atomic Swap Demo</h1>
-    <p class="status">Status: Ready</p>
+    <p class="status" id="status">Status: Ready</p>
     <p class="description">Experience the power of atomic swaps between Bitcoin and Nostr using Taproot and Schnorr adaptor signatures.</p>
-    <form action="/start" method="post">
-    <button type="submit">🚀 Start Swap Demo</button>
-    </form>
+    <select id="scenario">/* SCENARIOS */</select>
+    <button id="start">🚀 Start Swap</button>
+    <button id="next" disabled>⏭️ Next Step</button>
+    <button id="run" disabled>⏩ Run to Completion</button>
+    <p class="description">Two-party mode: open the <a href="/seller">seller</a> and <a href="/buyer">buyer</a> pages in separate browsers.</p>
+    <div class="logs hidden" id="logs"></div>
+    <div class="details hidden" id="details">
+    <h3>🔐 Technical Details</h3>
+    <div id="detail-list"></div>
+    <h4>Schnorr Signature Mathematics:</h4>
+    <p><strong>Standard Schnorr:</strong> s = k + e * x, where e = H(R || P || m), R = k * G</p>
+    <p><strong>Verification:</strong> s * G = R + e * P</p>
+    <p><strong>Adaptor Signature:</strong> s' = s + t, where t is the secret</p>
+    <p><strong>Challenge:</strong> e = H(R' || P || m) where R' = R + T, T = t * G</p>
+    <p><strong>BIP340:</strong> Uses tagged hash for e to prevent collision attacks</p>
     </div>
-    </body>
-    </html>
-    "#;
-    Html(html.to_string())
-}
-
-#[axum::debug_handler]
-async fn start_swap() -> Html<String> {
-    let mut logs = vec![];
-
-    let log = |logs: &mut Vec<String>, msg: String| {
-        println!("{}", msg);
-        logs.push(msg.clone());
-    };
-
-    log(&mut logs, "Starting atomic swap between Bitcoin and Nostr using Taproot and Schnorr adaptor signatures".to_string());
+    </div>
+    <script>
+    let session = null;
+    let count = 0;
+    const $ = (id) => document.getElementById(id);
 
-    let secp = Secp256k1::new();
-    let mut rng = OsRng;
+    function setStatus(status) {
+        $('status').textContent = 'Status: ' + status;
+        $('status').className = 'status ' + status;
+        const finished = ['completed', 'refunded', 'aborted', 'failed'].includes(status);
+        $('next').disabled = finished;
+        $('run').disabled = finished;
+        $('start').disabled = !finished;
+    }
 
-    // Establish Seller and Buyer
-    let seller_nostr_keypair = Keypair::new(&secp, &mut rng);
-    let buyer_bitcoin_keypair = Keypair::new(&secp, &mut rng);
+    function addLog(text) {
+        count += 1;
+        const item = document.createElement('div');
+        item.className = 'log-item';
+        item.innerHTML = '<span class="step">' + count + '</span>';
+        item.appendChild(document.createTextNode(text));
+        $('logs').appendChild(item);
+        $('logs').scrollTop = $('logs').scrollHeight;
+    }
 
-    let _seller = tapstr::Seller::new();
-    let mut buyer = tapstr::Buyer::new();
+    function renderDetails(details) {
+        const list = $('detail-list');
+        list.innerHTML = '';
+        for (const [name, value] of details) {
+            const heading = document.createElement('h4');
+            heading.textContent = name;
+            const body = document.createElement('p');
+            body.textContent = value;
+            list.appendChild(heading);
+            list.appendChild(body);
+        }
+        $('details').classList.toggle('hidden', details.length === 0);
+    }
 
-    log(&mut logs, "Seller and Buyer established.".to_string());
+    async function refreshDetails() {
+        const response = await fetch('/sessions/' + session);
+        renderDetails((await response.json()).details);
+    }
 
-    // Step 1: Seller creates a Nostr event and a commitment
-    let content = "Buy this digital item".to_string();
-    let message = Message::from_hashed_data::<sha256::Hash>(content.as_bytes());
-    let t = SecretKey::new(&mut rng);
-    let adaptor_sig = adaptor::AdaptorSignature::new(&secp, &seller_nostr_keypair, &message, &t);
-    log(&mut logs, "Seller created adaptor signature.".to_string());
+    $('start').onclick = async () => {
+        const response = await fetch('/sessions', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ scenario: $('scenario').value }),
+        });
+        session = (await response.json()).id;
+        count = 0;
+        $('logs').innerHTML = '<h3>📋 Progress:</h3>';
+        $('logs').classList.remove('hidden');
+        const events = new EventSource('/sessions/' + session + '/events');
+        events.addEventListener('snapshot', (e) => {
+            const snapshot = JSON.parse(e.data);
+            snapshot.logs.forEach(addLog);
+            setStatus(snapshot.status);
+            renderDetails(snapshot.details);
+        });
+        events.addEventListener('progress', (e) => {
+            const progress = JSON.parse(e.data);
+            addLog(progress.log);
+            setStatus(progress.status);
+            const finished = ['completed', 'refunded', 'aborted', 'failed'].includes(progress.status);
+            // Closing the stream of a finished session makes the server forget it.
+            refreshDetails().finally(() => { if (finished) events.close(); });
+        });
+    };
 
-    // Commitment: hash of the adaptor s for simplicity
-    let commitment = sha256::Hash::const_hash(adaptor_sig.s.as_ref());
-    log(&mut logs, format!("Seller created commitment: {:?}", commitment));
-
-    // Step 2: Buyer creates a lock transaction
-    // Simulated previous tx
-    let prev_txid = Txid::from_str("0000000000000000000000000000000000000000000000000000000000000000").unwrap();
-    let prev_vout = 0;
-    let amount = Amount::from_sat(10000);
-
-    // Tweaked key for locking
-    let seller_pubkey = seller_nostr_keypair.x_only_public_key().0;
-    let tweaked_key = bitcoin_utils::create_nostr_signature_lock_script(*commitment.as_byte_array(), seller_pubkey).unwrap();
-    buyer.create_locking_transaction(prev_txid, prev_vout, amount, tweaked_key);
-    log(&mut logs, "Buyer created locking transaction.".to_string());
-
-    // Step 3: Buyer verifies the adaptor signature
-    let verified = adaptor_sig.verify(&secp);
-    log(&mut logs, format!("Buyer verified adaptor signature: {}", verified));
-
-    // Step 4: Seller completes the signature and reveals the secret
-    // In the protocol, seller provides the real Nostr sig
-    let s_prime = adaptor_sig.complete(&t);
-    let _final_nostr_sig = adaptor_sig.generate_final_signature(&s_prime);
-    log(&mut logs, "Seller completed the Nostr signature and revealed it.".to_string());
-
-    // Step 5: Buyer verifies the secret
-    // Buyer extracts t from the sig
-    let extracted_t = adaptor_sig.extract_secret(&s_prime);
-    let t_matches = extracted_t == t;
-    log(&mut logs, format!("Buyer verified the secret: {}", t_matches));
-
-    log(&mut logs, "Atomic swap completed successfully!".to_string());
-
-    // Collect technical details
-    let seller_pubkey = seller_nostr_keypair.x_only_public_key().0;
-    let buyer_pubkey = buyer_bitcoin_keypair.x_only_public_key().0;
-    let adaptor_details = format!("Nonce Point: {:?}, s: {:?}, ex: {:?}", adaptor_sig.nonce_point, adaptor_sig.s, adaptor_sig.ex);
-    let revealed_secret = format!("t: {:?}", t);
-    let math_details = r#"
-    <h4>Schnorr Signature Mathematics:</h4>
-    <p><strong>Standard Schnorr:</strong> s = k + e * x, where e = H(R || P || m), R = k * G</p>
-    <p><strong>Verification:</strong> s * G = R + e * P</p>
-    <p><strong>Adaptor Signature:</strong> s' = s + t, where t is the secret</p>
-    <p><strong>Challenge:</strong> e = H(R' || P || m) where R' = R + T, T = t * G</p>
-    <p><strong>BIP340:</strong> Uses tagged hash for e to prevent collision attacks</p>
+    async function post(action) {
+        const response = await fetch('/sessions/' + session + '/' + action, { method: 'POST' });
+        if (!response.ok) addLog('⚠️ ' + (await response.json()).error);
+    }
+    $('next').onclick = () => post('advance');
+    $('run').onclick = () => post('run');
+    </script>
+    </body>
+    </html>
     "#;
+    let scenarios: String = Scenario::ALL
+        .iter()
+        .map(|scenario| {
+            let value = serde_json::to_value(scenario).unwrap();
+            format!(r#"<option value="{}">{}</option>"#, value.as_str().unwrap_or_default(), scenario.describe())
+        })
+        .collect();
+    Html(html.replace("/* PAGE_STYLE */", PAGE_STYLE).replace("/* SCENARIOS */", &scenarios))
+}
 
-    let logs_html = logs.iter().enumerate().map(|(i, log)| format!("<div class='log-item' style='animation-delay: {}s'><span class='step'>{}</span> {}</div>", i as f32 * 0.3, i + 1, log)).collect::<String>();
-    let details_html = format!(r#"
-    <div class="details">
-    <h3>🔐 Technical Details</h3>
-    <div class="detail-section">
-    <h4>Keys:</h4>
-    <p><strong>Seller Public Key:</strong> {}</p>
-    <p><strong>Buyer Public Key:</strong> {}</p>
-    </div>
-    <div class="detail-section">
-    <h4>Adaptor Signature:</h4>
-    <p>{}</p>
-    </div>
-    <div class="detail-section">
-    <h4>Revealed Secret:</h4>
-    <p>{}</p>
-    </div>
-    <div class="detail-section">
-    {}
-    </div>
-    </div>
-    "#, seller_pubkey, buyer_pubkey, adaptor_details, revealed_secret, math_details);
-    let html = format!(r#"
+/// Layout of the seller and buyer pages: the role's controls plus what it knows and learns.
+const PARTY_PAGE: &str = r#"
     <!DOCTYPE html>
     <html lang="en">
     <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Atomic Swap Demo - Results</title>
+    <title>Atomic Swap Demo - /* ROLE */</title>
     <style>
-    body {{
-        font-family: 'Segoe UI', Tahoma, Geneva, Verdana, sans-serif;
-        background: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
-        color: #333;
-        margin: 0;
-        padding: 0;
-        min-height: 100vh;
-        display: flex;
-        justify-content: center;
-        align-items: center;
-    }}
-    .container {{
-        background: white;
-        border-radius: 15px;
-        box-shadow: 0 10px 30px rgba(0,0,0,0.3);
-        padding: 40px;
-        max-width: 800px;
-        width: 90%;
-        text-align: center;
-    }}
-    h1 {{
-        color: #4a5568;
-        margin-bottom: 20px;
-        font-size: 2.5em;
-    }}
-    .status {{
-        font-size: 1.2em;
-        margin: 20px 0;
-        padding: 10px;
-        border-radius: 10px;
-        background: #c6f6d5;
-        color: #2d3748;
-    }}
-    .logs {{
-        text-align: left;
-        margin: 30px 0;
-        max-height: 400px;
-        overflow-y: auto;
-        border: 1px solid #e2e8f0;
-        border-radius: 10px;
-        padding: 20px;
-        background: #f7fafc;
-    }}
-    .log-item {{
-        margin-bottom: 10px;
-        padding: 10px;
-        border-radius: 5px;
-        background: white;
-        border-left: 4px solid #667eea;
-        animation: fadeInUp 0.6s ease forwards;
-        opacity: 0;
-        transform: translateY(20px);
-    }}
-    .step {{
-        font-weight: bold;
-        color: #667eea;
-        margin-right: 10px;
-    }}
-    @keyframes fadeInUp {{
-        to {{
-            opacity: 1;
-            transform: translateY(0);
-        }}
-    }}
-    a {{
-        display: inline-block;
-        background: #667eea;
-        color: white;
-        padding: 10px 20px;
-        text-decoration: none;
-        border-radius: 5px;
-        margin-top: 20px;
-        transition: background 0.2s;
-    }}
-    a:hover {{
-        background: #5a67d8;
-    }}
-    .success {{
-        color: #38a169;
-        font-size: 1.5em;
-        margin: 20px 0;
-    }}
-    .details {{
-        margin-top: 40px;
-        text-align: left;
-        background: #f8f9fa;
-        padding: 20px;
-        border-radius: 10px;
-        border: 1px solid #e2e8f0;
-    }}
-    .detail-section {{
-        margin-bottom: 20px;
-    }}
-    .detail-section h4 {{
-        color: #4a5568;
-        margin-bottom: 10px;
-    }}
-    .detail-section p {{
-        margin: 5px 0;
-        font-family: monospace;
-        background: #ffffff;
-        padding: 5px;
-        border-radius: 3px;
-        word-break: break-all;
-    }}
+    /* PAGE_STYLE */
     </style>
     </head>
     <body>
     <div class="container">
-    <h1>🎉 Swap Completed!</h1>
-    <p class="status">Status: Completed</p>
-    <div class="success">✅ Atomic swap between Bitcoin and Nostr successful!</div>
+    <h1>/* TITLE */</h1>
+    <p class="status" id="status">Status: Starting</p>
+    <p class="description" id="swap"></p>
+    /* CONTROLS */
+    <div class="details">
+    <h3>🔑 Only I know</h3>
+    <div id="secrets"></div>
+    </div>
     <div class="logs">
-    <h3>📋 Execution Logs:</h3>
-    {}
+    <h3>📥 What I learned, and when</h3>
+    <div id="learned"></div>
     </div>
-    {}
-    <a href="/">🔄 Try Again</a>
+    <p class="description"><a href="/">Back to the single-process demo</a></p>
     </div>
+    <script>
+    const role = '/* ROLE */';
+    const $ = (id) => document.getElementById(id);
+    let party = null;
+
+    function render(view) {
+        $('status').textContent = 'Status: ' + view.status;
+        $('swap').textContent = view.swap_id ? 'Swap id: ' + view.swap_id : '';
+        $('secrets').innerHTML = '';
+        for (const [name, value] of view.secrets) {
+            const heading = document.createElement('h4');
+            heading.textContent = name;
+            const body = document.createElement('p');
+            body.textContent = value;
+            $('secrets').append(heading, body);
+        }
+        $('learned').innerHTML = '';
+        for (const item of view.learned) {
+            const entry = document.createElement('div');
+            entry.className = 'log-item';
+            const time = document.createElement('span');
+            time.className = 'time';
+            time.textContent = new Date(item.at * 1000).toLocaleTimeString();
+            const what = document.createElement('strong');
+            what.textContent = item.what + ': ';
+            entry.append(time, what, document.createTextNode(item.value));
+            $('learned').appendChild(entry);
+        }
+    }
+
+    async function refresh() {
+        if (!party) return;
+        const response = await fetch('/' + role + '/parties/' + party);
+        if (response.ok) render(await response.json());
+    }
+
+    async function act(action, body) {
+        const response = await fetch('/' + role + '/parties/' + party + '/' + action, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(body || {}),
+        });
+        const result = await response.json();
+        if (response.ok) render(result); else alert(result.error);
+    }
+
+    (async () => {
+        const response = await fetch('/' + role + '/parties', { method: 'POST' });
+        party = (await response.json()).id;
+        refresh();
+        setInterval(refresh, 1000);
+    })();
+    </script>
     </body>
     </html>
-    "#, logs_html, details_html);
-    Html(html)
-}
\ No newline at end of file
+"#;
+
+fn party_page(role: &str, title: &str, controls: &str) -> Html<String> {
+    Html(
+        PARTY_PAGE
+            .replace("/* PAGE_STYLE */", PAGE_STYLE)
+            .replace("/* ROLE */", role)
+            .replace("/* TITLE */", title)
+            .replace("/* CONTROLS */", controls),
+    )
+}
+
+#[axum::debug_handler]
+async fn get_seller_ui() -> Html<String> {
+    party_page(
+        "seller",
+        "🏪 Seller",
+        r#"
+    <textarea id="content" rows="3">Buy this digital item</textarea>
+    <textarea id="unlockable" rows="2" placeholder="Content unlocked by payment (optional)"></textarea>
+    <input id="amount" type="number" value="10000" min="1">
+    <button onclick="act('offer', { content: $('content').value, unlockable: $('unlockable').value, amount_sat: Number($('amount').value) })">📝 Make Offer</button>
+    <button onclick="act('reveal')">📣 Publish Event</button>
+    "#,
+    )
+}
+
+#[axum::debug_handler]
+async fn get_buyer_ui() -> Html<String> {
+    party_page(
+        "buyer",
+        "🛒 Buyer",
+        r#"
+    <input id="swap-id" placeholder="Swap id from the seller">
+    <button onclick="act('accept', { swap_id: $('swap-id').value })">🤝 Accept Offer</button>
+    <button onclick="act('lock')">🔒 Lock Bitcoin</button>
+    <button onclick="act('extract')">🔍 Watch for Secret</button>
+    "#,
+    )
+}
@@ -0,0 +1,42 @@
+use bitcoin::{Address, Network, address::NetworkUnchecked};
+use anyhow::{anyhow, Result};
+
+/// Per-network defaults for swaps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkParams {
+    pub network: Network,
+    /// Relative timelock on the buyer's refund path, in blocks.
+    pub refund_blocks: u16,
+    /// Confirmations to wait for before treating the lock as final.
+    pub min_confirmations: u32,
+}
+
+impl NetworkParams {
+    pub fn new(network: Network) -> Self {
+        match network {
+            Network::Bitcoin => NetworkParams {
+                network,
+                refund_blocks: 144,
+                min_confirmations: 3,
+            },
+            Network::Testnet | Network::Signet => NetworkParams {
+                network,
+                refund_blocks: 72,
+                min_confirmations: 1,
+            },
+            _ => NetworkParams {
+                network,
+                refund_blocks: 10,
+                min_confirmations: 1,
+            },
+        }
+    }
+}
+
+/// Check that an address parsed from user input belongs to `network`.
+pub fn require_network(address: Address<NetworkUnchecked>, network: Network) -> Result<Address> {
+    let shown = address.clone().assume_checked();
+    address
+        .require_network(network)
+        .map_err(|_| anyhow!("Address {} is not valid for {}", shown, network))
+}
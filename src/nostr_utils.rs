@@ -1,5 +1,15 @@
-use nostr::{Event, Keys, Kind, Tag, EventBuilder};
-use secp256k1::SecretKey;
+use nostr::{Event, EventId, Keys, Kind, Tag, EventBuilder, Filter, ClientMessage, RelayMessage, SubscriptionId, JsonUtil, UnsignedEvent, Alphabet};
+use secp256k1::{SecretKey, Message, PublicKey, Secp256k1, XOnlyPublicKey};
+use crate::adaptor::AdaptorSignature;
+use crate::signer::AdaptorSigner;
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite;
 
 pub fn create_signed_event(keys: &Keys, kind: Kind, content: &str, tags: Vec<Tag>) -> Event {
     EventBuilder::new(kind, content, tags).to_event(keys).unwrap()
@@ -9,4 +19,487 @@ pub fn extract_secret_from_signature(adaptor_sig: &crate::adaptor::AdaptorSignat
     let sig_bytes = nostr_sig.as_ref();
     let s_prime = SecretKey::from_slice(&sig_bytes[32..64]).unwrap();
     adaptor_sig.extract_secret(&s_prime)
-}
\ No newline at end of file
+}
+
+/// Convert an x-only key into the secp256k1 version used by the nostr crate.
+pub fn to_nostr_pubkey(pubkey: &XOnlyPublicKey) -> Result<nostr::secp256k1::XOnlyPublicKey> {
+    Ok(nostr::secp256k1::XOnlyPublicKey::from_slice(&pubkey.serialize())?)
+}
+
+/// Build the unsigned event the seller will pre-sign. Its id is the adaptor message.
+pub fn create_unsigned_event(pubkey: &XOnlyPublicKey, kind: Kind, content: &str, tags: Vec<Tag>) -> Result<UnsignedEvent> {
+    Ok(EventBuilder::new(kind, content, tags).to_unsigned_event(to_nostr_pubkey(pubkey)?))
+}
+
+/// The message a Nostr signature commits to: the 32-byte event id.
+pub fn event_message(event_id: &EventId) -> Message {
+    Message::from_digest(event_id.to_bytes())
+}
+
+/// Complete the adaptor signature with `t` and attach it to the unsigned event.
+/// The resulting event carries a valid BIP340 signature and can be published.
+pub fn complete_adaptor_event(unsigned: UnsignedEvent, adaptor_sig: &crate::adaptor::AdaptorSignature, t: &SecretKey) -> Result<Event> {
+    if adaptor_sig.message != unsigned.id.as_bytes() {
+        return Err(anyhow!("Adaptor signature does not commit to event {}", unsigned.id));
+    }
+    let s_prime = adaptor_sig.complete(t);
+    let sig = adaptor_sig.generate_final_signature(&s_prime);
+    let sig = nostr::secp256k1::schnorr::Signature::from_slice(sig.as_ref())?;
+    Ok(unsigned.add_signature(sig)?)
+}
+
+/// Extract the adaptor secret `t` from a published, completed event.
+pub fn extract_secret_from_event(adaptor_sig: &crate::adaptor::AdaptorSignature, event: &Event) -> Result<SecretKey> {
+    if adaptor_sig.message != event.id.as_bytes() {
+        return Err(anyhow!("Event {} is not the pre-signed event", event.id));
+    }
+    event.verify()?;
+    let sig = secp256k1::schnorr::Signature::from_slice(event.signature().as_ref())?;
+    Ok(extract_secret_from_signature(adaptor_sig, &sig))
+}
+
+/// NIP-01 filter for the seller's completed event, narrowed by single-letter tag values.
+pub fn completed_event_filter(seller_pubkey: &XOnlyPublicKey, kind: Kind, tags: &[(Alphabet, String)]) -> Result<Filter> {
+    let mut filter = Filter::new().author(to_nostr_pubkey(seller_pubkey)?).kind(kind);
+    for (letter, value) in tags {
+        filter = filter.custom_tag(*letter, [value.clone()]);
+    }
+    Ok(filter)
+}
+
+/// Check an event against a NIP-01 filter, the same way a relay would.
+pub fn filter_matches(filter: &Filter, event: &Event) -> bool {
+    if !filter.ids.is_empty() && !filter.ids.contains(&event.id) {
+        return false;
+    }
+    if !filter.authors.is_empty() && !filter.authors.contains(&event.pubkey) {
+        return false;
+    }
+    if !filter.kinds.is_empty() && !filter.kinds.contains(&event.kind) {
+        return false;
+    }
+    if filter.since.is_some_and(|since| event.created_at < since) {
+        return false;
+    }
+    if filter.until.is_some_and(|until| event.created_at > until) {
+        return false;
+    }
+    filter.generic_tags.iter().all(|(letter, values)| {
+        let letter = letter.to_string();
+        event.tags.iter().any(|tag| {
+            let tag = tag.as_vec();
+            tag.len() >= 2 && tag[0] == letter && values.iter().any(|v| v.to_string() == tag[1])
+        })
+    })
+}
+
+/// Draft the NIP-58 award of `badge_definition` to `buyer`, authored by the badge's issuer.
+pub fn create_badge_award(badge_definition: &Event, buyer: &XOnlyPublicKey) -> Result<UnsignedEvent> {
+    if badge_definition.kind != Kind::BadgeDefinition {
+        return Err(anyhow!("Event {} is kind {}, not a badge definition", badge_definition.id, badge_definition.kind));
+    }
+    badge_definition.verify()?;
+    let award = EventBuilder::award_badge(badge_definition, [Tag::public_key(to_nostr_pubkey(buyer)?)])?;
+    Ok(award.to_unsigned_event(badge_definition.author()))
+}
+
+/// Draft the award and pre-sign it. `signer` must be the badge's issuer.
+pub async fn presign_badge_award(signer: &impl AdaptorSigner, badge_definition: &Event, buyer: &XOnlyPublicKey, adaptor_point: &PublicKey) -> Result<(UnsignedEvent, AdaptorSignature)> {
+    let award = create_badge_award(badge_definition, buyer)?;
+    let adaptor_sig = signer.presign_event(&award, adaptor_point).await?;
+    Ok((award, adaptor_sig))
+}
+
+/// Check `event.id` hashes the event's fields. `UnsignedEvent::sign` signs whatever id it
+/// carries, so an unchecked id lets the signer be asked to sign any 32 bytes.
+pub fn check_event_id(event: &UnsignedEvent) -> Result<()> {
+    let computed = EventId::new(&event.pubkey, event.created_at, &event.kind, &event.tags, &event.content);
+    if computed != event.id {
+        return Err(anyhow!("Event id should be {}", computed));
+    }
+    Ok(())
+}
+
+/// Check `adaptor_sig` pre-signs `event` for its author: the id hashes the event's fields,
+/// and the pre-signature is over that id, by the author's key, and verifies.
+pub fn check_presigned_event(event: &UnsignedEvent, adaptor_sig: &AdaptorSignature) -> Result<()> {
+    check_event_id(event)?;
+    if adaptor_sig.message != event.id.as_bytes() {
+        return Err(anyhow!("Pre-signature does not commit to event {}", event.id));
+    }
+    let signer = adaptor_sig.pubkey.x_only_public_key().0;
+    if signer.serialize() != event.pubkey.serialize() {
+        return Err(anyhow!("Pre-signature key {} is not the event author", signer));
+    }
+    if !adaptor_sig.verify(&Secp256k1::new()) {
+        return Err(anyhow!("Pre-signature does not verify against event {}", event.id));
+    }
+    Ok(())
+}
+
+/// Before locking: check the issuer pre-signed an award of `badge_definition` to `buyer`.
+pub fn check_badge_award(award: &UnsignedEvent, adaptor_sig: &AdaptorSignature, badge_definition: &Event, buyer: &XOnlyPublicKey) -> Result<()> {
+    check_presigned_event(award, adaptor_sig)?;
+    badge_award_matches(&award.pubkey, award.kind, &award.tags, badge_definition, buyer)
+}
+
+/// Check a completed event is the issuer's signed award of `badge_definition` to `buyer`.
+pub fn verify_badge_award(award: &Event, badge_definition: &Event, buyer: &XOnlyPublicKey) -> Result<()> {
+    award.verify()?;
+    badge_award_matches(&award.pubkey, award.kind, &award.tags, badge_definition, buyer)
+}
+
+fn badge_award_matches(author: &nostr::secp256k1::XOnlyPublicKey, kind: Kind, tags: &[Tag], badge_definition: &Event, buyer: &XOnlyPublicKey) -> Result<()> {
+    if kind != Kind::BadgeAward {
+        return Err(anyhow!("Event is kind {}, not a badge award", kind));
+    }
+    let issuer = badge_definition.author();
+    if *author != issuer {
+        return Err(anyhow!("Award author {} is not the badge issuer {}", author, issuer));
+    }
+    let identifier = badge_definition.identifier().ok_or_else(|| anyhow!("Badge definition has no d tag"))?;
+    let awards_badge = tags.iter().any(|tag| matches!(tag,
+        Tag::A { kind: Kind::BadgeDefinition, public_key, identifier: id, .. } if *public_key == issuer && id == identifier));
+    if !awards_badge {
+        return Err(anyhow!("Award does not reference badge 30009:{}:{}", issuer, identifier));
+    }
+    let buyer = to_nostr_pubkey(buyer)?;
+    if !tags.iter().any(|tag| matches!(tag, Tag::PublicKey { public_key, .. } if *public_key == buyer)) {
+        return Err(anyhow!("Award does not name {}", buyer));
+    }
+    Ok(())
+}
+
+/// A bidirectional text-frame connection to a single relay.
+pub struct RelayConnection {
+    pub outgoing: mpsc::UnboundedSender<String>,
+    pub incoming: mpsc::UnboundedReceiver<String>,
+}
+
+/// Opens relay connections. Implement this to run the client over something other than WebSockets.
+pub trait RelayTransport {
+    fn connect(&self, url: &str) -> impl Future<Output = Result<RelayConnection>> + Send;
+}
+
+/// Default transport: one WebSocket per relay.
+#[derive(Debug, Clone, Default)]
+pub struct WebSocketTransport;
+
+impl RelayTransport for WebSocketTransport {
+    async fn connect(&self, url: &str) -> Result<RelayConnection> {
+        let (ws, _) = tokio_tungstenite::connect_async(url).await?;
+        let (mut sink, mut stream) = ws.split();
+        let (outgoing, mut out_rx) = mpsc::unbounded_channel::<String>();
+        let (in_tx, incoming) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Some(msg) = out_rx.recv().await {
+                if sink.send(tungstenite::Message::Text(msg)).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+        tokio::spawn(async move {
+            while let Some(Ok(msg)) = stream.next().await {
+                let tungstenite::Message::Text(text) = msg else {
+                    if msg.is_close() {
+                        break;
+                    }
+                    continue;
+                };
+                if in_tx.send(text).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(RelayConnection { outgoing, incoming })
+    }
+}
+
+/// Async client that publishes to and subscribes on a set of relays.
+/// Events received from several relays are verified and delivered once per subscription.
+pub struct RelayClient<T: RelayTransport> {
+    transport: T,
+    relays: Vec<String>,
+    connections: HashMap<String, mpsc::UnboundedSender<String>>,
+    incoming_tx: mpsc::UnboundedSender<(String, String)>,
+    incoming: mpsc::UnboundedReceiver<(String, String)>,
+    /// Events received for an open subscription while waiting for something else.
+    pending: VecDeque<(String, RelayMessage)>,
+    /// Open subscriptions and the events they have delivered.
+    seen: HashMap<SubscriptionId, HashSet<EventId>>,
+}
+
+impl<T: RelayTransport> RelayClient<T> {
+    pub fn new(transport: T, relays: Vec<String>) -> Self {
+        let (incoming_tx, incoming) = mpsc::unbounded_channel();
+        RelayClient {
+            transport,
+            relays,
+            connections: HashMap::new(),
+            incoming_tx,
+            incoming,
+            pending: VecDeque::new(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Connect to every configured relay. Returns the relays that connected;
+    /// fails only if none did.
+    pub async fn connect(&mut self) -> Result<Vec<String>> {
+        let mut errors = Vec::new();
+        for url in self.relays.clone() {
+            if self.connections.contains_key(&url) {
+                continue;
+            }
+            match self.transport.connect(&url).await {
+                Ok(RelayConnection { outgoing, mut incoming }) => {
+                    let tx = self.incoming_tx.clone();
+                    let relay = url.clone();
+                    tokio::spawn(async move {
+                        while let Some(msg) = incoming.recv().await {
+                            if tx.send((relay.clone(), msg)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                    self.connections.insert(url, outgoing);
+                }
+                Err(e) => errors.push(format!("{}: {}", url, e)),
+            }
+        }
+        if self.connections.is_empty() {
+            return Err(anyhow!("Failed to connect to any relay: {}", errors.join(", ")));
+        }
+        Ok(self.connections.keys().cloned().collect())
+    }
+
+    fn send(&mut self, msg: &ClientMessage) {
+        let json = msg.as_json();
+        self.connections.retain(|_, outgoing| outgoing.send(json.clone()).is_ok());
+    }
+
+    /// Keep an event for a later `next_event`, unless its subscription has been closed.
+    fn buffer(&mut self, relay: String, msg: RelayMessage) {
+        if let RelayMessage::Event { subscription_id, .. } = &msg {
+            if self.seen.contains_key(subscription_id) {
+                self.pending.push_back((relay, msg));
+            }
+        }
+    }
+
+    async fn recv_until(&mut self, deadline: Instant) -> Option<(String, RelayMessage)> {
+        loop {
+            let (relay, raw) = tokio::time::timeout_at(deadline, self.incoming.recv()).await.ok()??;
+            if let Ok(msg) = RelayMessage::from_json(raw) {
+                return Some((relay, msg));
+            }
+        }
+    }
+
+    /// Publish an event to all connected relays and wait for their `OK`.
+    /// Returns the relays that accepted the event.
+    pub async fn publish(&mut self, event: &Event, timeout: Duration) -> Result<Vec<String>> {
+        if self.connections.is_empty() {
+            return Err(anyhow!("Not connected to any relay"));
+        }
+        self.send(&ClientMessage::event(event.clone()));
+
+        let deadline = Instant::now() + timeout;
+        let mut waiting: HashSet<String> = self.connections.keys().cloned().collect();
+        let mut accepted = Vec::new();
+        while !waiting.is_empty() {
+            let Some((relay, msg)) = self.recv_until(deadline).await else {
+                break;
+            };
+            match msg {
+                RelayMessage::Ok { event_id, status, .. } if event_id == event.id => {
+                    let first_reply = waiting.remove(&relay);
+                    if first_reply && status {
+                        accepted.push(relay);
+                    }
+                }
+                msg @ RelayMessage::Event { .. } => self.buffer(relay, msg),
+                _ => {}
+            }
+        }
+        Ok(accepted)
+    }
+
+    /// Open a subscription with the given filters on every connected relay.
+    pub fn subscribe(&mut self, filters: Vec<Filter>) -> Result<SubscriptionId> {
+        if self.connections.is_empty() {
+            return Err(anyhow!("Not connected to any relay"));
+        }
+        let subscription_id = SubscriptionId::generate();
+        self.seen.insert(subscription_id.clone(), HashSet::new());
+        self.send(&ClientMessage::req(subscription_id.clone(), filters));
+        Ok(subscription_id)
+    }
+
+    pub fn unsubscribe(&mut self, subscription_id: &SubscriptionId) {
+        self.seen.remove(subscription_id);
+        self.pending.retain(|(_, msg)| !matches!(msg, RelayMessage::Event { subscription_id: id, .. } if id == subscription_id));
+        self.send(&ClientMessage::close(subscription_id.clone()));
+    }
+
+    /// Next verified, not yet seen event for the subscription, or `None` on timeout.
+    pub async fn next_event(&mut self, subscription_id: &SubscriptionId, timeout: Duration) -> Option<Event> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let msg = match self.pending.iter().position(|(_, m)| matches!(m, RelayMessage::Event { subscription_id: id, .. } if id == subscription_id)) {
+                Some(i) => self.pending.remove(i),
+                None => self.recv_until(deadline).await,
+            };
+            let (relay, msg) = msg?;
+            match msg {
+                RelayMessage::Event { subscription_id: id, event } if &id == subscription_id => {
+                    let fresh = event.verify().is_ok() && self.seen.get_mut(&id).is_some_and(|seen| seen.insert(event.id));
+                    if fresh {
+                        return Some(*event);
+                    }
+                }
+                RelayMessage::Event { .. } => self.buffer(relay, msg),
+                _ => {}
+            }
+        }
+    }
+
+    /// Subscribe with `filter` and wait for the first matching event, e.g. the seller's completed event.
+    pub async fn wait_for_event(&mut self, filter: Filter, timeout: Duration) -> Result<Option<Event>> {
+        let subscription_id = self.subscribe(vec![filter.clone()])?;
+        let deadline = Instant::now() + timeout;
+        let mut found = None;
+        while let Some(event) = self.next_event(&subscription_id, deadline.saturating_duration_since(Instant::now())).await {
+            if filter_matches(&filter, &event) {
+                found = Some(event);
+                break;
+            }
+        }
+        self.unsubscribe(&subscription_id);
+        Ok(found)
+    }
+}
+
+#[cfg(all(test, feature = "relay"))]
+mod tests {
+    use super::*;
+    use crate::relay::Relay;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    async fn client(relay: &Relay, relays: &[&str]) -> RelayClient<Relay> {
+        let mut client = RelayClient::new(relay.clone(), relays.iter().map(|r| r.to_string()).collect());
+        client.connect().await.unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn publish_is_accepted_and_delivered_to_subscribers() {
+        let relay = Relay::new();
+        let keys = Keys::generate();
+        let mut watcher = client(&relay, &["ws://a"]).await;
+        let filter = Filter::new().author(keys.public_key()).kind(Kind::TextNote);
+        let subscription_id = watcher.subscribe(vec![filter]).unwrap();
+
+        let event = create_signed_event(&keys, Kind::TextNote, "hello", vec![]);
+        let mut publisher = client(&relay, &["ws://a"]).await;
+        assert_eq!(publisher.publish(&event, TIMEOUT).await.unwrap(), vec!["ws://a".to_string()]);
+
+        assert_eq!(watcher.next_event(&subscription_id, TIMEOUT).await.map(|e| e.id), Some(event.id));
+        assert_eq!(relay.events().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn event_from_several_relays_is_delivered_once_per_subscription() {
+        let relay = Relay::new();
+        let keys = Keys::generate();
+        let event = create_signed_event(&keys, Kind::TextNote, "stored", vec![]);
+        client(&relay, &["ws://a"]).await.publish(&event, TIMEOUT).await.unwrap();
+
+        // Both "relays" are connections to the same relay, so each stored event arrives twice.
+        let mut watcher = client(&relay, &["ws://a", "ws://b"]).await;
+        let first = watcher.subscribe(vec![Filter::new().author(keys.public_key())]).unwrap();
+        let second = watcher.subscribe(vec![Filter::new().kind(Kind::TextNote)]).unwrap();
+
+        assert_eq!(watcher.next_event(&first, TIMEOUT).await.map(|e| e.id), Some(event.id));
+        assert_eq!(watcher.next_event(&second, TIMEOUT).await.map(|e| e.id), Some(event.id));
+        assert!(watcher.next_event(&first, Duration::from_millis(200)).await.is_none());
+        assert!(watcher.next_event(&second, Duration::from_millis(200)).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn closed_subscriptions_do_not_keep_events() {
+        let relay = Relay::new();
+        let keys = Keys::generate();
+        let mut client = client(&relay, &["ws://a"]).await;
+        let subscription_id = client.subscribe(vec![Filter::new().author(keys.public_key())]).unwrap();
+        // Events for the subscription arrive while publishing and wait in the buffer.
+        client.publish(&create_signed_event(&keys, Kind::TextNote, "first", vec![]), TIMEOUT).await.unwrap();
+        client.publish(&create_signed_event(&keys, Kind::TextNote, "second", vec![]), TIMEOUT).await.unwrap();
+        assert!(!client.pending.is_empty());
+
+        client.unsubscribe(&subscription_id);
+        assert!(client.pending.is_empty());
+        client.publish(&create_signed_event(&keys, Kind::TextNote, "third", vec![]), TIMEOUT).await.unwrap();
+        assert!(client.pending.is_empty());
+        assert!(client.next_event(&subscription_id, Duration::from_millis(200)).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn wait_for_event_ignores_events_outside_the_filter() {
+        let relay = Relay::new();
+        let seller = Keys::generate();
+        let mut publisher = client(&relay, &["ws://a"]).await;
+        publisher.publish(&create_signed_event(&Keys::generate(), Kind::TextNote, "other", vec![]), TIMEOUT).await.unwrap();
+        let completed = create_signed_event(&seller, Kind::TextNote, "completed", vec![]);
+        publisher.publish(&completed, TIMEOUT).await.unwrap();
+
+        let seller_pubkey = XOnlyPublicKey::from_slice(&seller.public_key().serialize()).unwrap();
+        let filter = completed_event_filter(&seller_pubkey, Kind::TextNote, &[]).unwrap();
+        let mut watcher = client(&relay, &["ws://a"]).await;
+        let found = watcher.wait_for_event(filter.clone(), TIMEOUT).await.unwrap();
+        assert_eq!(found.map(|e| e.id), Some(completed.id));
+
+        let missing = completed_event_filter(&seller_pubkey, Kind::Metadata, &[]).unwrap();
+        assert!(watcher.wait_for_event(missing, Duration::from_millis(200)).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn filter_matches_tags() {
+        let keys = Keys::generate();
+        let event = create_signed_event(&keys, Kind::TextNote, "", vec![Tag::Hashtag("tapstr".into())]);
+        let filter = |value: &str| Filter::new().custom_tag(Alphabet::T, [value.to_string()]);
+        assert!(filter_matches(&filter("tapstr"), &event));
+        assert!(!filter_matches(&filter("other"), &event));
+    }
+
+    fn badge_definition(issuer: &secp256k1::Keypair) -> Event {
+        let keys = Keys::new(nostr::secp256k1::SecretKey::from_slice(&issuer.secret_bytes()).unwrap());
+        EventBuilder::define_badge("supporter", None, None, None, None, vec![]).to_event(&keys).unwrap()
+    }
+
+    #[tokio::test]
+    async fn badge_award_must_be_presigned_by_the_issuer() {
+        let secp = Secp256k1::new();
+        let issuer = secp256k1::Keypair::new(&secp, &mut rand::thread_rng());
+        let buyer = secp256k1::Keypair::new(&secp, &mut rand::thread_rng()).x_only_public_key().0;
+        let definition = badge_definition(&issuer);
+        let adaptor_point = SecretKey::new(&mut rand::thread_rng()).public_key(&secp);
+        let (award, adaptor_sig) = presign_badge_award(&issuer, &definition, &buyer, &adaptor_point).await.unwrap();
+        check_badge_award(&award, &adaptor_sig, &definition, &buyer).unwrap();
+
+        // Someone else's pre-signature over the issuer's award.
+        let forger = secp256k1::Keypair::new(&secp, &mut rand::thread_rng());
+        let forged = forger.presign(&event_message(&award.id), &adaptor_point, crate::signer::SigningKey::Internal).await.unwrap();
+        assert!(check_badge_award(&award, &forged, &definition, &buyer).is_err());
+
+        // Tags that no longer hash to the pre-signed id.
+        let mut altered = award.clone();
+        altered.tags.retain(|tag| !matches!(tag, Tag::PublicKey { .. }));
+        altered.tags.push(Tag::public_key(to_nostr_pubkey(&forger.x_only_public_key().0).unwrap()));
+        assert!(check_badge_award(&altered, &adaptor_sig, &definition, &forger.x_only_public_key().0).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use nostr::{Event, Filter, ClientMessage, RelayMessage, SubscriptionId, JsonUtil};
+use axum::{
+    extract::{ws::{Message as WsMessage, WebSocket, WebSocketUpgrade}, State},
+    response::Response,
+    routing::get,
+    Router,
+};
+use anyhow::Result;
+use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use crate::nostr_utils::{filter_matches, RelayConnection, RelayTransport};
+
+struct Subscriber {
+    sender: mpsc::UnboundedSender<String>,
+    subscriptions: HashMap<SubscriptionId, Vec<Filter>>,
+}
+
+#[derive(Default)]
+struct RelayState {
+    events: Vec<Event>,
+    subscribers: HashMap<u64, Subscriber>,
+    next_id: u64,
+}
+
+/// Minimal in-memory NIP-01 relay (EVENT, REQ, CLOSE, EOSE) for local testing and demos.
+/// Events are only accepted with a valid id and signature.
+#[derive(Clone, Default)]
+pub struct Relay {
+    state: Arc<Mutex<RelayState>>,
+}
+
+impl Relay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach an in-process client. The relay processes frames until the connection is dropped.
+    pub fn open_connection(&self) -> RelayConnection {
+        let (outgoing, mut from_client) = mpsc::unbounded_channel::<String>();
+        let (to_client, incoming) = mpsc::unbounded_channel();
+
+        let conn_id = {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.subscribers.insert(id, Subscriber { sender: to_client, subscriptions: HashMap::new() });
+            id
+        };
+
+        let relay = self.clone();
+        tokio::spawn(async move {
+            while let Some(raw) = from_client.recv().await {
+                relay.handle_message(conn_id, &raw);
+            }
+            relay.state.lock().unwrap().subscribers.remove(&conn_id);
+        });
+
+        RelayConnection { outgoing, incoming }
+    }
+
+    /// All events stored so far.
+    pub fn events(&self) -> Vec<Event> {
+        self.state.lock().unwrap().events.clone()
+    }
+
+    fn handle_message(&self, conn_id: u64, raw: &str) {
+        let mut state = self.state.lock().unwrap();
+        let reply = |state: &RelayState, msg: RelayMessage| {
+            if let Some(sub) = state.subscribers.get(&conn_id) {
+                let _ = sub.sender.send(msg.as_json());
+            }
+        };
+
+        let msg = match ClientMessage::from_json(raw) {
+            Ok(msg) => msg,
+            Err(e) => {
+                reply(&state, RelayMessage::notice(format!("error: invalid message: {}", e)));
+                return;
+            }
+        };
+
+        match msg {
+            ClientMessage::Event(event) => {
+                if let Err(e) = event.verify() {
+                    reply(&state, RelayMessage::ok(event.id, false, format!("invalid: {}", e)));
+                    return;
+                }
+                if state.events.iter().any(|e| e.id == event.id) {
+                    reply(&state, RelayMessage::ok(event.id, true, "duplicate: already have this event"));
+                    return;
+                }
+                reply(&state, RelayMessage::ok(event.id, true, ""));
+
+                for sub in state.subscribers.values() {
+                    for (subscription_id, filters) in &sub.subscriptions {
+                        if filters.iter().any(|f| filter_matches(f, &event)) {
+                            let _ = sub.sender.send(RelayMessage::event(subscription_id.clone(), *event.clone()).as_json());
+                        }
+                    }
+                }
+                if !event.is_ephemeral() {
+                    state.events.push(*event);
+                }
+            }
+            ClientMessage::Req { subscription_id, filters } => {
+                for filter in &filters {
+                    let matching = state.events.iter().rev().filter(|e| filter_matches(filter, e));
+                    let stored: Vec<&Event> = match filter.limit {
+                        Some(limit) => matching.take(limit).collect(),
+                        None => matching.collect(),
+                    };
+                    for event in stored.into_iter().rev() {
+                        reply(&state, RelayMessage::event(subscription_id.clone(), event.clone()));
+                    }
+                }
+                reply(&state, RelayMessage::eose(subscription_id.clone()));
+                if let Some(sub) = state.subscribers.get_mut(&conn_id) {
+                    sub.subscriptions.insert(subscription_id, filters);
+                }
+            }
+            ClientMessage::Close(subscription_id) => {
+                if let Some(sub) = state.subscribers.get_mut(&conn_id) {
+                    sub.subscriptions.remove(&subscription_id);
+                }
+            }
+            _ => reply(&state, RelayMessage::notice("error: unsupported message")),
+        }
+    }
+
+    /// Serve the relay over WebSocket at `/`.
+    pub fn router(&self) -> Router {
+        Router::new().route("/", get(ws_handler)).with_state(self.clone())
+    }
+
+    async fn serve_socket(self, socket: WebSocket) {
+        let RelayConnection { outgoing, mut incoming } = self.open_connection();
+        let (mut sink, mut stream) = socket.split();
+
+        let writer = tokio::spawn(async move {
+            while let Some(msg) = incoming.recv().await {
+                if sink.send(WsMessage::Text(msg)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        while let Some(Ok(msg)) = stream.next().await {
+            let WsMessage::Text(text) = msg else {
+                if matches!(msg, WsMessage::Close(_)) {
+                    break;
+                }
+                continue;
+            };
+            if outgoing.send(text).is_err() {
+                break;
+            }
+        }
+        drop(outgoing);
+        writer.abort();
+    }
+}
+
+/// In-process transport: every relay URL connects to this relay, no network involved.
+impl RelayTransport for Relay {
+    async fn connect(&self, _url: &str) -> Result<RelayConnection> {
+        Ok(self.open_connection())
+    }
+}
+
+async fn ws_handler(State(relay): State<Relay>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| relay.serve_socket(socket))
+}
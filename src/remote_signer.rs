@@ -0,0 +1,392 @@
+use crate::adaptor::AdaptorSignature;
+use crate::api::PreSignature;
+use crate::nostr_utils::{self, event_message, RelayClient, RelayTransport};
+use crate::signer::{AdaptorSigner, SchnorrSigner, SigningKey};
+use anyhow::{anyhow, Context, Result};
+use nostr::nips::{nip04, nip46::Message as ConnectMessage};
+use nostr::{Event, EventBuilder, Filter, JsonUtil, Keys, Kind, SubscriptionId, Timestamp, UnsignedEvent, Url};
+use rand::{rngs::OsRng, RngCore};
+use secp256k1::{schnorr::Signature, Keypair, Message, PublicKey, Secp256k1, XOnlyPublicKey};
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// NIP-46 method tapstr adds: adaptor pre-signature of an event. Params are the unsigned
+/// event JSON and the compressed adaptor point in hex; the result is the `PreSignature` JSON.
+/// Only events are pre-signed, so a bunker never signs a raw message such as a sighash.
+pub const PRESIGN_METHOD: &str = "tapstr_presign";
+
+/// `bunker://<signer-pubkey>?relay=<url>&secret=<token>`: how to reach a remote signer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BunkerUri {
+    pub signer: XOnlyPublicKey,
+    pub relays: Vec<String>,
+    pub secret: Option<String>,
+}
+
+impl FromStr for BunkerUri {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let url = Url::parse(s).with_context(|| format!("Invalid bunker URI {}", s))?;
+        if url.scheme() != "bunker" {
+            return Err(anyhow!("Bunker URI must start with bunker://"));
+        }
+        let signer = XOnlyPublicKey::from_str(url.host_str().unwrap_or_default()).context("Invalid signer key in bunker URI")?;
+        let mut relays = Vec::new();
+        let mut secret = None;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "relay" => relays.push(value.into_owned()),
+                "secret" => secret = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        if relays.is_empty() {
+            return Err(anyhow!("Bunker URI names no relay"));
+        }
+        Ok(BunkerUri { signer, relays, secret })
+    }
+}
+
+impl std::fmt::Display for BunkerUri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut url = Url::parse(&format!("bunker://{}", self.signer)).map_err(|_| std::fmt::Error)?;
+        {
+            let mut query = url.query_pairs_mut();
+            for relay in &self.relays {
+                query.append_pair("relay", relay);
+            }
+            if let Some(secret) = &self.secret {
+                query.append_pair("secret", secret);
+            }
+        }
+        f.write_str(url.as_str())
+    }
+}
+
+/// NIP-46 client: the key stays in a bunker and requests travel as encrypted kind 24133 events.
+/// Bunkers only sign and pre-sign Nostr events, so `sign_schnorr` and `presign` of a raw
+/// message fail; use `presign_event`.
+pub struct RemoteSigner<T: RelayTransport> {
+    client: tokio::sync::Mutex<(RelayClient<T>, SubscriptionId)>,
+    client_keys: Keys,
+    signer: nostr::secp256k1::XOnlyPublicKey,
+    user: XOnlyPublicKey,
+    timeout: Duration,
+}
+
+impl<T: RelayTransport> RemoteSigner<T> {
+    /// Connect to the bunker, authenticate with the URI's secret and fetch the seller's key.
+    pub async fn connect(transport: T, uri: &BunkerUri, timeout: Duration) -> Result<Self> {
+        let client_keys = Keys::generate();
+        let signer = nostr_utils::to_nostr_pubkey(&uri.signer)?;
+        let mut client = RelayClient::new(transport, uri.relays.clone());
+        client.connect().await?;
+        let filter = Filter::new()
+            .kind(Kind::NostrConnect)
+            .author(signer)
+            .pubkey(client_keys.public_key())
+            .since(Timestamp::now() - Duration::from_secs(10));
+        let subscription_id = client.subscribe(vec![filter])?;
+        let mut remote = RemoteSigner {
+            client: tokio::sync::Mutex::new((client, subscription_id)),
+            client_keys,
+            signer,
+            user: uri.signer,
+            timeout,
+        };
+
+        let mut params = vec![remote.client_keys.public_key().to_string()];
+        params.extend(uri.secret.clone());
+        remote.request("connect", params).await?;
+        remote.user = XOnlyPublicKey::from_str(&remote.request("get_public_key", vec![]).await?).context("Bunker returned an invalid public key")?;
+        Ok(remote)
+    }
+
+    /// Send one request and wait for the matching response.
+    pub async fn request(&self, method: &str, params: Vec<String>) -> Result<String> {
+        let id = OsRng.next_u64().to_string();
+        let message = ConnectMessage::Request {
+            id: id.clone(),
+            method: method.to_string(),
+            params: params.into_iter().map(serde_json::Value::String).collect(),
+        };
+        let event = EventBuilder::nostr_connect(&self.client_keys, self.signer, message)?.to_event(&self.client_keys)?;
+
+        let mut guard = self.client.lock().await;
+        let (client, subscription_id) = &mut *guard;
+        if client.publish(&event, self.timeout).await?.is_empty() {
+            return Err(anyhow!("No relay accepted the {} request", method));
+        }
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            let reply = client.next_event(subscription_id, remaining).await.ok_or_else(|| anyhow!("Bunker did not answer {} in time", method))?;
+            let Ok(plaintext) = nip04::decrypt(&self.client_keys.secret_key()?, &reply.pubkey, &reply.content) else {
+                continue;
+            };
+            match ConnectMessage::from_json(plaintext) {
+                Ok(ConnectMessage::Response { id: reply_id, result, error }) if reply_id == id => {
+                    if let Some(error) = error {
+                        return Err(anyhow!("Bunker refused {}: {}", method, error));
+                    }
+                    return match result {
+                        Some(serde_json::Value::String(result)) => Ok(result),
+                        Some(result) => Ok(result.to_string()),
+                        None => Err(anyhow!("Bunker sent an empty {} response", method)),
+                    };
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+impl<T: RelayTransport + Send> SchnorrSigner for RemoteSigner<T> {
+    async fn public_key(&self) -> Result<XOnlyPublicKey> {
+        Ok(self.user)
+    }
+
+    async fn sign_schnorr(&self, _message: &Message, _key: SigningKey) -> Result<Signature> {
+        Err(anyhow!("Bunkers do not sign raw messages"))
+    }
+}
+
+impl<T: RelayTransport + Send> AdaptorSigner for RemoteSigner<T> {
+    async fn presign(&self, _message: &Message, _adaptor_point: &PublicKey, _key: SigningKey) -> Result<AdaptorSignature> {
+        Err(anyhow!("Bunkers only pre-sign events"))
+    }
+
+    /// Sends the whole event, so the bunker can show what it is pre-signing.
+    async fn presign_event(&self, event: &UnsignedEvent, adaptor_point: &PublicKey) -> Result<AdaptorSignature> {
+        let result = self.request(PRESIGN_METHOD, vec![event.as_json(), adaptor_point.to_string()]).await?;
+        self.check_presignature(&result, &event_message(&event.id), adaptor_point)
+    }
+}
+
+impl<T: RelayTransport> RemoteSigner<T> {
+    /// The bunker is not trusted to have used our message, key or adaptor point.
+    fn check_presignature(&self, result: &str, message: &Message, adaptor_point: &PublicKey) -> Result<AdaptorSignature> {
+        let presignature: PreSignature = serde_json::from_str(result).context("Bunker sent an invalid pre-signature")?;
+        let adaptor_sig = AdaptorSignature::from_parts(presignature.nonce_point, presignature.adaptor_point, presignature.s, presignature.pubkey, message);
+        if adaptor_sig.adaptor_point != *adaptor_point
+            || adaptor_sig.pubkey.x_only_public_key().0 != self.user
+            || !adaptor_sig.verify(&Secp256k1::new())
+        {
+            return Err(anyhow!("Bunker returned a pre-signature that does not verify"));
+        }
+        Ok(adaptor_sig)
+    }
+}
+
+/// A minimal NIP-46 remote signer holding the seller's key, standing in for a real bunker
+/// in development and demos. It answers `connect`, `get_public_key`, `ping`, `sign_event`
+/// and `tapstr_presign`, and only to clients that connected with its secret or are allowed.
+pub struct Bunker {
+    keypair: Keypair,
+    secret: Option<String>,
+    allowed: HashSet<nostr::secp256k1::XOnlyPublicKey>,
+    clients: Mutex<HashSet<nostr::secp256k1::XOnlyPublicKey>>,
+}
+
+impl Bunker {
+    /// Serves no one until given a secret or an allowed client.
+    pub fn new(keypair: Keypair) -> Self {
+        Bunker { keypair, secret: None, allowed: HashSet::new(), clients: Mutex::new(HashSet::new()) }
+    }
+
+    /// Token a client must present on `connect`.
+    pub fn with_secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// Let `client` connect without the secret.
+    pub fn allow_client(mut self, client: XOnlyPublicKey) -> Result<Self> {
+        self.allowed.insert(nostr_utils::to_nostr_pubkey(&client)?);
+        Ok(self)
+    }
+
+    pub fn uri(&self, relays: Vec<String>) -> BunkerUri {
+        BunkerUri { signer: self.keypair.x_only_public_key().0, relays, secret: self.secret.clone() }
+    }
+
+    fn keys(&self) -> Result<Keys> {
+        Ok(Keys::new(nostr::secp256k1::SecretKey::from_slice(&self.keypair.secret_bytes())?))
+    }
+
+    /// Only events authored by this key, with ids that hash their fields, are signed.
+    fn check_event(&self, event: &UnsignedEvent) -> Result<()> {
+        nostr_utils::check_event_id(event)?;
+        let pubkey = self.keypair.x_only_public_key().0;
+        if event.pubkey.serialize() != pubkey.serialize() {
+            return Err(anyhow!("event author {} is not the signer {}", event.pubkey, pubkey));
+        }
+        Ok(())
+    }
+
+    /// Answer one request from `client`.
+    pub async fn handle(&self, client: nostr::secp256k1::XOnlyPublicKey, method: &str, params: &[String]) -> Result<String> {
+        if method == "connect" {
+            let has_secret = self.secret.is_some() && params.get(1) == self.secret.as_ref();
+            if !has_secret && !self.allowed.contains(&client) {
+                return Err(anyhow!("unauthorized"));
+            }
+            self.clients.lock().unwrap().insert(client);
+            return Ok("ack".to_string());
+        }
+        if !self.clients.lock().unwrap().contains(&client) {
+            return Err(anyhow!("not connected"));
+        }
+        match method {
+            "get_public_key" => Ok(self.keypair.x_only_public_key().0.to_string()),
+            "ping" => Ok("pong".to_string()),
+            "sign_event" => {
+                let event = UnsignedEvent::from_json(params.first().ok_or_else(|| anyhow!("missing event"))?)?;
+                self.check_event(&event)?;
+                Ok(event.sign(&self.keys()?)?.as_json())
+            }
+            PRESIGN_METHOD => {
+                let [event, adaptor_point] = params else {
+                    return Err(anyhow!("expected an event and an adaptor point"));
+                };
+                let event = UnsignedEvent::from_json(event)?;
+                self.check_event(&event)?;
+                let adaptor_sig = self.keypair.presign_event(&event, &PublicKey::from_str(adaptor_point)?).await?;
+                Ok(serde_json::to_string(&PreSignature::from(&adaptor_sig))?)
+            }
+            other => Err(anyhow!("unsupported method {}", other)),
+        }
+    }
+
+    /// Serve requests arriving on `relays` until the connection fails.
+    pub async fn run<T: RelayTransport>(&self, transport: T, relays: Vec<String>) -> Result<()> {
+        let keys = self.keys()?;
+        let mut client = RelayClient::new(transport, relays);
+        client.connect().await?;
+        let filter = Filter::new().kind(Kind::NostrConnect).pubkey(keys.public_key()).since(Timestamp::now());
+        let subscription_id = client.subscribe(vec![filter])?;
+        loop {
+            let Some(request) = client.next_event(&subscription_id, Duration::from_secs(60)).await else {
+                continue;
+            };
+            if let Some(reply) = self.reply(&keys, &request).await? {
+                client.publish(&reply, Duration::from_secs(10)).await?;
+            }
+        }
+    }
+
+    async fn reply(&self, keys: &Keys, request: &Event) -> Result<Option<Event>> {
+        let Ok(plaintext) = nip04::decrypt(&keys.secret_key()?, &request.pubkey, &request.content) else {
+            return Ok(None);
+        };
+        let Ok(ConnectMessage::Request { id, method, params }) = ConnectMessage::from_json(plaintext) else {
+            return Ok(None);
+        };
+        let params: Vec<String> = params.into_iter().map(|p| p.as_str().map(str::to_string).unwrap_or_else(|| p.to_string())).collect();
+        let response = match self.handle(request.pubkey, &method, &params).await {
+            Ok(result) => ConnectMessage::Response { id, result: Some(serde_json::Value::String(result)), error: None },
+            Err(e) => ConnectMessage::Response { id, result: None, error: Some(e.to_string()) },
+        };
+        Ok(Some(EventBuilder::nostr_connect(keys, request.pubkey, response)?.to_event(keys)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::EventId;
+
+    fn keypair() -> Keypair {
+        Keypair::new(&Secp256k1::new(), &mut OsRng)
+    }
+
+    fn client() -> (XOnlyPublicKey, nostr::secp256k1::XOnlyPublicKey) {
+        let pubkey = keypair().x_only_public_key().0;
+        (pubkey, nostr_utils::to_nostr_pubkey(&pubkey).unwrap())
+    }
+
+    fn params(params: &[&str]) -> Vec<String> {
+        params.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn bunker_serves_only_authorized_clients() {
+        let (_, stranger) = client();
+        let open = Bunker::new(keypair());
+        assert!(open.handle(stranger, "connect", &params(&["bunker"])).await.is_err());
+        assert!(open.handle(stranger, "get_public_key", &[]).await.is_err());
+
+        let bunker = Bunker::new(keypair()).with_secret("token");
+        assert!(bunker.handle(stranger, "connect", &params(&["bunker", "guess"])).await.is_err());
+        assert!(bunker.handle(stranger, "ping", &[]).await.is_err());
+        let (_, holder) = client();
+        assert_eq!(bunker.handle(holder, "connect", &params(&["bunker", "token"])).await.unwrap(), "ack");
+        assert_eq!(bunker.handle(holder, "ping", &[]).await.unwrap(), "pong");
+
+        let (allowed, allowed_nostr) = client();
+        let bunker = Bunker::new(keypair()).allow_client(allowed).unwrap();
+        assert!(bunker.handle(stranger, "connect", &params(&["bunker"])).await.is_err());
+        assert_eq!(bunker.handle(allowed_nostr, "connect", &params(&["bunker"])).await.unwrap(), "ack");
+    }
+
+    #[tokio::test]
+    async fn bunker_never_signs_raw_messages() {
+        let bunker = Bunker::new(keypair()).with_secret("token");
+        let (_, client) = client();
+        bunker.handle(client, "connect", &params(&["bunker", "token"])).await.unwrap();
+        let message = hex::encode([7u8; 32]);
+        let adaptor_point = keypair().public_key().to_string();
+        assert!(bunker.handle(client, "tapstr_sign", &params(&[&message, "internal"])).await.is_err());
+        assert!(bunker.handle(client, PRESIGN_METHOD, &params(&[&message, &adaptor_point, "internal"])).await.is_err());
+        assert!(bunker.handle(client, PRESIGN_METHOD, &params(&[&message, &adaptor_point])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn bunker_signs_only_its_own_events_with_honest_ids() {
+        let seller = keypair();
+        let bunker = Bunker::new(seller).with_secret("token");
+        let (_, client) = client();
+        bunker.handle(client, "connect", &params(&["bunker", "token"])).await.unwrap();
+        let adaptor_point = keypair().public_key().to_string();
+        let event = nostr_utils::create_unsigned_event(&seller.x_only_public_key().0, Kind::TextNote, "for sale", vec![]).unwrap();
+        assert!(bunker.handle(client, "sign_event", &params(&[&event.as_json()])).await.is_ok());
+        assert!(bunker.handle(client, PRESIGN_METHOD, &params(&[&event.as_json(), &adaptor_point])).await.is_ok());
+
+        let forged = UnsignedEvent { id: EventId::from_slice(&[7; 32]).unwrap(), ..event };
+        assert!(bunker.handle(client, "sign_event", &params(&[&forged.as_json()])).await.is_err());
+        assert!(bunker.handle(client, PRESIGN_METHOD, &params(&[&forged.as_json(), &adaptor_point])).await.is_err());
+
+        let other = nostr_utils::create_unsigned_event(&keypair().x_only_public_key().0, Kind::TextNote, "for sale", vec![]).unwrap();
+        assert!(bunker.handle(client, "sign_event", &params(&[&other.as_json()])).await.is_err());
+        assert!(bunker.handle(client, PRESIGN_METHOD, &params(&[&other.as_json(), &adaptor_point])).await.is_err());
+    }
+
+    #[cfg(feature = "relay")]
+    #[tokio::test]
+    async fn remote_signer_presigns_events_through_the_bunker() {
+        let relay = crate::relay::Relay::new();
+        let seller = keypair();
+        let bunker = Bunker::new(seller).with_secret("token");
+        let relays = vec!["ws://relay".to_string()];
+        let uri = bunker.uri(relays.clone());
+        let transport = relay.clone();
+        tokio::spawn(async move { bunker.run(transport, relays).await });
+        // Requests are ephemeral events, so the bunker must subscribe before the first one.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let signer = RemoteSigner::connect(relay.clone(), &uri, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(signer.public_key().await.unwrap(), seller.x_only_public_key().0);
+        let event = nostr_utils::create_unsigned_event(&seller.x_only_public_key().0, Kind::TextNote, "for sale", vec![]).unwrap();
+        let adaptor_point = keypair().public_key();
+        let adaptor_sig = signer.presign_event(&event, &adaptor_point).await.unwrap();
+        assert!(adaptor_sig.verify(&Secp256k1::new()));
+        assert_eq!(adaptor_sig.message, event.id.as_bytes());
+        assert!(signer.sign_schnorr(&Message::from_digest([7; 32]), SigningKey::Internal).await.is_err());
+
+        let wrong = BunkerUri { secret: Some("guess".to_string()), ..uri };
+        assert!(RemoteSigner::connect(relay, &wrong, Duration::from_secs(2)).await.is_err());
+    }
+}
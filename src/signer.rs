@@ -0,0 +1,192 @@
+use crate::adaptor::AdaptorSignature;
+use crate::keystore::Keystore;
+use crate::nostr_utils::{check_event_id, event_message};
+use anyhow::{anyhow, Result};
+use bitcoin::key::TapTweak;
+use bitcoin::taproot::TapNodeHash;
+use nostr::UnsignedEvent;
+use rand::rngs::OsRng;
+use secp256k1::{schnorr::Signature, Keypair, Message, PublicKey, Secp256k1, XOnlyPublicKey};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Which of the signer's keys signs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningKey {
+    /// The key itself, as in tapscript leaves and Nostr events.
+    Internal,
+    /// The BIP341 output key committing to `merkle_root`, for taproot key-path spends.
+    Taproot(Option<TapNodeHash>),
+}
+
+impl SigningKey {
+    /// The public key signatures for `internal_key` verify against.
+    pub fn public_key(&self, internal_key: XOnlyPublicKey) -> XOnlyPublicKey {
+        match self {
+            SigningKey::Internal => internal_key,
+            SigningKey::Taproot(merkle_root) => internal_key.tap_tweak(&Secp256k1::new(), *merkle_root).0.to_inner(),
+        }
+    }
+
+    /// The private key to sign with when holding `keypair`.
+    pub fn keypair(&self, keypair: &Keypair) -> Keypair {
+        match self {
+            SigningKey::Internal => *keypair,
+            SigningKey::Taproot(merkle_root) => keypair.tap_tweak(&Secp256k1::new(), *merkle_root).to_inner(),
+        }
+    }
+}
+
+/// `internal`, `taproot` or `taproot:<merkle root hex>`, as sent to remote signers.
+impl std::fmt::Display for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SigningKey::Internal => f.write_str("internal"),
+            SigningKey::Taproot(None) => f.write_str("taproot"),
+            SigningKey::Taproot(Some(merkle_root)) => write!(f, "taproot:{}", merkle_root),
+        }
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "internal" => Ok(SigningKey::Internal),
+            None if s == "taproot" => Ok(SigningKey::Taproot(None)),
+            Some(("taproot", merkle_root)) => Ok(SigningKey::Taproot(Some(TapNodeHash::from_str(merkle_root)?))),
+            _ => Err(anyhow!("Unknown signing key {}", s)),
+        }
+    }
+}
+
+/// Produces BIP340 signatures with a key, wherever that key lives.
+pub trait SchnorrSigner: Sync {
+    /// The internal (untweaked) public key.
+    fn public_key(&self) -> impl Future<Output = Result<XOnlyPublicKey>> + Send;
+    fn sign_schnorr(&self, message: &Message, key: SigningKey) -> impl Future<Output = Result<Signature>> + Send;
+}
+
+/// Also produces adaptor pre-signatures, completed by the discrete log of the adaptor point.
+pub trait AdaptorSigner: SchnorrSigner {
+    fn presign(&self, message: &Message, adaptor_point: &PublicKey, key: SigningKey) -> impl Future<Output = Result<AdaptorSignature>> + Send;
+
+    /// Pre-sign `event`'s id, which must hash its fields and be authored by this signer.
+    fn presign_event(&self, event: &UnsignedEvent, adaptor_point: &PublicKey) -> impl Future<Output = Result<AdaptorSignature>> + Send {
+        async move {
+            check_event_id(event)?;
+            let pubkey = self.public_key().await?;
+            if event.pubkey.serialize() != pubkey.serialize() {
+                return Err(anyhow!("Event author {} is not the signer {}", event.pubkey, pubkey));
+            }
+            self.presign(&event_message(&event.id), adaptor_point, SigningKey::Internal).await
+        }
+    }
+}
+
+impl<S: SchnorrSigner> SchnorrSigner for &S {
+    fn public_key(&self) -> impl Future<Output = Result<XOnlyPublicKey>> + Send {
+        (**self).public_key()
+    }
+
+    fn sign_schnorr(&self, message: &Message, key: SigningKey) -> impl Future<Output = Result<Signature>> + Send {
+        (**self).sign_schnorr(message, key)
+    }
+}
+
+/// The key is in memory.
+impl SchnorrSigner for Keypair {
+    async fn public_key(&self) -> Result<XOnlyPublicKey> {
+        Ok(self.x_only_public_key().0)
+    }
+
+    async fn sign_schnorr(&self, message: &Message, key: SigningKey) -> Result<Signature> {
+        Ok(Secp256k1::new().sign_schnorr_with_rng(message, &key.keypair(self), &mut OsRng))
+    }
+}
+
+impl AdaptorSigner for Keypair {
+    async fn presign(&self, message: &Message, adaptor_point: &PublicKey, key: SigningKey) -> Result<AdaptorSignature> {
+        Ok(AdaptorSignature::with_adaptor_point(&Secp256k1::new(), &key.keypair(self), message, adaptor_point))
+    }
+}
+
+/// A key held in a `Keystore`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeystoreKey {
+    /// Long-lived key by name, e.g. `nostr`.
+    Identity(String),
+    /// The buyer's key in the swap output, by swap id.
+    Swap(String),
+}
+
+/// Signs with a key from a shared keystore, which must be unlocked whenever it signs.
+#[derive(Debug, Clone)]
+pub struct KeystoreSigner {
+    keystore: Arc<Mutex<Keystore>>,
+    key: KeystoreKey,
+    pubkey: XOnlyPublicKey,
+}
+
+impl KeystoreSigner {
+    /// Fails unless the keystore is unlocked and holds `key`.
+    pub fn new(keystore: Arc<Mutex<Keystore>>, key: KeystoreKey) -> Result<Self> {
+        let pubkey = load_keypair(&keystore, &key)?.x_only_public_key().0;
+        Ok(KeystoreSigner { keystore, key, pubkey })
+    }
+
+    fn keypair(&self) -> Result<Keypair> {
+        load_keypair(&self.keystore, &self.key)
+    }
+}
+
+fn load_keypair(keystore: &Mutex<Keystore>, key: &KeystoreKey) -> Result<Keypair> {
+    let mut keystore = keystore.lock().unwrap();
+    let contents = keystore.contents()?;
+    let secret = match key {
+        KeystoreKey::Identity(name) => contents.identities.get(name).copied(),
+        KeystoreKey::Swap(swap_id) => contents.swaps.get(swap_id).and_then(|s| s.swap_key),
+    };
+    let secret = secret.ok_or_else(|| anyhow!("Keystore has no {:?}", key))?;
+    Ok(Keypair::from_secret_key(&Secp256k1::new(), &secret))
+}
+
+impl SchnorrSigner for KeystoreSigner {
+    async fn public_key(&self) -> Result<XOnlyPublicKey> {
+        Ok(self.pubkey)
+    }
+
+    async fn sign_schnorr(&self, message: &Message, key: SigningKey) -> Result<Signature> {
+        self.keypair()?.sign_schnorr(message, key).await
+    }
+}
+
+impl AdaptorSigner for KeystoreSigner {
+    async fn presign(&self, message: &Message, adaptor_point: &PublicKey, key: SigningKey) -> Result<AdaptorSignature> {
+        self.keypair()?.presign(message, adaptor_point, key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr_utils::{check_presigned_event, create_unsigned_event};
+    use nostr::{EventId, Kind};
+
+    #[tokio::test]
+    async fn presigns_only_its_own_events_with_honest_ids() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::new(&secp, &mut OsRng);
+        let adaptor_point = Keypair::new(&secp, &mut OsRng).public_key();
+        let event = create_unsigned_event(&keypair.x_only_public_key().0, Kind::TextNote, "for sale", vec![]).unwrap();
+        let adaptor_sig = keypair.presign_event(&event, &adaptor_point).await.unwrap();
+        check_presigned_event(&event, &adaptor_sig).unwrap();
+
+        let forged = UnsignedEvent { id: EventId::from_slice(&[7; 32]).unwrap(), ..event };
+        assert!(keypair.presign_event(&forged, &adaptor_point).await.is_err());
+        let other = create_unsigned_event(&Keypair::new(&secp, &mut OsRng).x_only_public_key().0, Kind::TextNote, "for sale", vec![]).unwrap();
+        assert!(keypair.presign_event(&other, &adaptor_point).await.is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use bitcoin::{
+    Address, Amount, FeeRate, Network, OutPoint, Psbt, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut, Witness,
+    absolute::LockTime, address::NetworkUnchecked, key::Keypair, psbt::PsbtSighashType, sighash::SighashCache,
+    taproot::{ControlBlock, LeafVersion, Signature as TaprootSignature, TapLeafHash, TapNodeHash}, transaction::Version,
+};
+use secp256k1::{Message, SecretKey, XOnlyPublicKey};
+use anyhow::{anyhow, Result};
+use crate::adaptor::AdaptorSignature;
+use crate::bitcoin_utils::{dummy_signature, is_anyone_can_pay, taproot_sighash_with_cache};
+use crate::network::require_network;
+use crate::signer::{SchnorrSigner, SigningKey};
+use crate::validation::{validate_transaction, MIN_RELAY_FEE};
+
+/// How a single input gets its witness. `S` is the signer of the locally signed inputs.
+#[derive(Debug, Clone)]
+pub enum InputSigner<S = Keypair> {
+    /// Taproot key path; the signer's key is tweaked with `merkle_root`.
+    KeyPath { signer: S, merkle_root: Option<TapNodeHash> },
+    /// Single-signature tapscript leaf such as the swap refund leaf.
+    ScriptPath { signer: S, script: ScriptBuf, control_block: ControlBlock },
+    /// Key path pre-signed with `presign_taproot_key_spend`, completed here with `t`.
+    Adaptor { adaptor_sig: AdaptorSignature, t: SecretKey },
+    /// Key path signed outside this process into `tap_key_sig` of the PSBT from `sign`.
+    ExternalKeyPath { internal_key: XOnlyPublicKey, merkle_root: Option<TapNodeHash> },
+    /// Tapscript leaf signed outside this process into `tap_script_sigs` of the PSBT from `sign`.
+    ExternalScriptPath { pubkey: XOnlyPublicKey, script: ScriptBuf, control_block: ControlBlock },
+}
+
+impl<S> InputSigner<S> {
+    fn leaf(&self) -> Option<(&ScriptBuf, &ControlBlock)> {
+        match self {
+            InputSigner::ScriptPath { script, control_block, .. } | InputSigner::ExternalScriptPath { script, control_block, .. } => {
+                Some((script, control_block))
+            }
+            _ => None,
+        }
+    }
+
+    fn leaf_hash(&self) -> Option<TapLeafHash> {
+        self.leaf().map(|(script, _)| TapLeafHash::from_script(script, LeafVersion::TapScript))
+    }
+}
+
+/// One input of a `SpendBuilder`. `sequence` defaults to BIP125 signalling and
+/// `sighash_type` to `Default`; set `sequence` to the CSV value for timelocked leaves.
+#[derive(Debug, Clone)]
+pub struct SpendInput<S = Keypair> {
+    pub outpoint: OutPoint,
+    pub prevout: TxOut,
+    pub sequence: Sequence,
+    pub sighash_type: TapSighashType,
+    pub signer: InputSigner<S>,
+}
+
+impl<S> SpendInput<S> {
+    pub fn new(outpoint: OutPoint, prevout: TxOut, signer: InputSigner<S>) -> Self {
+        SpendInput {
+            outpoint,
+            prevout,
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            sighash_type: TapSighashType::Default,
+            signer,
+        }
+    }
+
+    /// Witness of the same size as the final one, for fee estimation.
+    fn dummy_witness(&self) -> Witness {
+        let sig = dummy_signature(self.sighash_type);
+        match self.signer.leaf() {
+            Some((script, control_block)) => Witness::from_slice(&[sig, script.to_bytes(), control_block.serialize()]),
+            None => Witness::from_slice(&[sig]),
+        }
+    }
+}
+
+/// Transaction spending many taproot inputs, each with its own signer, to many outputs.
+/// All sighashes come from one `SighashCache` over the unsigned transaction.
+#[derive(Debug, Clone)]
+pub struct SpendBuilder<S = Keypair> {
+    pub network: Network,
+    pub lock_time: LockTime,
+    pub inputs: Vec<SpendInput<S>>,
+    pub outputs: Vec<TxOut>,
+}
+
+impl<S: SchnorrSigner> SpendBuilder<S> {
+    pub fn new(network: Network) -> Self {
+        SpendBuilder { network, lock_time: LockTime::ZERO, inputs: Vec::new(), outputs: Vec::new() }
+    }
+
+    pub fn add_input(&mut self, input: SpendInput<S>) -> &mut Self {
+        self.inputs.push(input);
+        self
+    }
+
+    /// Pay `amount` to `address`, which must belong to the builder's network.
+    pub fn add_output(&mut self, address: Address<NetworkUnchecked>, amount: Amount) -> Result<&mut Self> {
+        let address = require_network(address, self.network)?;
+        self.outputs.push(TxOut { value: amount, script_pubkey: address.script_pubkey() });
+        Ok(self)
+    }
+
+    pub fn add_output_script(&mut self, script_pubkey: ScriptBuf, amount: Amount) -> &mut Self {
+        self.outputs.push(TxOut { value: amount, script_pubkey });
+        self
+    }
+
+    /// Send whatever the inputs hold beyond the other outputs and a fee at `fee_rate` to
+    /// `address`. Call after every input and fixed output has been added.
+    pub fn drain_to(&mut self, address: Address<NetworkUnchecked>, fee_rate: FeeRate) -> Result<&mut Self> {
+        let address = require_network(address, self.network)?;
+        self.outputs.push(TxOut { value: Amount::ZERO, script_pubkey: address.script_pubkey() });
+
+        let mut sized = self.unsigned_tx();
+        for (txin, input) in sized.input.iter_mut().zip(&self.inputs) {
+            txin.witness = input.dummy_witness();
+        }
+        let fee = fee_rate.fee_vb(sized.vsize() as u64).ok_or_else(|| anyhow!("Fee overflow"))?;
+        let total_in = self.inputs.iter().map(|i| i.prevout.value).sum::<Amount>();
+        let others = self.outputs.iter().map(|o| o.value).sum::<Amount>();
+        let value = total_in
+            .checked_sub(fee + others)
+            .ok_or_else(|| anyhow!("Inputs of {} do not cover outputs of {} and fee of {}", total_in, others, fee))?;
+        if value < address.script_pubkey().dust_value() {
+            return Err(anyhow!("Drain output of {} would be dust after a fee of {}", value, fee));
+        }
+        self.outputs.last_mut().expect("drain output pushed above").value = value;
+        Ok(self)
+    }
+
+    pub fn prevouts(&self) -> Vec<TxOut> {
+        self.inputs.iter().map(|i| i.prevout.clone()).collect()
+    }
+
+    pub fn unsigned_tx(&self) -> Transaction {
+        Transaction {
+            version: Version(2),
+            lock_time: self.lock_time,
+            input: self
+                .inputs
+                .iter()
+                .map(|i| TxIn {
+                    previous_output: i.outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: i.sequence,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: self.outputs.clone(),
+        }
+    }
+
+    /// PSBT with every locally signable input signed and the taproot metadata external
+    /// signers need (witness UTXO, internal key, leaf scripts, sighash type) filled in.
+    pub async fn sign(&self) -> Result<Psbt> {
+        let tx = self.unsigned_tx();
+        let prevouts = self.prevouts();
+        let mut psbt = Psbt::from_unsigned_tx(tx.clone())?;
+        let mut cache = SighashCache::new(&tx);
+
+        for (index, (input, psbt_input)) in self.inputs.iter().zip(psbt.inputs.iter_mut()).enumerate() {
+            let hash_ty = input.sighash_type;
+            psbt_input.witness_utxo = Some(input.prevout.clone());
+            psbt_input.sighash_type = Some(PsbtSighashType::from(hash_ty));
+            if let Some((script, control_block)) = input.signer.leaf() {
+                psbt_input.tap_scripts.insert(control_block.clone(), (script.clone(), LeafVersion::TapScript));
+                psbt_input.tap_internal_key = Some(control_block.internal_key);
+            }
+
+            let sighash = taproot_sighash_with_cache(&mut cache, index, &prevouts, input.signer.leaf_hash(), hash_ty)?;
+            let message = Message::from(sighash);
+            match &input.signer {
+                InputSigner::KeyPath { signer, merkle_root } => {
+                    let sig = signer.sign_schnorr(&message, SigningKey::Taproot(*merkle_root)).await?;
+                    psbt_input.tap_internal_key = Some(signer.public_key().await?);
+                    psbt_input.tap_merkle_root = *merkle_root;
+                    psbt_input.tap_key_sig = Some(TaprootSignature { sig, hash_ty });
+                }
+                InputSigner::ScriptPath { signer, script, .. } => {
+                    let sig = signer.sign_schnorr(&message, SigningKey::Internal).await?;
+                    let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+                    psbt_input.tap_script_sigs.insert((signer.public_key().await?, leaf_hash), TaprootSignature { sig, hash_ty });
+                }
+                InputSigner::Adaptor { adaptor_sig, t } => {
+                    if adaptor_sig.message != message.as_ref() {
+                        return Err(anyhow!("Adaptor signature for input {} does not commit to its sighash", index));
+                    }
+                    let sig = adaptor_sig.generate_final_signature(&adaptor_sig.complete(t));
+                    psbt_input.tap_key_sig = Some(TaprootSignature { sig, hash_ty });
+                }
+                InputSigner::ExternalKeyPath { internal_key, merkle_root } => {
+                    psbt_input.tap_internal_key = Some(*internal_key);
+                    psbt_input.tap_merkle_root = *merkle_root;
+                }
+                InputSigner::ExternalScriptPath { .. } => {}
+            }
+        }
+        Ok(psbt)
+    }
+
+    /// Assemble the witnesses from a PSBT produced by `sign` (plus any external
+    /// signatures) and validate the result.
+    pub fn finalize(&self, psbt: &Psbt) -> Result<Transaction> {
+        let mut tx = self.unsigned_tx();
+        if psbt.unsigned_tx != tx {
+            return Err(anyhow!("PSBT does not match the transaction being built"));
+        }
+        for (index, (input, psbt_input)) in self.inputs.iter().zip(&psbt.inputs).enumerate() {
+            tx.input[index].witness = match &input.signer {
+                InputSigner::KeyPath { .. } | InputSigner::Adaptor { .. } | InputSigner::ExternalKeyPath { .. } => {
+                    let sig = psbt_input.tap_key_sig.ok_or_else(|| anyhow!("Input {} has no key path signature", index))?;
+                    Witness::from_slice(&[sig.to_vec()])
+                }
+                InputSigner::ScriptPath { script, control_block, .. } => {
+                    // `sign` put the only signature for this leaf there.
+                    let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+                    let pubkey = psbt_input
+                        .tap_script_sigs
+                        .keys()
+                        .find(|(_, leaf)| *leaf == leaf_hash)
+                        .map(|(pubkey, _)| *pubkey)
+                        .ok_or_else(|| anyhow!("Input {} has no script path signature", index))?;
+                    script_path_witness(psbt_input, index, pubkey, script, control_block)?
+                }
+                InputSigner::ExternalScriptPath { pubkey, script, control_block } => {
+                    script_path_witness(psbt_input, index, *pubkey, script, control_block)?
+                }
+            };
+        }
+
+        // A transaction whose every input is ANYONECANPAY may still gain fee inputs.
+        let min_fee_rate = if self.inputs.iter().all(|i| is_anyone_can_pay(i.sighash_type)) { FeeRate::ZERO } else { MIN_RELAY_FEE };
+        validate_transaction(&tx, &self.prevouts(), min_fee_rate)?;
+        Ok(tx)
+    }
+
+    /// Sign and finalize in one step; fails if any input needs an external signer.
+    pub async fn build(&self) -> Result<Transaction> {
+        self.finalize(&self.sign().await?)
+    }
+}
+
+fn script_path_witness(
+    psbt_input: &bitcoin::psbt::Input,
+    index: usize,
+    pubkey: XOnlyPublicKey,
+    script: &ScriptBuf,
+    control_block: &ControlBlock,
+) -> Result<Witness> {
+    let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+    let sig = psbt_input
+        .tap_script_sigs
+        .get(&(pubkey, leaf_hash))
+        .ok_or_else(|| anyhow!("Input {} has no script path signature", index))?;
+    Ok(Witness::from_slice(&[sig.to_vec(), script.to_bytes(), control_block.serialize()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::Txid;
+    use secp256k1::Secp256k1;
+
+    fn funded(keypair: &Keypair, value: Amount) -> SpendBuilder {
+        let script_pubkey = ScriptBuf::new_p2tr(&Secp256k1::new(), keypair.x_only_public_key().0, None);
+        let outpoint = OutPoint { txid: Txid::all_zeros(), vout: 0 };
+        let mut builder = SpendBuilder::new(Network::Regtest);
+        builder.add_input(SpendInput::new(outpoint, TxOut { value, script_pubkey }, InputSigner::KeyPath { signer: *keypair, merkle_root: None }));
+        builder
+    }
+
+    fn address(keypair: &Keypair) -> Address<NetworkUnchecked> {
+        Address::p2tr(&Secp256k1::new(), keypair.x_only_public_key().0, None, Network::Regtest).as_unchecked().clone()
+    }
+
+    #[tokio::test]
+    async fn key_path_spend_pays_the_requested_feerate() {
+        let keypair = Keypair::new(&Secp256k1::new(), &mut rand::thread_rng());
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(3);
+        let mut builder = funded(&keypair, Amount::from_sat(100_000));
+        builder.add_output(address(&keypair), Amount::from_sat(40_000)).unwrap().drain_to(address(&keypair), fee_rate).unwrap();
+        let tx = builder.build().await.unwrap();
+
+        let fee = Amount::from_sat(100_000) - tx.output.iter().map(|o| o.value).sum::<Amount>();
+        assert_eq!(fee, fee_rate.fee_vb(tx.vsize() as u64).unwrap());
+        assert_eq!(tx.input[0].witness.len(), 1);
+    }
+
+    #[test]
+    fn drain_refuses_dust_and_overspending() {
+        let keypair = Keypair::new(&Secp256k1::new(), &mut rand::thread_rng());
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+        let mut builder = funded(&keypair, Amount::from_sat(10_000));
+        builder.add_output(address(&keypair), Amount::from_sat(9_700)).unwrap();
+        assert!(builder.drain_to(address(&keypair), fee_rate).is_err());
+
+        let mut builder = funded(&keypair, Amount::from_sat(10_000));
+        builder.add_output(address(&keypair), Amount::from_sat(10_000)).unwrap();
+        assert!(builder.drain_to(address(&keypair), fee_rate).is_err());
+    }
+}
@@ -1,23 +1,34 @@
-use bitcoin::{Amount, Address, Transaction, TxOut, Txid, ScriptBuf};
+use bitcoin::{Amount, Address, FeeRate, Network, OutPoint, address::NetworkUnchecked, Transaction, TxOut, Txid, ScriptBuf, TapSighashType};
 use secp256k1::{Secp256k1, XOnlyPublicKey};
+use anyhow::anyhow;
+use std::time::Duration;
+use crate::bitcoin_utils::SwapOutput;
+use crate::chain::ChainBackend;
+use crate::network::NetworkParams;
+use crate::signer::SchnorrSigner;
 
 #[derive(Debug)]
 pub struct Buyer {
+    pub network: Network,
     pub locking_tx: Option<Transaction>,
     pub output_script: Option<ScriptBuf>,
+    pub swap_output: Option<SwapOutput>,
     // Add more fields as needed, e.g., adaptor_sig, keys, etc.
 }
 
 #[derive(Debug)]
 pub struct Seller {
+    pub network: Network,
     // Fields for seller, e.g., keys, adaptor_sig
 }
 
 impl Buyer {
-    pub fn new() -> Self {
+    pub fn new(network: Network) -> Self {
         Buyer {
+            network,
             locking_tx: None,
             output_script: None,
+            swap_output: None,
         }
     }
 
@@ -37,14 +48,63 @@ impl Buyer {
         self.output_script = Some(ScriptBuf::new_p2tr(&Secp256k1::new(), recipient_pubkey, None));
     }
 
-    pub fn create_spending_transaction(
+    pub fn params(&self) -> NetworkParams {
+        NetworkParams::new(self.network)
+    }
+
+    /// Lock `amount` into a swap output the buyer can refund after its timelock.
+    pub fn create_swap_locking_transaction(
+        &mut self,
+        prev_txid: Txid,
+        prev_vout: u32,
+        amount: Amount,
+        swap: SwapOutput,
+    ) -> Result<(), anyhow::Error> {
+        let script_pubkey = swap.script_pubkey()?;
+        self.locking_tx = Some(crate::bitcoin_utils::create_locking_transaction_to_script(
+            prev_txid,
+            prev_vout,
+            amount,
+            script_pubkey.clone(),
+        ));
+        self.output_script = Some(script_pubkey);
+        self.swap_output = Some(swap);
+        Ok(())
+    }
+
+    /// Lock `amount` into a swap output from wallet UTXOs, with fees and change.
+    pub fn create_funded_locking_transaction(
+        &mut self,
+        utxos: &[crate::chain::Utxo],
+        amount: Amount,
+        swap: SwapOutput,
+        change_script: ScriptBuf,
+        fee_rate: FeeRate,
+    ) -> Result<crate::coin_selection::CoinSelection, anyhow::Error> {
+        let script_pubkey = swap.script_pubkey()?;
+        let (tx, selection) = crate::bitcoin_utils::create_funded_locking_transaction(
+            utxos,
+            amount,
+            script_pubkey.clone(),
+            change_script,
+            fee_rate,
+        )?;
+        self.locking_tx = Some(tx);
+        self.output_script = Some(script_pubkey);
+        self.swap_output = Some(swap);
+        Ok(selection)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_spending_transaction(
         &self,
         prev_txid: Txid,
         prev_vout: u32,
         prev_txout: &TxOut,
         amount: Amount,
-        recipient: Address,
-        signer_keypair: &secp256k1::Keypair,
+        recipient: Address<NetworkUnchecked>,
+        signer: &impl SchnorrSigner,
+        sighash_type: TapSighashType,
     ) -> Result<Transaction, anyhow::Error> {
         crate::bitcoin_utils::create_spending_transaction(
             prev_txid,
@@ -52,8 +112,75 @@ impl Buyer {
             prev_txout,
             amount,
             recipient,
-            signer_keypair,
+            self.network,
+            signer,
+            sighash_type,
+        )
+        .await
+    }
+
+    /// The swap output created by the locking transaction.
+    pub fn lock_outpoint(&self) -> Option<OutPoint> {
+        self.locking_tx.as_ref().map(|tx| OutPoint { txid: tx.txid(), vout: 0 })
+    }
+
+    /// Descriptor of the swap output, for importing into a watch-only wallet.
+    pub fn lock_descriptor(&self) -> Option<String> {
+        self.swap_output.as_ref().map(|swap| swap.descriptor())
+    }
+
+    pub async fn broadcast_locking_transaction<B: ChainBackend>(&self, backend: &B) -> Result<Txid, anyhow::Error> {
+        let tx = self.locking_tx.as_ref().ok_or_else(|| anyhow!("No locking transaction"))?;
+        backend.broadcast(tx).await
+    }
+
+    /// Wait until the locking transaction has `confirmations` confirmations.
+    pub async fn wait_for_lock_confirmation<B: ChainBackend>(&self, backend: &B, confirmations: u32, poll_interval: Duration) -> Result<u32, anyhow::Error> {
+        let outpoint = self.lock_outpoint().ok_or_else(|| anyhow!("No locking transaction"))?;
+        crate::chain::wait_for_confirmations(backend, &outpoint.txid, confirmations, poll_interval).await
+    }
+
+    /// Wait for the seller's claim (or any spend) of the swap output.
+    pub async fn wait_for_claim<B: ChainBackend>(&self, backend: &B, poll_interval: Duration) -> Result<Transaction, anyhow::Error> {
+        let outpoint = self.lock_outpoint().ok_or_else(|| anyhow!("No locking transaction"))?;
+        crate::chain::watch_outpoint_spend(backend, &outpoint, poll_interval).await
+    }
+
+    /// Wait for the claim of the swap output and extract `t` from its witness.
+    pub async fn wait_for_claim_secret<B: ChainBackend>(
+        &self,
+        backend: &B,
+        adaptor_sig: &crate::adaptor::AdaptorSignature,
+        poll_interval: Duration,
+    ) -> Result<secp256k1::SecretKey, anyhow::Error> {
+        let outpoint = self.lock_outpoint().ok_or_else(|| anyhow!("No locking transaction"))?;
+        crate::chain::watch_for_adaptor_secret(backend, &outpoint, adaptor_sig, poll_interval).await
+    }
+
+    /// Reclaim the swap output once the refund timelock has passed.
+    pub async fn create_refund_transaction(
+        &self,
+        recipient: Address<NetworkUnchecked>,
+        fee_rate: FeeRate,
+        anchor: Option<ScriptBuf>,
+        buyer_signer: &impl SchnorrSigner,
+        sighash_type: TapSighashType,
+    ) -> Result<Transaction, anyhow::Error> {
+        let swap = self.swap_output.as_ref().ok_or_else(|| anyhow!("No swap output"))?;
+        let lock_tx = self.locking_tx.as_ref().ok_or_else(|| anyhow!("No locking transaction"))?;
+        let outpoint = self.lock_outpoint().ok_or_else(|| anyhow!("No locking transaction"))?;
+        crate::bitcoin_utils::create_refund_transaction(
+            outpoint,
+            &lock_tx.output[0],
+            swap,
+            recipient,
+            self.network,
+            fee_rate,
+            anchor,
+            buyer_signer,
+            sighash_type,
         )
+        .await
     }
 
     pub fn verify_adaptor_signature(
@@ -66,9 +193,58 @@ impl Buyer {
 }
 
 impl Seller {
-    pub fn new() -> Self {
-        Seller {}
+    pub fn new(network: Network) -> Self {
+        Seller { network }
+    }
+
+    pub fn params(&self) -> NetworkParams {
+        NetworkParams::new(self.network)
     }
 
     // Add methods for seller as needed
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::MockChain;
+    use bitcoin::hashes::Hash;
+    use bitcoin::sighash::{Prevouts, SighashCache};
+    use bitcoin::taproot::{LeafVersion, TapLeafHash};
+    use rand::rngs::OsRng;
+    use secp256k1::{schnorr, Keypair, Message};
+
+    #[tokio::test]
+    async fn funded_lock_can_be_refunded_after_the_timelock() {
+        let secp = Secp256k1::new();
+        let chain = MockChain::new();
+        let (claim, buyer_key) = (Keypair::new(&secp, &mut OsRng), Keypair::new(&secp, &mut OsRng));
+        let wallet = ScriptBuf::new_p2tr(&secp, buyer_key.x_only_public_key().0, None);
+        chain.fund(wallet.clone(), Amount::from_sat(100_000));
+        let utxos = chain.get_utxos(&wallet).await.unwrap();
+
+        let mut buyer = Buyer::new(Network::Regtest);
+        let swap = SwapOutput::new(claim.x_only_public_key().0, buyer_key.x_only_public_key().0, 6).unwrap();
+        buyer.create_funded_locking_transaction(&utxos, Amount::from_sat(50_000), swap, wallet.clone(), FeeRate::from_sat_per_vb(2).unwrap()).unwrap();
+        let lock_txout = &buyer.locking_tx.as_ref().unwrap().output[0];
+        assert_eq!(lock_txout.script_pubkey, swap.script_pubkey().unwrap());
+        let output_key = XOnlyPublicKey::from_slice(&lock_txout.script_pubkey.as_bytes()[2..]).unwrap();
+        assert!(swap.refund_control_block().unwrap().verify_taproot_commitment(&secp, output_key, &swap.refund_script()));
+        assert!(buyer.lock_descriptor().is_some());
+        buyer.broadcast_locking_transaction(&chain).await.unwrap();
+        chain.mine(1);
+
+        let recipient = Address::p2tr(&secp, buyer_key.x_only_public_key().0, None, Network::Regtest).as_unchecked().clone();
+        let refund = buyer.create_refund_transaction(recipient, FeeRate::from_sat_per_vb(2).unwrap(), None, &buyer_key, TapSighashType::Default).await.unwrap();
+        assert_eq!(refund.input[0].witness.nth(1).unwrap(), swap.refund_script().as_bytes());
+        let leaf_hash = TapLeafHash::from_script(&swap.refund_script(), LeafVersion::TapScript);
+        let sighash = SighashCache::new(&refund)
+            .taproot_script_spend_signature_hash(0, &Prevouts::All(&[lock_txout]), leaf_hash, TapSighashType::Default)
+            .unwrap();
+        let signature = schnorr::Signature::from_slice(refund.input[0].witness.nth(0).unwrap()).unwrap();
+        secp.verify_schnorr(&signature, &Message::from_digest(sighash.to_byte_array()), &buyer_key.x_only_public_key().0).unwrap();
+        assert!(chain.broadcast(&refund).await.is_err());
+        chain.mine(5);
+        chain.broadcast(&refund).await.unwrap();
+    }
+}
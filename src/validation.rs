@@ -0,0 +1,101 @@
+use bitcoin::{Amount, FeeRate, Transaction, TxOut, Weight};
+use thiserror::Error;
+
+/// Bitcoin Core's default minimum relay feerate.
+pub const MIN_RELAY_FEE: FeeRate = FeeRate::from_sat_per_vb_unchecked(1);
+
+/// Largest transaction weight Bitcoin Core relays by default.
+pub const MAX_STANDARD_TX_WEIGHT: Weight = Weight::from_wu(400_000);
+
+/// Reasons a transaction would be rejected by consensus or by default relay policy.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("expected {expected} prevouts, got {actual}")]
+    PrevoutCount { expected: usize, actual: usize },
+    #[error("input {index} failed script verification: {reason}")]
+    Script { index: usize, reason: String },
+    #[error("inputs total {inputs} but outputs total {outputs}")]
+    NegativeFee { inputs: Amount, outputs: Amount },
+    #[error("output {index} of {value} is below the dust limit of {limit}")]
+    Dust { index: usize, value: Amount, limit: Amount },
+    #[error("weight {weight} exceeds the standard maximum of {max}")]
+    Weight { weight: Weight, max: Weight },
+    #[error("input {index} carries a taproot annex, which is non-standard")]
+    Annex { index: usize },
+    #[error("feerate of {actual} sat/kwu is below the floor of {floor} sat/kwu")]
+    FeeRate { actual: u64, floor: u64 },
+}
+
+/// Run every input script against its prevout with libbitcoinconsensus (taproot included).
+/// `prevouts` must line up with `tx.input`.
+#[cfg(feature = "consensus")]
+pub fn verify_scripts(tx: &Transaction, prevouts: &[TxOut]) -> Result<(), ValidationError> {
+    check_prevout_count(tx, prevouts)?;
+    let serialized = bitcoin::consensus::encode::serialize(tx);
+    let utxos: Vec<bitcoinconsensus::Utxo> = prevouts
+        .iter()
+        .map(|txout| bitcoinconsensus::Utxo {
+            script_pubkey: txout.script_pubkey.as_bytes().as_ptr(),
+            script_pubkey_len: txout.script_pubkey.len() as u32,
+            value: txout.value.to_sat() as i64,
+        })
+        .collect();
+    for (index, txout) in prevouts.iter().enumerate() {
+        bitcoinconsensus::verify(txout.script_pubkey.as_bytes(), txout.value.to_sat(), &serialized, Some(&utxos), index)
+            .map_err(|e| ValidationError::Script { index, reason: format!("{:?}", e) })?;
+    }
+    Ok(())
+}
+
+/// Without the `consensus` feature scripts are not executed; only the standardness checks run.
+#[cfg(not(feature = "consensus"))]
+pub fn verify_scripts(tx: &Transaction, prevouts: &[TxOut]) -> Result<(), ValidationError> {
+    check_prevout_count(tx, prevouts)
+}
+
+fn check_prevout_count(tx: &Transaction, prevouts: &[TxOut]) -> Result<(), ValidationError> {
+    if tx.input.len() != prevouts.len() {
+        return Err(ValidationError::PrevoutCount { expected: tx.input.len(), actual: prevouts.len() });
+    }
+    Ok(())
+}
+
+/// Default relay policy: no dust outputs, standard weight, no annex, feerate at least `min_fee_rate`.
+pub fn check_standardness(tx: &Transaction, prevouts: &[TxOut], min_fee_rate: FeeRate) -> Result<(), ValidationError> {
+    check_prevout_count(tx, prevouts)?;
+
+    for (index, output) in tx.output.iter().enumerate() {
+        let limit = output.script_pubkey.dust_value();
+        if output.value < limit {
+            return Err(ValidationError::Dust { index, value: output.value, limit });
+        }
+    }
+
+    let weight = tx.weight();
+    if weight > MAX_STANDARD_TX_WEIGHT {
+        return Err(ValidationError::Weight { weight, max: MAX_STANDARD_TX_WEIGHT });
+    }
+
+    for (index, (input, prevout)) in tx.input.iter().zip(prevouts).enumerate() {
+        let has_annex = input.witness.len() >= 2 && input.witness.last().is_some_and(|e| e.first() == Some(&0x50));
+        if prevout.script_pubkey.is_p2tr() && has_annex {
+            return Err(ValidationError::Annex { index });
+        }
+    }
+
+    let inputs = prevouts.iter().map(|p| p.value).sum::<Amount>();
+    let outputs = tx.output.iter().map(|o| o.value).sum::<Amount>();
+    let fee = inputs.checked_sub(outputs).ok_or(ValidationError::NegativeFee { inputs, outputs })?;
+    let floor = min_fee_rate.fee_vb(tx.vsize() as u64).unwrap_or(Amount::MAX_MONEY);
+    if fee < floor {
+        let actual = fee.to_sat() * 1000 / weight.to_wu().max(1);
+        return Err(ValidationError::FeeRate { actual, floor: min_fee_rate.to_sat_per_kwu() });
+    }
+    Ok(())
+}
+
+/// Full pre-broadcast check: consensus script verification, then standardness.
+pub fn validate_transaction(tx: &Transaction, prevouts: &[TxOut], min_fee_rate: FeeRate) -> Result<(), ValidationError> {
+    verify_scripts(tx, prevouts)?;
+    check_standardness(tx, prevouts, min_fee_rate)
+}